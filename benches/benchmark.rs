@@ -3,11 +3,15 @@ use criterion::{criterion_group, criterion_main};
 mod decode_from_file_bench;
 mod decode_multiple_events_bench;
 mod decode_single_event_bench;
+mod filler_scan_bench;
+mod skip_events_bench;
 
 criterion_group!(
     benches,
     decode_from_file_bench::decode_from_file,
     decode_multiple_events_bench::decode_multiple_events,
-    decode_single_event_bench::decode_single_event
+    decode_single_event_bench::decode_single_event,
+    filler_scan_bench::filler_scan,
+    skip_events_bench::skip_events
 );
 criterion_main!(benches);
@@ -8,7 +8,7 @@ pub fn decode_single_event(c: &mut Criterion) {
     let mut group = c.benchmark_group("decode single event");
     {
         group.bench_function("decode event fsm iterator", |b| {
-            b.iter(|| moss_decoder::decode_event(&f))
+            b.iter(|| moss_decoder::decode_event(&f, None))
         });
     }
     group.finish();
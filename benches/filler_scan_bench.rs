@@ -0,0 +1,24 @@
+use criterion::Criterion;
+use moss_decoder::moss_protocol::test_util::fake_event_simple;
+
+const EVENTS: usize = 10_000;
+const FILLER_PER_EVENT: usize = 1000;
+const FILLER: u8 = 0xFA; // The default filler byte
+
+/// Synthetic input: events separated by long runs of filler bytes, which are scanned for the next Unit Frame Header.
+pub fn filler_scan(c: &mut Criterion) {
+    let mut event = fake_event_simple();
+    event.extend([FILLER; FILLER_PER_EVENT]);
+    let bytes = event.repeat(EVENTS);
+
+    let mut group = c.benchmark_group("filler_scan_bench");
+    {
+        group.bench_function("decode events between filler", |b| {
+            b.iter(|| moss_decoder::decode_all_events(&bytes, None))
+        });
+        group.bench_function("skip events between filler", |b| {
+            b.iter(|| moss_decoder::decode_n_events(&bytes, 1, Some(EVENTS - 1), None, None))
+        });
+    }
+    group.finish();
+}
@@ -0,0 +1,26 @@
+use criterion::Criterion;
+
+const BENCH_FILE_PATH: &str = "tests/test-data/noise_all_regions.raw";
+const BENCH_FILE_PACKETS: usize = 1000;
+const REPEAT: usize = 1000;
+
+pub fn skip_events(c: &mut Criterion) {
+    let f = std::fs::read(std::path::PathBuf::from(BENCH_FILE_PATH)).unwrap();
+    let bytes = f.repeat(REPEAT);
+
+    let mut group = c.benchmark_group("skip_events_bench");
+    {
+        group.bench_function("skip all but one event", |b| {
+            b.iter(|| {
+                moss_decoder::decode_n_events(
+                    &bytes,
+                    1,
+                    Some(BENCH_FILE_PACKETS * REPEAT - 1),
+                    None,
+                    None,
+                )
+            })
+        });
+    }
+    group.finish();
+}
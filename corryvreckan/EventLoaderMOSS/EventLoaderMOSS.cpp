@@ -0,0 +1,169 @@
+/**
+ * @file
+ * @brief Implementation of module EventLoaderMOSS
+ */
+
+#include "EventLoaderMOSS.h"
+
+#include <filesystem>
+#include <sstream>
+
+using namespace corryvreckan;
+
+EventLoaderMOSS::EventLoaderMOSS(Configuration& config, std::shared_ptr<Detector> detector)
+    : Module(config, detector), m_detector(detector) {
+
+    config_.setDefault<std::string>("file_format", "text");
+    config_.setDefault<double>("event_length", Units::get<double>(1, "us"));
+
+    auto format = config_.get<std::string>("file_format");
+    if(format != "text" && format != "binary") {
+        throw InvalidValueError(config_, "file_format", "must be \"text\" or \"binary\"");
+    }
+    binary_ = format == "binary";
+    event_length_ = config_.get<double>("event_length");
+}
+
+void EventLoaderMOSS::initialize() {
+    std::filesystem::path directory = config_.getPath("input_directory", true);
+    auto hit_path = directory / (m_detector->getName() + (binary_ ? ".bin" : ".txt"));
+    auto index_path = directory / "events.txt";
+
+    hit_file_.open(hit_path, binary_ ? std::ios::binary : std::ios::in);
+    if(!hit_file_.is_open()) {
+        throw InvalidValueError(config_, "input_directory", "cannot open hit file " + hit_path.string());
+    }
+    index_file_.open(index_path);
+    if(!index_file_.is_open()) {
+        throw InvalidValueError(config_, "input_directory", "cannot open event index " + index_path.string());
+    }
+    LOG(INFO) << "Reading hits of " << m_detector->getName() << " from " << hit_path;
+
+    auto columns = m_detector->nPixels().X();
+    auto rows = m_detector->nPixels().Y();
+    std::string title = m_detector->getName() + " hitmap;column;row;hits";
+    hitmap_ = new TH2F("hitmap", title.c_str(), columns, -0.5, columns - 0.5, rows, -0.5, rows - 0.5);
+    title = m_detector->getName() + " hits per event;hits;events";
+    hits_per_event_ = new TH1F("hits_per_event", title.c_str(), 200, -0.5, 199.5);
+}
+
+std::optional<uint64_t> EventLoaderMOSS::read_event() {
+    std::string line;
+    while(std::getline(index_file_, line)) {
+        if(line.empty() || line[0] == '#') {
+            continue;
+        }
+        return std::stoull(line);
+    }
+    return std::nullopt;
+}
+
+std::optional<EventLoaderMOSS::Hit> EventLoaderMOSS::read_hit() {
+    if(binary_) {
+        // event (u64), column (u16), row (u16), all little-endian
+        unsigned char record[12];
+        if(!hit_file_.read(reinterpret_cast<char*>(record), sizeof(record))) {
+            return std::nullopt;
+        }
+        Hit hit{0, 0, 0};
+        for(int i = 7; i >= 0; i--) {
+            hit.event = (hit.event << 8) | record[i];
+        }
+        hit.column = static_cast<uint16_t>(record[8] | (record[9] << 8));
+        hit.row = static_cast<uint16_t>(record[10] | (record[11] << 8));
+        return hit;
+    }
+
+    std::string line;
+    while(std::getline(hit_file_, line)) {
+        if(line.empty() || line[0] == '#') {
+            continue;
+        }
+        std::istringstream fields(line);
+        Hit hit{0, 0, 0};
+        if(!(fields >> hit.event >> hit.column >> hit.row)) {
+            throw ModuleError("Invalid line in the hit file of " + m_detector->getName() + ": " + line);
+        }
+        return hit;
+    }
+    return std::nullopt;
+}
+
+StatusCode EventLoaderMOSS::run(const std::shared_ptr<Clipboard>& clipboard) {
+    // Collects the hits of event `number` of the index, at the time of its trigger
+    PixelVector pixels;
+    auto load_event = [&](uint64_t number, double timestamp) {
+        size_t hits = 0;
+        while(auto hit = pending_hit_ ? pending_hit_ : read_hit()) {
+            pending_hit_.reset();
+            if(hit->event > number) {
+                pending_hit_ = hit;
+                break;
+            }
+            // Hits of events missing from the index can't be placed and are dropped
+            if(hit->event < number || m_detector->masked(hit->column, hit->row)) {
+                continue;
+            }
+            pixels.push_back(std::make_shared<Pixel>(m_detector->getName(), hit->column, hit->row, 1, 1., timestamp));
+            hitmap_->Fill(hit->column, hit->row);
+            hits++;
+        }
+        hits_per_event_->Fill(static_cast<double>(hits));
+        hits_loaded_ += hits;
+        events_loaded_++;
+    };
+
+    if(!clipboard->isEventDefined()) {
+        // Define an event of `event_length` per event of the index, numbered by its trigger ID
+        auto number = pending_event_ ? pending_event_ : read_event();
+        pending_event_.reset();
+        if(!number) {
+            return StatusCode::EndRun;
+        }
+        double start = static_cast<double>(*number) * event_length_;
+        auto event = std::make_shared<Event>(start, start + event_length_);
+        event->addTrigger(static_cast<uint32_t>(*number), start);
+        clipboard->putEvent(event);
+        load_event(*number, start);
+    } else {
+        auto event = clipboard->getEvent();
+        auto triggers = event->triggerList();
+        if(triggers.empty()) {
+            throw ModuleError("The event on the clipboard has no trigger IDs to match the MOSS event numbers to");
+        }
+        // Load the events of the index that match a trigger ID of the event, events without a matching trigger ID are discarded
+        bool loaded = false;
+        while(true) {
+            auto number = pending_event_ ? pending_event_ : read_event();
+            pending_event_.reset();
+            if(!number) {
+                if(!loaded) {
+                    return StatusCode::EndRun;
+                }
+                break;
+            }
+            if(*number > triggers.rbegin()->first) {
+                pending_event_ = number;
+                break;
+            }
+            if(event->hasTriggerID(static_cast<uint32_t>(*number))) {
+                load_event(*number, triggers.at(static_cast<uint32_t>(*number)));
+                loaded = true;
+            } else {
+                events_discarded_++;
+            }
+        }
+    }
+
+    LOG(DEBUG) << "Loaded " << pixels.size() << " hits of " << m_detector->getName();
+    if(pixels.empty()) {
+        return StatusCode::NoData;
+    }
+    clipboard->putData(pixels, m_detector->getName());
+    return StatusCode::Success;
+}
+
+void EventLoaderMOSS::finalize(const std::shared_ptr<ReadonlyClipboard>&) {
+    LOG(INFO) << "Loaded " << hits_loaded_ << " hits in " << events_loaded_ << " events of " << m_detector->getName()
+              << ", discarded " << events_discarded_ << " events without a matching trigger ID";
+}
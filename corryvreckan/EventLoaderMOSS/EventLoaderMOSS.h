@@ -0,0 +1,76 @@
+/**
+ * @file
+ * @brief Definition of module EventLoaderMOSS
+ *
+ * Reads the pixel hit files written by export_corryvreckan of moss_decoder.
+ */
+
+#ifndef EventLoaderMOSS_H
+#define EventLoaderMOSS_H 1
+
+#include <cstdint>
+#include <fstream>
+#include <memory>
+#include <optional>
+#include <string>
+
+#include <TH1F.h>
+#include <TH2F.h>
+
+#include "core/module/Module.hpp"
+#include "objects/Pixel.hpp"
+
+namespace corryvreckan {
+    /** @ingroup Modules
+     * @brief Module to read the hit files of a MOSS detector exported by moss_decoder
+     *
+     * The event numbers of the event index are either matched to the trigger IDs of an event defined by an earlier
+     * module, or define the events themselves.
+     */
+    class EventLoaderMOSS : public Module {
+
+    public:
+        /**
+         * @brief Constructor for this detector module
+         * @param config Configuration object for this module as retrieved from the steering file
+         * @param detector Pointer to the detector for this module instance
+         */
+        EventLoaderMOSS(Configuration& config, std::shared_ptr<Detector> detector);
+        ~EventLoaderMOSS() {}
+
+        void initialize() override;
+        StatusCode run(const std::shared_ptr<Clipboard>& clipboard) override;
+        void finalize(const std::shared_ptr<ReadonlyClipboard>& clipboard) override;
+
+    private:
+        struct Hit {
+            uint64_t event;
+            uint16_t column;
+            uint16_t row;
+        };
+
+        // Reads the next event number from the event index
+        std::optional<uint64_t> read_event();
+        // Reads the next hit from the hit file
+        std::optional<Hit> read_hit();
+
+        std::shared_ptr<Detector> m_detector;
+        bool binary_;
+        double event_length_;
+        std::ifstream index_file_;
+        std::ifstream hit_file_;
+
+        // Event of the index not yet loaded, kept when it belongs to a later event on the clipboard
+        std::optional<uint64_t> pending_event_;
+        // Hit read ahead of its event
+        std::optional<Hit> pending_hit_;
+
+        TH2F* hitmap_;
+        TH1F* hits_per_event_;
+
+        size_t events_loaded_{0};
+        size_t events_discarded_{0};
+        size_t hits_loaded_{0};
+    };
+} // namespace corryvreckan
+#endif // EventLoaderMOSS_H
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::{fuzzing, FramingConfig};
+
+fuzz_target!(|data: &[u8]| {
+    _ = fuzzing::debug_decode_event(data, &FramingConfig::default());
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::{fuzzing, FramingConfig};
+
+fuzz_target!(|data: &[u8]| {
+    let framing = FramingConfig::default();
+    _ = fuzzing::extract_packet_from_buf(data, None, &framing);
+
+    // The first byte selects how many of the following bytes are prepended
+    if let Some((&split, rest)) = data.split_first() {
+        let (prepend, bytes) = rest.split_at(usize::from(split).min(rest.len()));
+        _ = fuzzing::extract_packet_from_buf(bytes, Some(prepend.to_vec()), &framing);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use moss_decoder::{fuzzing, FramingConfig};
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte selects the alignment of the events
+    if let Some((&alignment, bytes)) = data.split_first() {
+        let framing = FramingConfig::new([0xFA], usize::from(alignment % 8) + 1, true).unwrap();
+        _ = fuzzing::get_all_packets_from_buf(bytes, &framing);
+    }
+});
@@ -0,0 +1,152 @@
+/*
+ * C interface of the moss_decoder library, generated from src/ffi.rs with cbindgen, do not edit.
+ *
+ * A decoder handle is created with moss_decoder_new(), fed chunks of raw data with moss_decoder_feed(),
+ * and the decoded packets are copied into caller-provided arrays with moss_decoder_poll().
+ * Events may be split across chunks, the bytes of a partial event are kept for the next chunk.
+ *
+ * All functions returning a moss_status set a message retrieved with moss_last_error() on failure.
+ * A decoder handle must not be used from several threads at the same time.
+ */
+
+#ifndef MOSS_DECODER_H
+#define MOSS_DECODER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Status code returned by the C functions.
+typedef enum moss_status {
+    // Success.
+    MOSS_STATUS_OK = 0,
+    // A required pointer argument was NULL.
+    MOSS_STATUS_NULL_POINTER = 1,
+    // An argument has an invalid value, e.g. an invalid framing.
+    MOSS_STATUS_INVALID_ARGUMENT = 2,
+    // The next packet has more hits than fit in the hits array.
+    MOSS_STATUS_BUFFER_TOO_SMALL = 3,
+    // The fed data contained protocol errors, the decoder recovered and decoded the valid events.
+    MOSS_STATUS_PROTOCOL = 4,
+} moss_status;
+
+// The decoder handle, opaque to C.
+typedef struct moss_decoder moss_decoder;
+
+// A packet as copied to C, its hits are the `hit_count` hits from index `first_hit` of the hits array of the same poll.
+typedef struct moss_packet {
+    // The unit ID of the packet.
+    uint8_t unit_id;
+    // Index of the first hit of the packet in the hits array.
+    size_t first_hit;
+    // Number of hits of the packet.
+    size_t hit_count;
+} moss_packet;
+
+// A hit as copied to C, the same fields as `MossHit` of the Rust and Python API.
+typedef struct moss_hit {
+    // The region ID of the hit.
+    uint8_t region;
+    // The row of the hit.
+    uint16_t row;
+    // The column of the hit.
+    uint16_t column;
+} moss_hit;
+
+// The statistics of a decoder as copied to C, the same fields as `StreamStats` of the Rust and Python API.
+typedef struct moss_stats {
+    // Number of bytes fed to the decoder.
+    size_t bytes;
+    // Number of decoded packets.
+    size_t packets;
+    // Number of decoded hits.
+    size_t hits;
+    // Number of protocol errors the decoder recovered from.
+    size_t protocol_errors;
+} moss_stats;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The version of the library as a NUL-terminated string.
+const char *moss_decoder_version(void);
+
+// The message of the last error on the calling thread, or NULL if there was none.
+//
+// The string is valid until the next call of a function of the library on the same thread.
+const char *moss_last_error(void);
+
+// Create a decoder with the default framing, free it with `moss_decoder_free()`.
+//
+// # Safety
+// `decoder` must be NULL or valid for writing a pointer.
+enum moss_status moss_decoder_new(struct moss_decoder **decoder);
+
+// Create a decoder with the given framing, see `FramingConfig` of the Python API, free it with `moss_decoder_free()`.
+//
+// # Safety
+// `filler_bytes` must be valid for reading `filler_len` bytes, it may be NULL if `filler_len` is 0.
+// `decoder` must be NULL or valid for writing a pointer.
+enum moss_status moss_decoder_new_with_framing(const uint8_t *filler_bytes,
+                                               size_t filler_len,
+                                               size_t alignment,
+                                               bool allow_leading_trailer,
+                                               struct moss_decoder **decoder);
+
+// Free a decoder created with `moss_decoder_new()` or `moss_decoder_new_with_framing()`, NULL is ignored.
+//
+// # Safety
+// `decoder` must be NULL or a decoder that was not freed yet.
+void moss_decoder_free(struct moss_decoder *decoder);
+
+// Decode the complete events in the next chunk of raw data, the packets are retrieved with `moss_decoder_poll()`.
+//
+// Returns `MOSS_STATUS_PROTOCOL` if the chunk contained protocol errors, the valid events are still decoded
+// and must still be polled.
+//
+// # Safety
+// `decoder` must be NULL or a valid decoder, `data` must be valid for reading `len` bytes, it may be NULL if `len` is 0.
+enum moss_status moss_decoder_feed(struct moss_decoder *decoder,
+                                   const uint8_t *data,
+                                   size_t len);
+
+// The number of decoded packets not yet polled, 0 if `decoder` is NULL.
+//
+// # Safety
+// `decoder` must be NULL or a valid decoder.
+size_t moss_decoder_pending(const struct moss_decoder *decoder);
+
+// Copy as many decoded packets and their hits as fit into the `packets` and `hits` arrays, oldest first.
+//
+// The number of packets and hits written are stored in `packets_written` and `hits_written`.
+// Returns `MOSS_STATUS_BUFFER_TOO_SMALL` if no packet was written because the next packet has more hits than `max_hits`.
+//
+// # Safety
+// `decoder` must be NULL or a valid decoder. `packets` and `hits` must be valid for writing `max_packets` and `max_hits` elements,
+// they may be NULL if the maximum is 0. `packets_written` and `hits_written` must be NULL or valid for writing.
+enum moss_status moss_decoder_poll(struct moss_decoder *decoder,
+                                   struct moss_packet *packets,
+                                   size_t max_packets,
+                                   struct moss_hit *hits,
+                                   size_t max_hits,
+                                   size_t *packets_written,
+                                   size_t *hits_written);
+
+// Copy the statistics of the decoder to `stats`.
+//
+// # Safety
+// `decoder` must be NULL or a valid decoder, `stats` must be NULL or valid for writing.
+enum moss_status moss_decoder_stats(const struct moss_decoder *decoder, struct moss_stats *stats);
+
+// Discard the partial event, the packets not yet polled and reset the statistics.
+//
+// # Safety
+// `decoder` must be NULL or a valid decoder.
+enum moss_status moss_decoder_reset(struct moss_decoder *decoder);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MOSS_DECODER_H */
@@ -1,7 +1,10 @@
 """Performant decoding of MOSS readout data implemented in Rust"""
 
 from pathlib import Path
-from typing import Optional
+from typing import Any, BinaryIO, Callable, Iterable, Iterator, Literal, Optional, Union
+
+# Any object implementing the buffer protocol with `uint8` items, e.g. NumPy arrays and `mmap` objects, is accepted without copying
+ReadableBuffer = Union[bytes, bytearray, memoryview]
 
 class MossHit:
     """A MOSS hit instance"""
@@ -14,39 +17,530 @@ class MossHit:
         self.region = region
         self.column = column
         self.row = row
+    def __hash__(self) -> int: ...
+    def __lt__(self, other: MossHit) -> bool:
+        """Hits are ordered by region, then row, then column"""
+
+class PacketMetadata:
+    """Where a `MossPacket` was found in the decoded stream"""
+
+    header_offset: int
+    trailer_offset: int
+    sequence: int
+    source_file: Optional[str]
+    trigger_id: Optional[int]
+    timestamp: Optional[int]
+
+    def __init__(
+        self,
+        header_offset: int,
+        trailer_offset: int,
+        sequence: int,
+        source_file: Optional[str] = None,
+        trigger_id: Optional[int] = None,
+        timestamp: Optional[int] = None,
+    ) -> PacketMetadata: ...
 
 class MossPacket:
     """A decoded MOSS event packet with a `Unit ID` and a list of `MossHit`s"""
 
     unit_id: int
     hits: list[MossHit]
+    metadata: Optional[PacketMetadata]
 
     def __init__(self, unit_id: int) -> MossPacket:
         self.unit_id = unit_id
         self.hits = []
+        self.metadata = None
+    def __eq__(self, other: object) -> bool:
+        """Packets are equal if their unit IDs and hits are equal, the metadata is not compared"""
+    def __hash__(self) -> int:
+        """Hashes the unit ID and hits, the metadata is not included"""
+    def __lt__(self, other: MossPacket) -> bool:
+        """Packets are ordered by unit ID, then by their hits"""
 
-def decode_event(bytes: bytes) -> tuple[MossPacket, int]: ...
-def decode_all_events(bytes: bytes) -> tuple[list[MossPacket], int]: ...
-def decode_from_file(path: str | Path) -> list[MossPacket]: ...
-def decode_n_events(
+class MossEvent:
+    """The `MossPacket`s of all units of a stitched sensor read out for one trigger"""
+
+    trigger_id: Optional[int]
+    timestamp: Optional[int]
+    packets: list[MossPacket]
+    missing_units: list[int]
+    duplicate_units: list[int]
+
+    def hits(self) -> list[tuple[int, MossHit]]:
+        """The hits of all units as `(unit_id, hit)` pairs"""
+    def hit_count(self) -> int: ...
+    def unit_ids(self) -> list[int]: ...
+    def is_complete(self) -> bool: ...
+
+class BatchColumn:
+    """A read-only view of an array of a `MossEventBatch`, or the part of it of one event.
+    Supports the buffer protocol, `memoryview(batch.rows)` and `numpy.asarray(batch.rows)` don't copy the items"""
+
+    def __len__(self) -> int: ...
+    def __getitem__(self, i: int) -> int: ...
+    def to_list(self) -> list[int]: ...
+
+class MossEventView:
+    """A view of an event of a `MossEventBatch`, yielded by iterating the batch"""
+
+    index: int
+    unit_id: int
+    hits: list[MossHit]
+    regions: BatchColumn
+    rows: BatchColumn
+    columns: BatchColumn
+
+    def __len__(self) -> int: ...
+    def to_packet(self) -> MossPacket: ...
+
+class MossEventBatch:
+    """The hits of many MOSS events stored in contiguous arrays.
+    The hits of event `i` are at `event_offsets[i]:event_offsets[i + 1]`"""
+
+    unit_ids: BatchColumn
+    regions: BatchColumn
+    rows: BatchColumn
+    columns: BatchColumn
+    event_offsets: BatchColumn
+
+    def __init__(self) -> MossEventBatch: ...
+    def __len__(self) -> int: ...
+    def __getitem__(self, i: int) -> MossPacket: ...
+    def __iter__(self) -> Iterator[MossEventView]: ...
+    def to_packets(self) -> list[MossPacket]: ...
+    @staticmethod
+    def from_packets(packets: list[MossPacket]) -> MossEventBatch: ...
+
+class DecodeOptions:
+    """Options for `decode`, `decode_file` and `decode_stream`.
+
+    - `skip`: Events to skip before decoding, the skipped events are not validated.
+    - `take`: Maximum number of events to decode, `None` decodes all events.
+    - `prepend`: Bytes decoded before the input, e.g. the `remainder` of the previous result.
+    - `errors`: `"raise"` to throw on the first invalid event, `"skip"` to record the error and continue.
+    - `mask`: Hits removed from the decoded events, e.g. noisy pixels.
+    - `output`: `"packets"` for a list of `MossPacket`s, `"batch"` for a `MossEventBatch`.
+    - `metadata`: Attach `PacketMetadata` to each `MossPacket`, only with the `"packets"` output."""
+
+    skip: int
+    take: Optional[int]
+    prepend: bytes
+    errors: Literal["raise", "skip"]
+    mask: set[MossHit]
+    output: Literal["packets", "batch"]
+    metadata: bool
+
+    def __init__(
+        self,
+        skip: int = 0,
+        take: Optional[int] = None,
+        prepend: Optional[bytes] = None,
+        errors: Literal["raise", "skip"] = "raise",
+        mask: Optional[Iterable[MossHit]] = None,
+        output: Literal["packets", "batch"] = "packets",
+        metadata: bool = False,
+    ) -> DecodeOptions: ...
+
+class DecodeResult:
+    """The result of decoding with `DecodeOptions`"""
+
+    packets: list[MossPacket]
+    batch: Optional[MossEventBatch]
+    skipped: int
+    bytes_consumed: int
+    remainder: bytes
+    errors: list[str]
+
+    def __len__(self) -> int: ...
+
+class HitMap:
+    """Hit counts of each pixel of each unit, filled without calling into Python when passed as sink"""
+
+    events: int
+    hits: int
+
+    def __init__(self) -> HitMap: ...
+    def add(self, packet: MossPacket) -> None: ...
+    def count(self, unit_id: int, region: int, row: int, column: int) -> int: ...
+    def unit_ids(self) -> list[int]: ...
+    def region_map(self, unit_id: int, region: int) -> list[list[int]]:
+        """The hit counts of a region as 512 rows of 512 columns"""
+    def hottest(self, n: int) -> list[tuple[int, MossHit, int]]:
+        """The `n` pixels with the most hits as `(unit_id, hit, count)`"""
+    def clear(self) -> None: ...
+
+class DetectorLayout:
+    """Maps units to detectors for `export_corryvreckan`. Within a unit the regions are side by side,
+    a hit is at column `region * region_size + column` and row `row`, offset by the position of the unit in its detector"""
+
+    def __init__(self) -> DetectorLayout: ...
+    @staticmethod
+    def per_unit(
+        units: Optional[list[int]] = None,
+        region_size: Optional[int] = None,
+        pitch_um: Optional[float] = None,
+        prefix: str = "MOSS_unit",
+    ) -> DetectorLayout:
+        """A detector named `<prefix><unit ID>` for each unit, all 10 units by default.
+        The region size and pitch default to 256 pixels and 22.5 um for the top half-units 1-5,
+        and 320 pixels and 18 um for the bottom half-units 6-10"""
+    @staticmethod
+    def stitched(
+        name: str = "MOSS",
+        units: Optional[list[int]] = None,
+        region_size: Optional[int] = None,
+        pitch_um: Optional[float] = None,
+    ) -> DetectorLayout:
+        """A single detector with the units side by side along the columns in the given order,
+        the top half-units by default. All units must have the same pitch"""
+    def add_unit(
+        self,
+        unit_id: int,
+        detector: str,
+        column_offset: int = 0,
+        row_offset: int = 0,
+        region_size: Optional[int] = None,
+        pitch_um: Optional[float] = None,
+    ) -> None:
+        """Raises a ValueError if the pitch differs from other units of the detector,
+        or the unit doesn't fit in a detector of 65536 x 65536 pixels"""
+    def detectors(self) -> list[str]: ...
+    def position(self, unit_id: int, hit: MossHit) -> Optional[tuple[str, int, int]]:
+        """The detector, column and row of a hit, `None` if the unit is not in the layout or the hit is outside its region"""
+
+class CorryExportStats:
+    """Summary of an export to Corryvreckan hit files"""
+
+    events: int
+    hits_written: int
+    hits_skipped: int
+    output_files: list[str]
+
+class TextExportStats:
+    """Summary of an export to a CSV or JSON Lines file"""
+
+    packets: int
+    hits: int
+    errors: list[str]
+    bytes_consumed: int
+    output_file: str
+    compressed: bool
+
+class EventFileInfo:
+    """The header of an event file written by `write_event_file`"""
+
+    version: tuple[int, int]
+    events: int
+    hits: int
+    metadata: dict[str, str]
+    # Byte offset of each column stored uncompressed: unit_ids (uint8), event_offsets (uint64),
+    # regions (uint8), rows (uint16) and columns (uint16), all little-endian
+    column_offsets: dict[str, int]
+
+class StopDecoding:
+    """Type of the `STOP` sentinel"""
+
+# Returned by a sink callback to stop decoding
+STOP: StopDecoding
+
+# Called with each `MossPacket`, or a list of packets if a batch size is given
+PacketCallback = Callable[[Any], Optional[StopDecoding]]
+
+class FramingConfig:
+    """How MOSS events are framed by the readout firmware.
+
+    - `filler_bytes`: Bytes allowed between events.
+    - `alignment`: Events are padded after the trailer to a multiple of `alignment` bytes.
+    - `allow_leading_trailer`: Whether the data may start with a Unit Frame Trailer."""
+
+    filler_bytes: list[int]
+    alignment: int
+    allow_leading_trailer: bool
+
+    def __init__(
+        self,
+        filler_bytes: list[int] = [0xFA],
+        alignment: int = 1,
+        allow_leading_trailer: bool = True,
+    ) -> FramingConfig: ...
+
+class ReadoutWrapper:
+    """Example record format wrapping each MOSS frame, other formats are supported from Rust
+    by implementing the `OuterFraming` trait. All fields are little-endian:
+    magic (u16), payload length N (u16), trigger ID (u32), timestamp (u64), payload (N bytes),
+    checksum (u16, wrapping sum of the bytes from the length field to the end of the payload)"""
+
+    magic: int
+    verify_checksum: bool
+
+    def __init__(
+        self, magic: int = 0x5AA5, verify_checksum: bool = True
+    ) -> ReadoutWrapper: ...
+    def wrap(self, trigger_id: int, timestamp: int, payload: bytes) -> bytes: ...
+
+class StreamStats:
+    """Statistics of a decoded stream"""
+
+    bytes: int
+    packets: int
+    hits: int
+    protocol_errors: int
+    last_error: Optional[str]
+
+class EventDiff:
+    """A difference between an event of decoding result a and b.
+    An event missing from one side has no index, unit ID or hits on that side"""
+
+    index_a: Optional[int]
+    index_b: Optional[int]
+    unit_id_a: Optional[int]
+    unit_id_b: Optional[int]
+    added_hits: list[MossHit]
+    removed_hits: list[MossHit]
+    def is_missing_in_a(self) -> bool: ...
+    def is_missing_in_b(self) -> bool: ...
+    def is_unit_id_mismatch(self) -> bool: ...
+
+class DecodeDiff:
+    """Event by event comparison of two decoding results with summary counts"""
+
+    events_a: int
+    events_b: int
+    identical_events: int
+    missing_in_a: int
+    missing_in_b: int
+    unit_id_mismatches: int
+    events_with_hit_differences: int
+    added_hits: int
+    removed_hits: int
+    differences: list[EventDiff]
+    def is_identical(self) -> bool: ...
+
+class EventFilter:
+    """Selects events by their properties, an event is selected if it matches all the criteria that are set.
+    Events are counted from 0 including invalid events, invalid events are never selected if a hit count is set"""
+
+    def __init__(
+        self,
+        first_event: Optional[int] = None,
+        event_count: Optional[int] = None,
+        unit_ids: Optional[list[int]] = None,
+        min_hits: Optional[int] = None,
+        max_hits: Optional[int] = None,
+        has_error: Optional[bool] = None,
+    ) -> EventFilter: ...
+
+class RawFilterStats:
+    """Summary of a raw file filtering"""
+
+    events_read: int
+    events_written: int
+    bytes_written: int
+    output_files: list[str]
+
+class MossDecoder:
+    """Decodes a MOSS stream chunk by chunk, keeping the partial event, statistics and buffers between chunks.
+    Protocol errors are counted in `stats` and decoding resumes at the next Unit Frame Header"""
+
+    stats: StreamStats
+    remainder: bytes
+
+    def __init__(self, framing: Optional[FramingConfig] = None) -> MossDecoder: ...
+    def feed(self, bytes: ReadableBuffer) -> list[MossPacket]:
+        """Returns the packets completed by this chunk"""
+    def reset(self) -> None:
+        """Discards the partial event and resets the statistics"""
+
+class LinkRecordFormat:
+    """Header of the link records of an interleaved multi-chip stream, a chip ID (u8) followed by the payload length.
+
+    - `length_size`: Number of bytes of the payload length, 1, 2 or 4.
+    - `big_endian`: Whether the payload length is big-endian."""
+
+    length_size: int
+    big_endian: bool
+
+    def __init__(
+        self, length_size: int = 2, big_endian: bool = False
+    ) -> LinkRecordFormat: ...
+
+class StreamDemux:
+    """Splits an interleaved multi-chip stream of link records into per-chip decoders.
+    Each record is a chip ID, the payload length N as described by `link_format` and N bytes of raw MOSS data.
+    The default format, a little-endian u16 length, is an example layout"""
+
+    link_format: LinkRecordFormat
+
+    def __init__(
+        self,
+        framing: Optional[FramingConfig] = None,
+        link_format: Optional[LinkRecordFormat] = None,
+    ) -> StreamDemux: ...
+    def feed(self, bytes: ReadableBuffer) -> list[tuple[int, MossPacket]]: ...
+    def chip_ids(self) -> list[int]: ...
+    def stats(self, chip_id: int) -> StreamStats: ...
+    def all_stats(self) -> dict[int, StreamStats]: ...
+    def remainder(self, chip_id: int) -> list[int]: ...
+
+def decode(
+    bytes: ReadableBuffer,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult: ...
+def decode_file(
+    path: str | Path,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult: ...
+def decode_stream(
+    stream: BinaryIO,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult: ...
+def decode_to_sink(
+    bytes: ReadableBuffer,
+    sink: PacketCallback | HitMap,
+    batch_size: Optional[int] = None,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult:
+    """Hands each decoded packet to `sink`. Writable buffers like `bytearray` are copied before decoding
+    as the sink could modify them, read-only buffers like `bytes` are decoded in place"""
+def decode_file_to_sink(
     path: str | Path,
+    sink: PacketCallback | HitMap,
+    batch_size: Optional[int] = None,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult: ...
+def decode_stream_to_sink(
+    stream: BinaryIO,
+    sink: PacketCallback | HitMap,
+    batch_size: Optional[int] = None,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeResult: ...
+def decode_event(
+    bytes: ReadableBuffer, framing: Optional[FramingConfig] = None
+) -> tuple[MossPacket, int]: ...
+def decode_all_events(
+    bytes: ReadableBuffer, framing: Optional[FramingConfig] = None
+) -> tuple[list[MossPacket], int]: ...
+def decode_from_file(
+    path: str | Path, framing: Optional[FramingConfig] = None
+) -> list[MossPacket]: ...
+def decode_all_events_with_metadata(
+    bytes: ReadableBuffer, framing: Optional[FramingConfig] = None
+) -> tuple[list[MossPacket], int]: ...
+def decode_from_file_with_metadata(
+    path: str | Path, framing: Optional[FramingConfig] = None
+) -> list[MossPacket]: ...
+def decode_from_stream(
+    stream: BinaryIO, framing: Optional[FramingConfig] = None
+) -> list[MossPacket]:
+    """Decodes all data read from an object with a `readinto` or `read` method"""
+def decode_from_stream_with_metadata(
+    stream: BinaryIO, framing: Optional[FramingConfig] = None
+) -> list[MossPacket]: ...
+def decode_all_events_batch(
+    bytes: ReadableBuffer, framing: Optional[FramingConfig] = None
+) -> tuple[MossEventBatch, int]: ...
+def decode_n_events(
+    bytes: ReadableBuffer,
     take: int,
     skip: Optional[int] = None,
     prepend_buffer: Optional[bytes] = None,
+    framing: Optional[FramingConfig] = None,
 ) -> tuple[list[MossPacket], int]: ...
 def decode_n_events_from_file(
-    bytes: bytes,
+    path: str | Path,
     take: int,
     skip: Optional[int] = None,
     prepend_buffer: Optional[bytes] = None,
-) -> tuple[list[MossPacket], int]: ...
+    framing: Optional[FramingConfig] = None,
+) -> list[MossPacket]: ...
 def skip_n_take_all(
-    bytes: bytes, skip: int = None
-) -> tuple[list[MossPacket], Optional[bytes]]: ...
+    bytes: ReadableBuffer, skip: int, framing: Optional[FramingConfig] = None
+) -> tuple[Optional[list[MossPacket]], Optional[list[int]]]:
+    """The packets and the bytes after the last event if they contain a partial event, `None` if empty"""
 def skip_n_take_all_from_file(
-    path: str | Path, skip: int = None
-) -> tuple[list[MossPacket], Optional[bytes]]: ...
-def debug_decode_all_events(b: bytes) -> tuple[list[MossPacket], int, list[str]]: ...
+    path: str | Path, skip: int, framing: Optional[FramingConfig] = None
+) -> tuple[Optional[list[MossPacket]], Optional[list[int]]]: ...
+def decode_run(
+    paths: str | list[str | Path],
+    framing: Optional[FramingConfig] = None,
+) -> list[MossPacket]: ...
+def decode_wrapped_events(
+    bytes: ReadableBuffer,
+    wrapper: Optional[ReadoutWrapper] = None,
+    framing: Optional[FramingConfig] = None,
+) -> tuple[list[MossPacket], int]: ...
+def decode_wrapped_from_file(
+    path: str | Path,
+    wrapper: Optional[ReadoutWrapper] = None,
+    framing: Optional[FramingConfig] = None,
+) -> list[MossPacket]: ...
+def build_events(
+    packets: list[MossPacket], expected_units: Optional[list[int]] = None
+) -> list[MossEvent]: ...
+def debug_decode_all_events(
+    bytes: ReadableBuffer, framing: Optional[FramingConfig] = None
+) -> tuple[list[MossPacket], int, list[str]]: ...
 def debug_decode_all_events_from_file(
     path: str | Path,
+    framing: Optional[FramingConfig] = None,
 ) -> tuple[list[MossPacket], int, list[str]]: ...
+def hexdump(
+    bytes: ReadableBuffer,
+    start: Optional[int] = None,
+    end: Optional[int] = None,
+    first_event: Optional[int] = None,
+    event_count: Optional[int] = None,
+    framing: Optional[FramingConfig] = None,
+) -> str: ...
+def diff_packets(a: list[MossPacket], b: list[MossPacket]) -> DecodeDiff: ...
+def diff_files(
+    path_a: str | Path,
+    path_b: str | Path,
+    framing: Optional[FramingConfig] = None,
+) -> DecodeDiff: ...
+def filter_raw_file(
+    input: str | Path,
+    output: str | Path,
+    event_filter: Optional[EventFilter] = None,
+    split_events: Optional[int] = None,
+    split_bytes: Optional[int] = None,
+    framing: Optional[FramingConfig] = None,
+) -> RawFilterStats: ...
+def export_corryvreckan(
+    events: list[MossEvent] | list[MossPacket],
+    output_dir: str | Path,
+    layout: Optional[DetectorLayout] = None,
+    format: Optional[Literal["text", "binary"]] = None,
+) -> CorryExportStats:
+    """Writes text hit files unless `format` is `"binary"`"""
+def export_csv(
+    path: str | Path,
+    output: str | Path,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+    gzip: Optional[bool] = None,
+) -> TextExportStats: ...
+def export_jsonl(
+    path: str | Path,
+    output: str | Path,
+    options: Optional[DecodeOptions] = None,
+    framing: Optional[FramingConfig] = None,
+    gzip: Optional[bool] = None,
+) -> TextExportStats: ...
+def write_event_file(
+    path: str | Path,
+    events: MossEventBatch | list[MossPacket],
+    metadata: Optional[dict[str, str]] = None,
+    compression: Optional[Literal["none", "deflate"]] = None,
+) -> EventFileInfo:
+    """Stores the columns uncompressed unless `compression` is `"deflate"`"""
+def read_event_file(path: str | Path) -> MossEventBatch: ...
+def read_event_file_info(path: str | Path) -> EventFileInfo: ...
@@ -0,0 +1,351 @@
+//! Annotated dump of raw MOSS data for debugging firmware and protocol issues.
+//!
+//! Each byte is printed on its own line with its [MossWord] classification, the current region,
+//! the coordinates of each completed hit, the start and end of events and any protocol errors.
+//! The bytes of an event are checked with the transitions of the decoder FSM in [decode_hits_fsm](crate::decode_hits_fsm),
+//! so the dump flags exactly the bytes the decoders reject.
+use std::io::{self, Read};
+use std::ops::Range;
+
+use crate::decode_hits_fsm::{FsmStepper, Step};
+use crate::moss_protocol::MossWord;
+use crate::FramingConfig;
+
+/// Which part of the data to dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpSelection {
+    /// All bytes.
+    All,
+    /// The bytes in the given range of offsets.
+    Bytes(Range<usize>),
+    /// `count` events starting from event number `first`, counting from 0.
+    ///
+    /// Includes any filler or invalid bytes between the selected events.
+    Events {
+        /// Number of the first event to dump.
+        first: usize,
+        /// Number of events to dump.
+        count: usize,
+    },
+}
+
+/// Marker placed in front of lines with an error so they are easy to find.
+const ERROR_MARKER: &str = "!!";
+
+/// The column names at the top of a dump.
+const DUMP_HEADER: &str = "   offset     byte word                region  annotation\n";
+
+/// Number of bytes read at a time by [annotated_hexdump_from_reader].
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The state of the dump between bytes.
+#[derive(Debug, Default)]
+struct DumpState {
+    /// The decoder FSM of the current event, `None` between events
+    event: Option<FsmStepper>,
+    event_cnt: usize,
+    event_hits: usize,
+}
+
+/// Returns an annotated dump of the selected part of `bytes`.
+///
+/// The whole buffer up to the selection is processed so the state at the start of the selection is correct,
+/// processing stops at the end of the selection.
+pub fn annotated_hexdump(
+    bytes: &[u8],
+    selection: &DumpSelection,
+    framing: &FramingConfig,
+) -> String {
+    let mut dump = String::from(DUMP_HEADER);
+    _ = dump_bytes(
+        &mut DumpState::default(),
+        bytes,
+        0,
+        selection,
+        framing,
+        &mut dump,
+    );
+    dump
+}
+
+/// Same as [annotated_hexdump] but reads the data from `reader` in chunks,
+/// only reading up to the end of the selection.
+pub fn annotated_hexdump_from_reader(
+    mut reader: impl Read,
+    selection: &DumpSelection,
+    framing: &FramingConfig,
+) -> io::Result<String> {
+    let mut dump = String::from(DUMP_HEADER);
+    let mut state = DumpState::default();
+    let mut buf = vec![0; READ_CHUNK_SIZE];
+    let mut offset = 0;
+    loop {
+        let bytes_read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if dump_bytes(
+            &mut state,
+            &buf[..bytes_read],
+            offset,
+            selection,
+            framing,
+            &mut dump,
+        ) {
+            break;
+        }
+        offset += bytes_read;
+    }
+    Ok(dump)
+}
+
+/// Appends the dump lines of the selected bytes in `bytes`, which start at `start_offset` in the dumped data.
+///
+/// Returns true once the end of the selection is reached.
+fn dump_bytes(
+    state: &mut DumpState,
+    bytes: &[u8],
+    start_offset: usize,
+    selection: &DumpSelection,
+    framing: &FramingConfig,
+    dump: &mut String,
+) -> bool {
+    for (offset, &b) in (start_offset..).zip(bytes) {
+        // The number of the event the byte belongs to, for filler bytes the next event
+        let event_num = state.event_cnt;
+        let selected = match selection {
+            DumpSelection::All => true,
+            DumpSelection::Bytes(range) if offset >= range.end => return true,
+            DumpSelection::Bytes(range) => range.contains(&offset),
+            DumpSelection::Events { first, count } if event_num >= first + count => return true,
+            DumpSelection::Events { first, .. } => event_num >= *first,
+        };
+        let (word, annotation, is_error) = annotate(state, b, offset, framing);
+        // Skip filler in front of the first selected event
+        let in_event = state.event.is_some() || state.event_cnt > event_num;
+        if !selected
+            || matches!(selection, DumpSelection::Events { first, .. } if event_num == *first && !in_event)
+        {
+            continue;
+        }
+        let line = format!(
+            "{marker:2} {offset:08X}   {b:02X}   {word:<19} {region:<7} {annotation}",
+            marker = if is_error { ERROR_MARKER } else { "" },
+            region = state
+                .event
+                .as_ref()
+                .and_then(FsmStepper::region)
+                .map(|r| r.to_string())
+                .unwrap_or_default(),
+        );
+        dump.push_str(line.trim_end());
+        dump.push('\n');
+    }
+    false
+}
+
+/// Classifies `b`, updates the state and returns the word name, an annotation and whether the byte is an error.
+///
+/// An invalid byte in an event is ignored, the following bytes are checked against the state before it.
+fn annotate(
+    state: &mut DumpState,
+    b: u8,
+    offset: usize,
+    framing: &FramingConfig,
+) -> (&'static str, String, bool) {
+    let Some(event) = state.event.as_mut() else {
+        return annotate_between_events(state, b, offset, framing);
+    };
+    if MossWord::from_byte(b) == MossWord::UnitFrameHeader {
+        return (
+            "UNIT_FRAME_HEADER",
+            "ERROR: Unit Frame Header before the trailer of the previous event".to_string(),
+            true,
+        );
+    }
+    let in_hit = event.in_hit();
+    match event.step(b) {
+        Step::Valid => (word_name(b), String::new(), false),
+        Step::Hit(hit) => {
+            state.event_hits += 1;
+            (
+                word_name(b),
+                format!("hit row: {} column: {}", hit.row, hit.column),
+                false,
+            )
+        }
+        Step::End => {
+            state.event = None;
+            let event_num = state.event_cnt;
+            state.event_cnt += 1;
+            (
+                "UNIT_FRAME_TRAILER",
+                format!(
+                    "=== End of event #{event_num}, {} hits ===",
+                    state.event_hits
+                ),
+                false,
+            )
+        }
+        Step::Error => (
+            word_name(b),
+            format!(
+                "ERROR: {}{}",
+                event.expected(),
+                if in_hit { ", incomplete hit" } else { "" }
+            ),
+            true,
+        ),
+    }
+}
+
+/// Same as [annotate] for a byte that is not part of an event.
+fn annotate_between_events(
+    state: &mut DumpState,
+    b: u8,
+    offset: usize,
+    framing: &FramingConfig,
+) -> (&'static str, String, bool) {
+    if framing.is_filler(b) {
+        return ("FILLER", String::new(), false);
+    }
+    match MossWord::from_byte(b) {
+        MossWord::UnitFrameHeader if !MossWord::UNIT_FRAME_HEADER_RANGE.contains(&b) => (
+            "UNIT_FRAME_HEADER",
+            format!("ERROR: invalid unit ID {}", b & 0xF),
+            true,
+        ),
+        MossWord::UnitFrameHeader => {
+            state.event = Some(FsmStepper::new());
+            state.event_hits = 0;
+            (
+                "UNIT_FRAME_HEADER",
+                format!("=== Event #{} unit {} ===", state.event_cnt, b & 0xF),
+                false,
+            )
+        }
+        MossWord::UnitFrameTrailer if offset == 0 && framing.allow_leading_trailer() => (
+            "UNIT_FRAME_TRAILER",
+            "Trailer of a previous event".to_string(),
+            false,
+        ),
+        _ => (
+            word_name(b),
+            "ERROR: invalid byte between events".to_string(),
+            true,
+        ),
+    }
+}
+
+fn word_name(b: u8) -> &'static str {
+    match MossWord::from_byte(b) {
+        MossWord::Idle => "IDLE",
+        MossWord::UnitFrameHeader => "UNIT_FRAME_HEADER",
+        MossWord::UnitFrameTrailer => "UNIT_FRAME_TRAILER",
+        MossWord::RegionHeader => "REGION_HEADER",
+        MossWord::Data0 => "DATA_0",
+        MossWord::Data1 => "DATA_1",
+        MossWord::Data2 => "DATA_2",
+        MossWord::Delimiter => "DELIMITER",
+        MossWord::ProtocolError => "PROTOCOL_ERROR",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+
+    #[test]
+    fn test_hexdump_simple_event() {
+        let dump = annotated_hexdump(
+            &fake_event_simple(),
+            &DumpSelection::All,
+            &FramingConfig::default(),
+        );
+        println!("{dump}");
+
+        assert!(dump.contains("=== Event #0 unit 1 ==="));
+        assert!(dump.contains("=== End of event #0, 4 hits ==="));
+        assert_eq!(dump.matches("hit row:").count(), 4);
+        assert!(!dump.contains(ERROR_MARKER));
+    }
+
+    #[test]
+    fn test_hexdump_error_and_event_selection() {
+        let mut bytes = fake_event_simple();
+        bytes.extend([0xFA, 0xFA]);
+        let mut second_event = fake_event_simple();
+        second_event.insert(3, 0xFB);
+        bytes.extend(second_event);
+
+        let dump = annotated_hexdump(
+            &bytes,
+            &DumpSelection::Events { first: 1, count: 1 },
+            &FramingConfig::default(),
+        );
+        println!("{dump}");
+
+        assert!(!dump.contains("Event #0"));
+        assert!(dump.contains("=== Event #1 unit 1 ==="));
+        assert!(!dump.contains("FILLER"));
+        assert_eq!(dump.matches(ERROR_MARKER).count(), 1);
+        assert!(dump.contains("ERROR: Expected DATA_1, incomplete hit"));
+
+        let dump = annotated_hexdump(
+            &bytes,
+            &DumpSelection::Bytes(18..21),
+            &FramingConfig::default(),
+        );
+        assert_eq!(dump.lines().count(), 4);
+        assert!(dump.contains("FILLER"));
+    }
+
+    #[test]
+    fn test_hexdump_follows_decoder_fsm() {
+        // A lower Region Header after a higher one and IDLE directly after a Region Header are rejected by the decoder
+        let bytes = [0xD1, 0xC1, 0xC0, 0xC2, 0xFF, 0xE0];
+        assert!(
+            crate::rust_only::extract_packet_from_buf(&bytes, None, &FramingConfig::default())
+                .is_err()
+        );
+
+        let dump = annotated_hexdump(&bytes, &DumpSelection::All, &FramingConfig::default());
+        println!("{dump}");
+        let error_lines: Vec<_> = dump
+            .lines()
+            .filter(|l| l.starts_with(ERROR_MARKER))
+            .collect();
+        assert_eq!(error_lines.len(), 2);
+        assert!(error_lines[0].contains("REGION_HEADER"));
+        assert!(error_lines[1].contains("IDLE"));
+        assert!(dump.contains("=== End of event #0, 0 hits ==="));
+    }
+
+    #[test]
+    fn test_hexdump_from_reader_stops_at_selection_end() {
+        let bytes = fake_event_simple().repeat(3);
+        let selection = DumpSelection::Events { first: 1, count: 1 };
+        let mut reader = std::io::Cursor::new(&bytes);
+        let dump = annotated_hexdump_from_reader(
+            std::io::Read::by_ref(&mut reader).take(40),
+            &selection,
+            &FramingConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dump,
+            annotated_hexdump(&bytes, &selection, &FramingConfig::default())
+        );
+        assert!(dump.contains("=== End of event #1, 4 hits ==="));
+        let dump = annotated_hexdump(
+            &bytes,
+            &DumpSelection::Bytes(2..5),
+            &FramingConfig::default(),
+        );
+        assert_eq!(dump.lines().count(), 4);
+    }
+}
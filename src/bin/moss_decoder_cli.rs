@@ -0,0 +1,334 @@
+//! Command line tools for inspecting raw MOSS data.
+//!
+//! Build with `cargo build --no-default-features --features cli`.
+use std::process::ExitCode;
+
+use moss_decoder::annotated_dump::{annotated_hexdump_from_reader, DumpSelection};
+use moss_decoder::decode_options::ErrorPolicy;
+use moss_decoder::raw_filter::{self, EventFilter, RawEventWriter, SplitBy};
+use moss_decoder::replay::{ReplayOptions, ReplayRate, ReplayTarget};
+use moss_decoder::text_export::{self, TextFormat};
+use moss_decoder::{DecodeOptions, FramingConfig};
+
+/// Default number of differing events printed by `diff`.
+const DEFAULT_MAX_DIFFERENCES: usize = 20;
+
+const USAGE: &str = "\
+Usage: moss_decoder_cli <COMMAND> [ARGS]
+
+Commands:
+  hexdump <FILE> [--start <OFFSET>] [--end <OFFSET>] [--event <N>] [--count <N>]
+      Print an annotated dump of the raw data in FILE, one line per byte.
+      Select a range of bytes with --start/--end or a range of events with --event/--count.
+  diff <FILE_A> <FILE_B> [--max <N>]
+      Decode both files and print the events that differ (at most N, default 20) and summary counts.
+      Exits with status 1 if the files differ.
+  filter <INPUT> <OUTPUT> [--first-event <N>] [--event-count <N>] [--units <ID,...>] [--min-hits <N>]
+         [--max-hits <N>] [--errors <only|none>] [--split-events <N>] [--split-bytes <N>]
+      Write the raw bytes of the selected events in INPUT to OUTPUT, optionally split into several files
+      named OUTPUT with a file number appended. Use --min-hits 1 to drop empty events.
+  export <INPUT> <OUTPUT> [--format <csv|jsonl>] [--errors <raise|skip>] [--gzip <yes|no>]
+      Decode INPUT and write a line per hit (csv, default) or a JSON object per packet (jsonl) to OUTPUT.
+      With --errors skip decoding resumes after errors, in jsonl they are written to OUTPUT.
+      OUTPUT is gzip compressed if its name ends in .gz, unless overridden with --gzip.
+  replay <FILE> <TARGET> [--bytes-per-sec <RATE>] [--events-per-sec <RATE>] [--loops <N>]
+      Replay FILE verbatim to TARGET at a fixed rate, N times (default 1, 0 to loop until TARGET is closed).
+      TARGET is - for stdout, a file or FIFO path, tcp://<host>:<port> to connect to a server
+      or tcp-listen://<host>:<port> to wait for a client.";
+
+fn main() -> ExitCode {
+    // Decoding errors are Python exceptions which need an interpreter to be formatted
+    pyo3::prepare_freethreaded_python();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("hexdump") => hexdump(&args[1..]),
+        Some("filter") => filter(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("diff") => match diff(&args[1..]) {
+            Ok(true) => Ok(()),
+            Ok(false) => return ExitCode::FAILURE,
+            Err(e) => Err(e),
+        },
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Some(cmd) => Err(format!("Unknown command: {cmd}")),
+        None => Err("Missing command".to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Options of a subcommand: the positional arguments and the values of the `--<name> <value>` options.
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String], known_options: &[&str]) -> Result<Self, String> {
+        let mut parsed = Self {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                if !known_options.contains(&name) {
+                    return Err(format!("Unknown option: {arg}"));
+                }
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {arg}"))?;
+                parsed.options.push((name, value));
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn value(&self, name: &str) -> Option<&'a str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| *value)
+    }
+
+    fn number(&self, name: &str) -> Result<Option<usize>, String> {
+        self.value(name).map(parse_number).transpose()
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(value: &str) -> Result<usize, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|e| format!("Invalid number {value}: {e}"))
+}
+
+fn hexdump(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["start", "end", "event", "count"])?;
+    let [path] = args.positional[..] else {
+        return Err("hexdump takes exactly one file".to_string());
+    };
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed opening {path}: {e}"))?;
+
+    let selection = match (
+        args.number("start")?,
+        args.number("end")?,
+        args.number("event")?,
+        args.number("count")?,
+    ) {
+        (None, None, None, None) => DumpSelection::All,
+        (start, end, None, None) => {
+            DumpSelection::Bytes(start.unwrap_or(0)..end.unwrap_or(usize::MAX))
+        }
+        (None, None, first, count) => DumpSelection::Events {
+            first: first.unwrap_or(0),
+            count: count.unwrap_or(1),
+        },
+        _ => {
+            return Err("Select either a range of bytes or a range of events, not both".to_string())
+        }
+    };
+    // Only the file up to the end of the selection is read
+    let dump = annotated_hexdump_from_reader(file, &selection, &FramingConfig::default())
+        .map_err(|e| format!("Failed reading {path}: {e}"))?;
+    print!("{dump}");
+    Ok(())
+}
+
+/// Returns true if the decoded files are identical.
+fn diff(args: &[String]) -> Result<bool, String> {
+    let args = Args::parse(args, &["max"])?;
+    let [path_a, path_b] = args.positional[..] else {
+        return Err("diff takes exactly two files".to_string());
+    };
+    let max_differences = args.number("max")?.unwrap_or(DEFAULT_MAX_DIFFERENCES);
+
+    let diff = moss_decoder::diff_files(path_a.into(), path_b.into(), None)
+        .map_err(|e| format!("Failed decoding: {e}"))?;
+    diff.differences
+        .iter()
+        .take(max_differences)
+        .for_each(|event_diff| println!("{event_diff}"));
+    if diff.differences.len() > max_differences {
+        println!(
+            "... {} more differences",
+            diff.differences.len() - max_differences
+        );
+    }
+    println!("{diff}");
+    Ok(diff.is_identical())
+}
+
+fn filter(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(
+        args,
+        &[
+            "first-event",
+            "event-count",
+            "units",
+            "min-hits",
+            "max-hits",
+            "errors",
+            "split-events",
+            "split-bytes",
+        ],
+    )?;
+    let [input, output] = args.positional[..] else {
+        return Err("filter takes an input and an output file".to_string());
+    };
+
+    let (first_event, event_count) = (args.number("first-event")?, args.number("event-count")?);
+    let event_filter = EventFilter {
+        event_range: raw_filter::event_range(first_event, event_count),
+        unit_ids: args
+            .value("units")
+            .map(|units| {
+                units
+                    .split(',')
+                    .map(|unit| {
+                        parse_number(unit).and_then(|u| u8::try_from(u).map_err(|e| e.to_string()))
+                    })
+                    .collect::<Result<Vec<u8>, String>>()
+            })
+            .transpose()?,
+        min_hits: args.number("min-hits")?,
+        max_hits: args.number("max-hits")?,
+        has_error: match args.value("errors") {
+            None => None,
+            Some("only") => Some(true),
+            Some("none") => Some(false),
+            Some(other) => return Err(format!("Invalid value for --errors: {other}")),
+        },
+    };
+    let split = match (args.number("split-events")?, args.number("split-bytes")?) {
+        (None, None) => SplitBy::Never,
+        (Some(events), None) if events > 0 => SplitBy::Events(events),
+        (None, Some(bytes)) if bytes > 0 => SplitBy::Bytes(bytes),
+        _ => {
+            return Err(
+                "Split by a positive number of either events or bytes, not both".to_string(),
+            )
+        }
+    };
+
+    let writer =
+        RawEventWriter::new(output, split).map_err(|e| format!("Failed creating {output}: {e}"))?;
+    let stats =
+        raw_filter::filter_raw_file(input, &event_filter, writer, &FramingConfig::default())
+            .map_err(|e| format!("Failed filtering {input}: {e}"))?;
+    println!(
+        "Read {read} events, wrote {written} events ({bytes} bytes) to:",
+        read = stats.events_read,
+        written = stats.events_written,
+        bytes = stats.bytes_written
+    );
+    stats
+        .output_files
+        .iter()
+        .for_each(|file| println!("  {}", file.display()));
+    Ok(())
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["format", "errors", "gzip"])?;
+    let [input, output] = args.positional[..] else {
+        return Err("export takes an input and an output file".to_string());
+    };
+    let format = match args.value("format") {
+        None | Some("csv") => TextFormat::Csv,
+        Some("jsonl") => TextFormat::JsonLines,
+        Some(other) => return Err(format!("Invalid value for --format: {other}")),
+    };
+    let errors = match args.value("errors") {
+        None | Some("raise") => ErrorPolicy::Raise,
+        Some("skip") => ErrorPolicy::Skip,
+        Some(other) => return Err(format!("Invalid value for --errors: {other}")),
+    };
+    let gzip = match args.value("gzip") {
+        None => None,
+        Some("yes") => Some(true),
+        Some("no") => Some(false),
+        Some(other) => return Err(format!("Invalid value for --gzip: {other}")),
+    };
+
+    let options = DecodeOptions {
+        errors,
+        ..Default::default()
+    };
+    let stats = text_export::export_file(
+        input,
+        output,
+        format,
+        &options,
+        &FramingConfig::default(),
+        gzip,
+    )
+    .map_err(|e| format!("Failed exporting {input}: {e}"))?;
+    println!(
+        "Wrote {packets} packets ({hits} hits) to {output}, skipped {errors} errors",
+        packets = stats.packets,
+        hits = stats.hits,
+        errors = stats.errors.len()
+    );
+    Ok(())
+}
+
+fn replay(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["bytes-per-sec", "events-per-sec", "loops"])?;
+    let [input, target] = args.positional[..] else {
+        return Err("replay takes a file and a target".to_string());
+    };
+    let parse_rate = |name| {
+        args.value(name)
+            .map(|rate| match rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 => Ok(rate),
+                _ => Err(format!("Invalid rate {rate}, expected a positive number")),
+            })
+            .transpose()
+    };
+    let rate = match (parse_rate("bytes-per-sec")?, parse_rate("events-per-sec")?) {
+        (None, None) => ReplayRate::Unlimited,
+        (Some(rate), None) => ReplayRate::BytesPerSec(rate),
+        (None, Some(rate)) => ReplayRate::EventsPerSec(rate),
+        (Some(_), Some(_)) => {
+            return Err("Limit either the byte rate or the event rate".to_string())
+        }
+    };
+    let options = ReplayOptions {
+        rate,
+        loops: match args.number("loops")? {
+            Some(0) => None,
+            loops => Some(loops.unwrap_or(1)),
+        },
+        framing: FramingConfig::default(),
+    };
+
+    let Ok(target) = target.parse::<ReplayTarget>();
+    let mut output = target
+        .open()
+        .map_err(|e| format!("Failed opening {target:?}: {e}"))?;
+    let stats = moss_decoder::replay::replay(input, &mut output, &options)
+        .map_err(|e| format!("Failed replaying {input}: {e}"))?;
+    eprintln!(
+        "Replayed {events} events ({bytes} bytes) {loops} time(s) in {elapsed:.3?}",
+        events = stats.events,
+        bytes = stats.bytes,
+        loops = stats.loops,
+        elapsed = stats.elapsed
+    );
+    Ok(())
+}
@@ -0,0 +1,94 @@
+//! Zero-copy access to the raw data of any Python object implementing the buffer protocol.
+//!
+//! Used with `#[pyo3(from_py_with = "...")]` so the decoding functions keep taking `&[u8]` in Rust,
+//! while Python callers can pass `bytes`, `bytearray`, `memoryview` slices, `mmap` objects and NumPy `uint8` arrays.
+use pyo3::buffer::PyBuffer;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// Keeps a buffer exported for as long as the slice borrowed from it is in use.
+///
+/// Exporters like `bytearray` can't be resized while a buffer is exported,
+/// and the guard is only released when the GIL pool of the call is dropped, after the decoding function returns.
+#[pyclass]
+struct BufferGuard(#[allow(dead_code)] PyBuffer<u8>);
+
+/// Borrows the bytes of `obj` for the duration of the current call without copying them.
+///
+/// Buffers that are not C-contiguous, e.g. a `memoryview` with a step, are copied once.
+///
+/// Only for functions that don't run Python code while the bytes are borrowed, a writable buffer could be modified by it.
+/// Functions calling back into Python use [readonly_bytes_from_buffer].
+pub(crate) fn bytes_from_buffer(obj: &PyAny) -> PyResult<&[u8]> {
+    borrow_buffer(obj, false)
+}
+
+/// Same as [bytes_from_buffer] but copies writable buffers once, for functions that call back into Python while decoding.
+///
+/// Read-only buffers like `bytes` and `mmap` objects opened with `ACCESS_READ` are still borrowed without copying.
+pub(crate) fn readonly_bytes_from_buffer(obj: &PyAny) -> PyResult<&[u8]> {
+    borrow_buffer(obj, true)
+}
+
+fn borrow_buffer(obj: &PyAny, copy_writable: bool) -> PyResult<&[u8]> {
+    if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        return Ok(bytes.as_bytes());
+    }
+    let py = obj.py();
+    let buffer = PyBuffer::<u8>::get(obj)?;
+    if !buffer.is_c_contiguous() || (copy_writable && !buffer.readonly()) {
+        return Ok(PyBytes::new(py, &buffer.to_vec(py)?).as_bytes());
+    }
+    let (ptr, len) = (buffer.buf_ptr() as *const u8, buffer.len_bytes());
+    _ = PyCell::new(py, BufferGuard(buffer))?;
+    if len == 0 {
+        return Ok(&[]);
+    }
+    // SAFETY: The buffer stays exported until the guard owned by the GIL pool is dropped, which outlives the returned slice.
+    // The buffer is either read-only, or the caller doesn't run Python code while the slice is in use
+    // and holds the GIL, so no other thread can write to the buffer in the meantime.
+    Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyByteArray;
+
+    #[test]
+    fn test_bytes_from_buffer() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let bytearray = PyByteArray::new(py, &[0xD1, 0xC0, 0xE0, 0xFA]);
+            let slice = bytes_from_buffer(bytearray).unwrap();
+            assert_eq!(slice, [0xD1, 0xC0, 0xE0, 0xFA]);
+            // Borrowed, not copied
+            assert_eq!(slice.as_ptr(), bytearray.data() as *const u8);
+
+            let stepped = py
+                .eval(
+                    "memoryview(bytearray([0xD1, 0xC0, 0xE0, 0xFA]))[::2]",
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(bytes_from_buffer(stepped).unwrap(), [0xD1, 0xE0]);
+
+            assert!(bytes_from_buffer(py.None().as_ref(py)).is_err());
+
+            // Writable buffers are copied for functions calling back into Python, read-only buffers are not
+            let copied = readonly_bytes_from_buffer(bytearray).unwrap();
+            assert_eq!(copied, [0xD1, 0xC0, 0xE0, 0xFA]);
+            assert_ne!(copied.as_ptr(), bytearray.data() as *const u8);
+            let readonly = py
+                .eval("memoryview(b'\\xD1\\xC0\\xE0')", None, None)
+                .unwrap();
+            let borrowed = readonly_bytes_from_buffer(readonly).unwrap();
+            assert_eq!(borrowed, [0xD1, 0xC0, 0xE0]);
+            assert_eq!(
+                borrowed.as_ptr(),
+                PyBuffer::<u8>::get(readonly).unwrap().buf_ptr() as *const u8
+            );
+        });
+    }
+}
@@ -0,0 +1,569 @@
+//! Export of decoded events as pixel hit files for track reconstruction with Corryvreckan.
+//!
+//! The hits of each unit are placed in the pixel matrix of a detector according to a [DetectorLayout],
+//! either one detector per unit or several units stitched into one detector.
+//! For each detector a hit file `<detector>.txt` or `<detector>.bin` is written, and for all detectors a Corryvreckan
+//! geometry file `geometry.conf` with the number of pixels and pitch of each detector, to be completed with the positions of the setup.
+//!
+//! The files are read by the Corryvreckan module `EventLoaderMOSS` in the `corryvreckan` directory of the repository,
+//! which is built with Corryvreckan like its own modules:
+//! - The text hit file has comment lines starting with `#` followed by a line `<event> <column> <row>` per hit,
+//!   the fields are decimal integers separated by a single space.
+//! - The binary hit file has no header, just a 12 byte record per hit: event (u64), column (u16), row (u16), all little-endian.
+//! - The event index `events.txt` has comment lines starting with `#` followed by a line `<event>` per exported event,
+//!   including events without hits, so the loader can tell an empty event from a missing one.
+//!
+//! Hits are written in event order. Events are numbered by trigger ID if the packets were decoded from readout wrapper records,
+//! otherwise by the sequence number in the stream of their first packet, see [event_number].
+//! Events skipped while decoding leave a gap in the numbering rather than renumbering the events after them.
+//! Columns and rows are pixel indexes in the matrix of the detector, [DetectorLayout::add_unit] ensures they fit in 16 bits.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{MossEvent, MossHit, MossPacket};
+
+/// Number of regions of a unit, side by side along the columns.
+const REGIONS: u32 = 4;
+
+/// The top half-units, with regions of 256 x 256 pixels at a pitch of 22.5 µm.
+pub const TOP_UNITS: std::ops::RangeInclusive<u8> = 1..=5;
+/// The bottom half-units, with regions of 320 x 320 pixels at a pitch of 18 µm.
+pub const BOTTOM_UNITS: std::ops::RangeInclusive<u8> = 6..=10;
+
+/// The region size and pitch in µm of a unit, see [TOP_UNITS] and [BOTTOM_UNITS].
+pub fn unit_pixel_size(unit_id: u8) -> (u16, f64) {
+    if BOTTOM_UNITS.contains(&unit_id) {
+        (320, 18.0)
+    } else {
+        (256, 22.5)
+    }
+}
+
+/// Where the hits of a unit are placed in the pixel matrix of a detector.
+#[derive(Debug, Clone, PartialEq)]
+struct UnitPlacement {
+    detector: String,
+    column_offset: u32,
+    row_offset: u32,
+    /// Rows and columns of a region of the unit.
+    region_size: u16,
+}
+
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq)]
+/// Maps the units of a MOSS sensor to detectors and the pixels of a unit to the pixel matrix of its detector.
+///
+/// Within a unit the regions are placed side by side, a hit is at column `region * region_size + column` and row `row`,
+/// offset by the position of the unit in its detector.
+pub struct DetectorLayout {
+    units: BTreeMap<u8, UnitPlacement>,
+    /// Pixel pitch in µm of each detector.
+    pitches: BTreeMap<String, f64>,
+}
+
+impl DetectorLayout {
+    /// Place the hits of `unit_id` at the given offset in the pixel matrix of `detector`.
+    ///
+    /// Fails if the pitch differs from other units of the detector, or the unit doesn't fit in a detector of 65536 x 65536 pixels.
+    pub fn add_unit(
+        &mut self,
+        unit_id: u8,
+        detector: &str,
+        column_offset: u32,
+        row_offset: u32,
+        region_size: u16,
+        pitch_um: f64,
+    ) -> Result<(), String> {
+        if region_size == 0 || pitch_um.is_nan() || pitch_um <= 0.0 {
+            return Err("region_size and pitch_um must be greater than 0".to_owned());
+        }
+        // The last column and row of the unit must fit in the 16 bits of the binary hit file
+        let region_size_px = u32::from(region_size);
+        let fits = |offset: u32, len: u32| {
+            offset
+                .checked_add(len - 1)
+                .is_some_and(|last| last <= u32::from(u16::MAX))
+        };
+        if !fits(column_offset, REGIONS * region_size_px) || !fits(row_offset, region_size_px) {
+            return Err(format!(
+                "Unit {unit_id} at column {column_offset} and row {row_offset} doesn't fit in a detector of 65536 x 65536 pixels"
+            ));
+        }
+        match self.pitches.get(detector) {
+            Some(&pitch) if pitch != pitch_um => {
+                return Err(format!(
+                    "Detector {detector} already has a pitch of {pitch} µm"
+                ))
+            }
+            _ => _ = self.pitches.insert(detector.to_owned(), pitch_um),
+        }
+        _ = self.units.insert(
+            unit_id,
+            UnitPlacement {
+                detector: detector.to_owned(),
+                column_offset,
+                row_offset,
+                region_size,
+            },
+        );
+        Ok(())
+    }
+
+    /// The detector and the column and row of a hit of `unit_id` in its pixel matrix.
+    ///
+    /// `None` if the unit is not in the layout or the hit is outside the region size.
+    pub fn position(&self, unit_id: u8, hit: &MossHit) -> Option<(&str, u32, u32)> {
+        let unit = self.units.get(&unit_id)?;
+        let region_size = u32::from(unit.region_size);
+        let (region, row, column) = (
+            u32::from(hit.region),
+            u32::from(hit.row),
+            u32::from(hit.column),
+        );
+        (region < REGIONS && row < region_size && column < region_size).then(|| {
+            (
+                unit.detector.as_str(),
+                unit.column_offset + region * region_size + column,
+                unit.row_offset + row,
+            )
+        })
+    }
+
+    /// The number of columns and rows of each detector, covering all its units.
+    pub fn detector_sizes(&self) -> BTreeMap<&str, (u32, u32)> {
+        let mut sizes = BTreeMap::new();
+        for unit in self.units.values() {
+            let size = sizes.entry(unit.detector.as_str()).or_insert((0, 0));
+            let region_size = u32::from(unit.region_size);
+            size.0 = size.0.max(unit.column_offset + REGIONS * region_size);
+            size.1 = size.1.max(unit.row_offset + region_size);
+        }
+        sizes
+    }
+}
+
+#[pymethods]
+impl DetectorLayout {
+    #[new]
+    fn py_new() -> Self {
+        Self::default()
+    }
+
+    /// A detector named `<prefix><unit ID>` for each unit, all 10 units by default.
+    ///
+    /// The region size and pitch default to those of each unit, see [unit_pixel_size].
+    #[staticmethod]
+    #[pyo3(signature = (units = None, region_size = None, pitch_um = None, prefix = "MOSS_unit"))]
+    pub fn per_unit(
+        units: Option<Vec<u8>>,
+        region_size: Option<u16>,
+        pitch_um: Option<f64>,
+        prefix: &str,
+    ) -> PyResult<Self> {
+        let mut layout = Self::default();
+        for unit_id in units.unwrap_or_else(|| crate::event_builder::ALL_UNITS.collect()) {
+            layout.py_add_unit(
+                unit_id,
+                &format!("{prefix}{unit_id}"),
+                0,
+                0,
+                region_size,
+                pitch_um,
+            )?;
+        }
+        Ok(layout)
+    }
+
+    /// A single detector with the `units` side by side along the columns in the given order, the top half-units by default.
+    ///
+    /// The region size and pitch default to those of each unit, see [unit_pixel_size].
+    /// All units must have the same pitch.
+    #[staticmethod]
+    #[pyo3(signature = (name = "MOSS", units = None, region_size = None, pitch_um = None))]
+    pub fn stitched(
+        name: &str,
+        units: Option<Vec<u8>>,
+        region_size: Option<u16>,
+        pitch_um: Option<f64>,
+    ) -> PyResult<Self> {
+        let mut layout = Self::default();
+        let mut column_offset = 0;
+        for unit_id in units.unwrap_or_else(|| TOP_UNITS.collect()) {
+            let unit_region_size = region_size.unwrap_or(unit_pixel_size(unit_id).0);
+            layout.py_add_unit(
+                unit_id,
+                name,
+                column_offset,
+                0,
+                Some(unit_region_size),
+                pitch_um,
+            )?;
+            column_offset += REGIONS * u32::from(unit_region_size);
+        }
+        Ok(layout)
+    }
+
+    /// Place the hits of a unit at the given offset in the pixel matrix of a detector, replacing an earlier placement of the unit.
+    ///
+    /// The region size and pitch default to those of the unit, see [unit_pixel_size].
+    /// All units of a detector must have the same pitch.
+    #[pyo3(name = "add_unit", signature = (unit_id, detector, column_offset = 0, row_offset = 0, region_size = None, pitch_um = None))]
+    fn py_add_unit(
+        &mut self,
+        unit_id: u8,
+        detector: &str,
+        column_offset: u32,
+        row_offset: u32,
+        region_size: Option<u16>,
+        pitch_um: Option<f64>,
+    ) -> PyResult<()> {
+        let (default_region_size, default_pitch_um) = unit_pixel_size(unit_id);
+        self.add_unit(
+            unit_id,
+            detector,
+            column_offset,
+            row_offset,
+            region_size.unwrap_or(default_region_size),
+            pitch_um.unwrap_or(default_pitch_um),
+        )
+        .map_err(PyValueError::new_err)
+    }
+
+    /// The names of the detectors in alphabetical order.
+    fn detectors(&self) -> Vec<String> {
+        self.pitches.keys().cloned().collect()
+    }
+
+    /// The detector, column and row of a hit of a unit, `None` if the unit is not in the layout or the hit is outside its region size.
+    #[pyo3(name = "position")]
+    fn py_position(&self, unit_id: u8, hit: &MossHit) -> Option<(String, u32, u32)> {
+        self.position(unit_id, hit)
+            .map(|(detector, column, row)| (detector.to_owned(), column, row))
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!(
+            "{}(units: {:?} detectors: {:?})",
+            class_name,
+            slf.borrow().units.keys().collect::<Vec<_>>(),
+            slf.borrow().detectors()
+        ))
+    }
+}
+
+/// Format of the exported hit files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HitFileFormat {
+    /// A line `<event> <column> <row>` per hit, given as `"text"`.
+    #[default]
+    Text,
+    /// A 12 byte little-endian record per hit, given as `"binary"`.
+    Binary,
+}
+
+impl<'source> FromPyObject<'source> for HitFileFormat {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "text" => Ok(Self::Text),
+            "binary" => Ok(Self::Binary),
+            other => Err(PyValueError::new_err(format!(
+                "Invalid hit file format {other:?}, expected \"text\" or \"binary\""
+            ))),
+        }
+    }
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Summary of an export to Corryvreckan hit files.
+pub struct CorryExportStats {
+    /// Number of events exported.
+    pub events: usize,
+    /// Number of hits written.
+    pub hits_written: usize,
+    /// Number of hits of units not in the layout or outside the region size of their unit.
+    pub hits_skipped: usize,
+    /// The event index, the hit files and the geometry file.
+    pub output_files: Vec<PathBuf>,
+}
+
+#[pymethods]
+impl CorryExportStats {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}(events: {} hits written: {} skipped: {} files: {})",
+            class_name,
+            slf.events,
+            slf.hits_written,
+            slf.hits_skipped,
+            slf.output_files.len()
+        ))
+    }
+}
+
+/// Name of the event index written next to the hit files.
+pub const EVENT_INDEX_FILE: &str = "events.txt";
+
+/// The number of an exported event: its trigger ID, otherwise the lowest sequence number of its packets.
+///
+/// Events of packets decoded without metadata are numbered by `idx`, their index in the exported events.
+pub fn event_number(event: &MossEvent, idx: usize) -> u64 {
+    if let Some(trigger_id) = event.trigger_id {
+        return u64::from(trigger_id);
+    }
+    event
+        .packets
+        .iter()
+        .filter_map(|packet| packet.metadata.as_ref().map(|metadata| metadata.sequence))
+        .min()
+        .map_or(idx as u64, |sequence| sequence as u64)
+}
+
+/// Writes the hits of `events` to a hit file per detector of the `layout`, the event index and a geometry file in `output_dir`.
+///
+/// `output_dir` is created if it doesn't exist, existing files are overwritten.
+pub fn export_events(
+    events: &[MossEvent],
+    output_dir: impl AsRef<Path>,
+    layout: &DetectorLayout,
+    format: HitFileFormat,
+) -> std::io::Result<CorryExportStats> {
+    let output_dir = output_dir.as_ref();
+    std::fs::create_dir_all(output_dir)?;
+    let mut stats = CorryExportStats::default();
+    let extension = match format {
+        HitFileFormat::Text => "txt",
+        HitFileFormat::Binary => "bin",
+    };
+    let index_path = output_dir.join(EVENT_INDEX_FILE);
+    if layout.pitches.contains_key("events") && format == HitFileFormat::Text {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The text hit file of detector events would overwrite the event index",
+        ));
+    }
+    let mut index = BufWriter::new(File::create(&index_path)?);
+    writeln!(
+        index,
+        "# Events exported by moss_decoder {}, including events without hits",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(index, "# event")?;
+    stats.output_files.push(index_path);
+    let mut writers = BTreeMap::new();
+    for detector in layout.pitches.keys() {
+        let path = output_dir.join(format!("{detector}.{extension}"));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        if format == HitFileFormat::Text {
+            writeln!(
+                writer,
+                "# Pixel hits of detector {detector} exported by moss_decoder {}",
+                env!("CARGO_PKG_VERSION")
+            )?;
+            writeln!(writer, "# event column row")?;
+        }
+        _ = writers.insert(detector.as_str(), writer);
+        stats.output_files.push(path);
+    }
+
+    for (idx, event) in events.iter().enumerate() {
+        let event_number = event_number(event, idx);
+        writeln!(index, "{event_number}")?;
+        for (unit_id, hit) in event.iter_hits() {
+            let Some((detector, column, row)) = layout.position(unit_id, &hit) else {
+                stats.hits_skipped += 1;
+                continue;
+            };
+            let writer = writers.get_mut(detector).expect("writer for each detector");
+            match format {
+                HitFileFormat::Text => writeln!(writer, "{event_number} {column} {row}")?,
+                HitFileFormat::Binary => {
+                    // Checked when the unit was added to the layout
+                    let column = u16::try_from(column).expect("column fits in 16 bits");
+                    let row = u16::try_from(row).expect("row fits in 16 bits");
+                    writer.write_all(&event_number.to_le_bytes())?;
+                    writer.write_all(&column.to_le_bytes())?;
+                    writer.write_all(&row.to_le_bytes())?;
+                }
+            }
+            stats.hits_written += 1;
+        }
+        stats.events += 1;
+    }
+    index.flush()?;
+    for writer in writers.values_mut() {
+        writer.flush()?;
+    }
+
+    let geometry_path = output_dir.join("geometry.conf");
+    write_geometry(&geometry_path, layout)?;
+    stats.output_files.push(geometry_path);
+    Ok(stats)
+}
+
+/// Writes a Corryvreckan geometry file with a section per detector of the `layout`.
+fn write_geometry(path: &Path, layout: &DetectorLayout) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "# Generated by moss_decoder {}, set the position, orientation and role of each detector for the setup",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    for (detector, (columns, rows)) in layout.detector_sizes() {
+        let pitch = layout.pitches[detector];
+        writeln!(writer)?;
+        writeln!(writer, "[{detector}]")?;
+        writeln!(writer, "type = \"moss\"")?;
+        writeln!(writer, "number_of_pixels = {columns}, {rows}")?;
+        writeln!(writer, "pixel_pitch = {pitch}um, {pitch}um")?;
+        writeln!(writer, "position = 0um, 0um, 0mm")?;
+        writeln!(writer, "orientation = 0deg, 0deg, 0deg")?;
+        writeln!(writer, "orientation_mode = \"xyz\"")?;
+        writeln!(writer, "role = \"dut\"")?;
+    }
+    writer.flush()
+}
+
+/// The events to export, either already built or the packets to build them from.
+#[derive(Debug, Clone, FromPyObject)]
+pub enum ExportInput {
+    /// Events from [build_events](crate::build_events).
+    #[pyo3(transparent, annotation = "list[MossEvent]")]
+    Events(Vec<MossEvent>),
+    /// Packets grouped into events like [build_events](crate::build_events) does.
+    #[pyo3(transparent, annotation = "list[MossPacket]")]
+    Packets(Vec<MossPacket>),
+}
+
+impl ExportInput {
+    /// The events, building them from the packets if needed.
+    pub fn into_events(self) -> Vec<MossEvent> {
+        match self {
+            Self::Events(events) => events,
+            Self::Packets(packets) => crate::event_builder::build_events(
+                packets,
+                crate::event_builder::ALL_UNITS.collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn hit(region: u8, row: u16, column: u16) -> MossHit {
+        MossHit {
+            region,
+            row,
+            column,
+        }
+    }
+
+    #[test]
+    fn test_stitched_layout_positions() {
+        let layout = DetectorLayout::stitched("MOSS", Some(vec![1, 2]), None, None).unwrap();
+        assert_eq!(layout.position(1, &hit(0, 3, 4)), Some(("MOSS", 4, 3)));
+        assert_eq!(layout.position(1, &hit(3, 3, 4)), Some(("MOSS", 772, 3)));
+        assert_eq!(layout.position(2, &hit(0, 3, 4)), Some(("MOSS", 1028, 3)));
+        assert_eq!(layout.position(2, &hit(0, 256, 4)), None);
+        assert_eq!(layout.position(3, &hit(0, 3, 4)), None);
+        assert_eq!(layout.detector_sizes()["MOSS"], (2048, 256));
+
+        let mut layout = layout;
+        assert!(layout.add_unit(3, "MOSS", 2048, 0, 256, 18.0).is_err());
+        // Columns and rows must fit in 16 bits
+        assert!(layout.add_unit(3, "MOSS", 64512, 0, 256, 22.5).is_ok());
+        assert!(layout.add_unit(3, "MOSS", 64513, 0, 256, 22.5).is_err());
+        assert!(layout.add_unit(3, "MOSS", u32::MAX, 0, 256, 22.5).is_err());
+        assert!(layout.add_unit(3, "MOSS", 0, 65280, 256, 22.5).is_ok());
+        assert!(layout.add_unit(3, "MOSS", 0, 65281, 256, 22.5).is_err());
+    }
+
+    #[test]
+    fn test_unit_pixel_size_defaults() {
+        let layout = DetectorLayout::per_unit(Some(vec![1, 6]), None, None, "MOSS_unit").unwrap();
+        assert_eq!(layout.detector_sizes()["MOSS_unit1"], (1024, 256));
+        assert_eq!(layout.detector_sizes()["MOSS_unit6"], (1280, 320));
+        assert_eq!(layout.pitches["MOSS_unit6"], 18.0);
+        assert_eq!(
+            layout.position(6, &hit(1, 300, 310)),
+            Some(("MOSS_unit6", 630, 300))
+        );
+
+        let bottom =
+            DetectorLayout::stitched("bottom", Some(BOTTOM_UNITS.collect()), None, None).unwrap();
+        assert_eq!(bottom.detector_sizes()["bottom"], (6400, 320));
+        // Mixing top and bottom half-units in a detector mixes pitches
+        assert!(DetectorLayout::stitched("MOSS", Some(vec![5, 6]), None, None).is_err());
+    }
+
+    #[test]
+    fn test_export_text_and_binary() {
+        let mut packet = MossPacket::new(1);
+        packet.hits = vec![hit(0, 1, 2), hit(1, 1, 2)];
+        let mut other_unit = MossPacket::new(7);
+        other_unit.hits = vec![hit(0, 5, 5)];
+        // Unit 1 after unit 7 starts a new event
+        let events = ExportInput::Packets(vec![packet.clone(), other_unit, packet]).into_events();
+        let layout = DetectorLayout::per_unit(Some(vec![1]), None, None, "MOSS_unit").unwrap();
+        let dir = std::env::temp_dir().join(format!("moss_corry_export_{}", std::process::id()));
+
+        let stats = export_events(&events, &dir, &layout, HitFileFormat::Text).unwrap();
+        assert_eq!(stats.events, 2);
+        assert_eq!(stats.hits_written, 4);
+        assert_eq!(stats.hits_skipped, 1);
+        let text = std::fs::read_to_string(dir.join("MOSS_unit1.txt")).unwrap();
+        let lines: Vec<&str> = text.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(lines, ["0 2 1", "0 258 1", "1 2 1", "1 258 1"]);
+        assert_eq!(stats.output_files[0], dir.join(EVENT_INDEX_FILE));
+        let geometry = std::fs::read_to_string(dir.join("geometry.conf")).unwrap();
+        assert!(geometry.contains("[MOSS_unit1]\ntype = \"moss\"\nnumber_of_pixels = 1024, 256\n"));
+
+        _ = export_events(&events, &dir, &layout, HitFileFormat::Binary).unwrap();
+        let binary = std::fs::read(dir.join("MOSS_unit1.bin")).unwrap();
+        assert_eq!(binary.len(), 4 * 12);
+        assert_eq!(binary[12..24], [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 0]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_numbers_events_by_sequence() {
+        // An event per packet of unit 1, packet 0 and 2 of the stream were skipped and packet 3 has no hits
+        let events: Vec<MossEvent> = [(1, 1), (3, 0), (4, 2)]
+            .into_iter()
+            .flat_map(|(sequence, hits)| {
+                let mut packet = MossPacket::new(1);
+                packet.hits = vec![hit(0, 1, 2); hits];
+                packet.metadata = Some(Box::new(crate::PacketMetadata::new(0, 0, sequence)));
+                crate::event_builder::build_events([packet], vec![1])
+            })
+            .collect();
+        let layout = DetectorLayout::per_unit(Some(vec![1]), None, None, "MOSS_unit").unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("moss_corry_export_seq_{}", std::process::id()));
+
+        let stats = export_events(&events, &dir, &layout, HitFileFormat::Text).unwrap();
+        assert_eq!(stats.events, 3);
+        let read_lines = |name: &str| -> Vec<String> {
+            std::fs::read_to_string(dir.join(name))
+                .unwrap()
+                .lines()
+                .filter(|l| !l.starts_with('#'))
+                .map(str::to_owned)
+                .collect()
+        };
+        assert_eq!(read_lines(EVENT_INDEX_FILE), ["1", "3", "4"]);
+        assert_eq!(read_lines("MOSS_unit1.txt"), ["1 2 1", "4 2 1", "4 2 1"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     moss_protocol::MossWord,
     parse_error::{ParseError, ParseErrorKind},
-    MossHit, MossPacket,
+    FramingConfig, MossHit, MossPacket,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -57,16 +57,21 @@ type LastTrailerIdx = usize;
 type DebugError = (ParseError, InvalidWords);
 
 /// Decodes a single MOSS event into a [MossPacket] and the index of the trailer byte (Rust only)
+///
+/// If the events are padded the index of the last padding byte is returned instead of the trailer index.
 #[inline]
 pub(crate) fn debug_decode_event(
     bytes: &[u8],
+    framing: &FramingConfig,
 ) -> Result<(MossPacket, LastTrailerIdx, InvalidWords), DebugError> {
     const INVALID_NO_HEADER_SEEN: u8 = 0xFF;
     let mut moss_packet = MossPacket {
         unit_id: INVALID_NO_HEADER_SEEN, // placeholder
         hits: Vec::new(),
+        metadata: None,
     };
 
+    let mut header_idx = 0;
     let mut trailer_idx = 0;
     let mut current_region: u8 = 0xff; // placeholder
 
@@ -74,6 +79,9 @@ pub(crate) fn debug_decode_event(
     let mut invalid_words: Vec<InvalidWordInfo> = Vec::new();
 
     for (i, byte) in bytes.iter().enumerate() {
+        if !is_moss_packet && framing.is_filler(*byte) {
+            continue;
+        }
         match MossWord::from_byte(*byte) {
             MossWord::Idle => {
                 if !is_moss_packet {
@@ -95,12 +103,15 @@ pub(crate) fn debug_decode_event(
                     )));
                 } else {
                     is_moss_packet = true;
+                    header_idx = i;
                     moss_packet.unit_id = *byte & 0x0F
                 }
             }
             MossWord::UnitFrameTrailer => {
                 if is_moss_packet {
-                    trailer_idx = i;
+                    // Skip the padding, even if the buffer ends before the padding does
+                    trailer_idx =
+                        (bytes.len() - 1).min(i + framing.padding_after(i - header_idx + 1));
                     break;
                 } else {
                     invalid_words.push(record_protocol_error(InvalidWordInfo::new(
@@ -140,11 +151,12 @@ pub(crate) fn debug_decode_event(
                 }
             }
             MossWord::Data1 => {
-                if is_moss_packet {
+                // A DATA_1 without a preceding DATA_0 has no hit to add to
+                if let Some(hit) = moss_packet.hits.last_mut().filter(|_| is_moss_packet) {
                     // row position [2:0]
-                    moss_packet.hits.last_mut().unwrap().row |= ((*byte & 0x38) >> 3) as u16;
+                    hit.row |= ((*byte & 0x38) >> 3) as u16;
                     // col position [8:6]
-                    moss_packet.hits.last_mut().unwrap().column = ((*byte & 0x07) as u16) << 6;
+                    hit.column = ((*byte & 0x07) as u16) << 6;
                 } else {
                     invalid_words.push(record_protocol_error(InvalidWordInfo::new(
                         *byte,
@@ -155,8 +167,8 @@ pub(crate) fn debug_decode_event(
                 }
             }
             MossWord::Data2 => {
-                if is_moss_packet {
-                    moss_packet.hits.last_mut().unwrap().column |= (*byte & 0x3F) as u16;
+                if let Some(hit) = moss_packet.hits.last_mut().filter(|_| is_moss_packet) {
+                    hit.column |= (*byte & 0x3F) as u16;
                 } else {
                     invalid_words.push(record_protocol_error(InvalidWordInfo::new(
                         *byte,
@@ -167,15 +179,14 @@ pub(crate) fn debug_decode_event(
                 }
                 // col position [5:0]
             }
+            // Filler bytes outside of an event are skipped before matching
             MossWord::Delimiter => {
-                if is_moss_packet {
-                    invalid_words.push(record_protocol_error(InvalidWordInfo::new(
-                        *byte,
-                        is_moss_packet,
-                        current_region,
-                        i,
-                    )));
-                }
+                invalid_words.push(record_protocol_error(InvalidWordInfo::new(
+                    *byte,
+                    is_moss_packet,
+                    current_region,
+                    i,
+                )));
             }
             MossWord::ProtocolError => {
                 invalid_words.push(record_protocol_error(InvalidWordInfo::new(
@@ -229,7 +240,7 @@ mod tests {
         pyo3::prepare_freethreaded_python();
         let event_data_packet = fake_event_simple();
 
-        let res = debug_decode_event(&event_data_packet);
+        let res = debug_decode_event(&event_data_packet, &FramingConfig::default());
         assert!(res.is_ok());
         let (moss_packet, trailer_idx, invalid_words) = res.unwrap();
         assert_eq!(moss_packet.unit_id, 1);
@@ -242,7 +253,7 @@ mod tests {
     fn test_debug_decode_protocol_error() {
         let event_data_packet = fake_event_protocol_error_fb_in_idle();
 
-        let res = debug_decode_event(&event_data_packet);
+        let res = debug_decode_event(&event_data_packet, &FramingConfig::default());
         assert!(res.is_ok());
 
         let (moss_packet, trailer_idx, invalid_words) = res.unwrap();
@@ -269,9 +280,10 @@ mod tests {
         let mut invalid_words = Vec::new();
         let mut last_trailer_idx = 0;
 
-        while let Ok((new_moss_packet, trailer_idx, new_invalid_words)) =
-            debug_decode_event(&data_two_packets[last_trailer_idx..])
-        {
+        while let Ok((new_moss_packet, trailer_idx, new_invalid_words)) = debug_decode_event(
+            &data_two_packets[last_trailer_idx..],
+            &FramingConfig::default(),
+        ) {
             new_invalid_words.into_iter().for_each(|mut invalid_word| {
                 invalid_word.set_index_offset(last_trailer_idx);
                 invalid_words.push(invalid_word);
@@ -309,9 +321,10 @@ mod tests {
         let mut invalid_words = Vec::new();
         let mut last_trailer_idx = 0;
 
-        while let Ok((new_moss_packet, trailer_idx, new_invalid_words)) =
-            debug_decode_event(&data_two_packets[last_trailer_idx..])
-        {
+        while let Ok((new_moss_packet, trailer_idx, new_invalid_words)) = debug_decode_event(
+            &data_two_packets[last_trailer_idx..],
+            &FramingConfig::default(),
+        ) {
             new_invalid_words.into_iter().for_each(|mut invalid_word| {
                 invalid_word.set_index_offset(last_trailer_idx);
                 invalid_words.push(invalid_word);
@@ -343,7 +356,7 @@ mod tests {
         let mut event_data_packet = fake_event_simple();
         event_data_packet.insert(1, 0xFB);
 
-        let res = debug_decode_event(&event_data_packet);
+        let res = debug_decode_event(&event_data_packet, &FramingConfig::default());
         assert!(res.is_ok());
         let (moss_packet, trailer_idx, invalid_words) = res.unwrap();
 
@@ -358,4 +371,20 @@ mod tests {
         assert!(invalid_words[0].in_packet);
         assert!(invalid_words[0].to_error_msg().contains("region unknown"));
     }
+
+    #[test]
+    fn test_debug_decode_event_data_without_data0() {
+        // DATA_1 and DATA_2 without a preceding DATA_0 have no hit to add to
+        let event = [0xD1, REGION_HEADER_0, 0x50, 0x88, UNIT_FRAME_TRAILER];
+
+        let (moss_packet, trailer_idx, invalid_words) =
+            debug_decode_event(&event, &FramingConfig::default()).unwrap();
+
+        assert!(moss_packet.hits.is_empty());
+        assert_eq!(trailer_idx, 4);
+        assert_eq!(
+            invalid_words.iter().map(|w| w.index).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
 }
@@ -1,349 +1,292 @@
-//! Contains an FSM implementation of the MOSS data readout protocol
-#![allow(non_camel_case_types)]
+//! Contains a table-driven FSM implementation of the MOSS data readout protocol
+//!
+//! Every byte is mapped to a [ByteClass] with a lookup table, and the next state is looked up in a
+//! transition table indexed by the current [State] and the [ByteClass] of the byte.
+//! Supporting a new word type means adding a byte class and a column to the transition table.
 
 use crate::moss_protocol::MossWord;
 use crate::parse_error::ParseError;
 use crate::parse_error::ParseErrorKind;
 use crate::MossHit;
 
-sm::sm! {
+/// The states of the hit decoder FSM, used as row index in the [TRANSITIONS] table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum State {
+    UnitFrameHeader,
+    RegionHeader0,
+    RegionHeader1,
+    RegionHeader2,
+    RegionHeader3,
+    Data0,
+    Data1,
+    Data2,
+    Idle,
+    /// Unit Frame Trailer seen, the event is complete
+    FrameTrailer,
+    /// Invalid transition
+    Error,
+}
 
-    MossDataFSM {
-        InitialStates { _UNIT_FRAME_HEADER_ }
+/// Number of states that consume a byte, i.e. all but the terminal states
+const STATE_CNT: usize = State::FrameTrailer as usize;
+
+/// The classification of a byte, used as column index in the [TRANSITIONS] table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum ByteClass {
+    RegionHeader0,
+    RegionHeader1,
+    RegionHeader2,
+    RegionHeader3,
+    Data0,
+    Data1,
+    Data2,
+    Idle,
+    UnitFrameTrailer,
+    Invalid,
+}
 
-        _Data {
-            REGION_HEADER0_ => DATA0_
-            REGION_HEADER1_ => DATA0_
-            REGION_HEADER2_ => DATA0_
-            REGION_HEADER3_ => DATA0_
-            DATA2_ => DATA0_
-            IDLE_ => DATA0_
-            DATA0_ => DATA1_
-            DATA1_ => DATA2_
-        }
+const BYTE_CLASS_CNT: usize = ByteClass::Invalid as usize + 1;
+
+const fn classify(b: u8) -> ByteClass {
+    match b {
+        REGION_HEADER0 => ByteClass::RegionHeader0,
+        REGION_HEADER1 => ByteClass::RegionHeader1,
+        REGION_HEADER2 => ByteClass::RegionHeader2,
+        REGION_HEADER3 => ByteClass::RegionHeader3,
+        MossWord::IDLE => ByteClass::Idle,
+        MossWord::UNIT_FRAME_TRAILER => ByteClass::UnitFrameTrailer,
+        // Same bounds as `MossWord::DATA_{0-2}_RANGE`
+        0..=0b0010_1000 => ByteClass::Data0,
+        0b0100_0000..=0b0111_1101 => ByteClass::Data1,
+        0b1000_0000..=0b1011_1111 => ByteClass::Data2,
+        _ => ByteClass::Invalid,
+    }
+}
 
-        _Idle {
-            DATA2_ => IDLE_
-            IDLE_ => IDLE_
-        }
+/// Maps every byte value to its [ByteClass].
+const BYTE_CLASSES: [ByteClass; 256] = {
+    let mut classes = [ByteClass::Invalid; 256];
+    let mut b = 0;
+    while b < 256 {
+        classes[b] = classify(b as u8);
+        b += 1;
+    }
+    classes
+};
+
+/// Next state indexed by `[current state][byte class]`
+#[rustfmt::skip]
+const TRANSITIONS: [[State; BYTE_CLASS_CNT]; STATE_CNT] = {
+    use State::*;
+    const E: State = Error;
+    const RH0: State = RegionHeader0;
+    const RH1: State = RegionHeader1;
+    const RH2: State = RegionHeader2;
+    const RH3: State = RegionHeader3;
+    const END: State = FrameTrailer;
+    [
+        //                       RH0  RH1  RH2  RH3  DATA0  DATA1  DATA2  IDLE  TRAILER  INVALID
+        /* UNIT_FRAME_HEADER */ [RH0, RH1, RH2, RH3, E,     E,     E,     E,    END,     E],
+        /* REGION_HEADER0 */    [E,   RH1, RH2, RH3, Data0, E,     E,     E,    END,     E],
+        /* REGION_HEADER1 */    [E,   E,   RH2, RH3, Data0, E,     E,     E,    END,     E],
+        /* REGION_HEADER2 */    [E,   E,   E,   RH3, Data0, E,     E,     E,    END,     E],
+        /* REGION_HEADER3 */    [E,   E,   E,   E,   Data0, E,     E,     E,    END,     E],
+        /* DATA0 */             [E,   E,   E,   E,   E,     Data1, E,     E,    E,       E],
+        /* DATA1 */             [E,   E,   E,   E,   E,     E,     Data2, E,    E,       E],
+        /* DATA2 */             [E,   RH1, RH2, RH3, Data0, E,     E,     Idle, END,     E],
+        /* IDLE */              [E,   RH1, RH2, RH3, Data0, E,     E,     Idle, END,     E],
+    ]
+};
+
+/// Error message describing the expected words, indexed by the state the error occurred in.
+const ERROR_MESSAGES: [&str; STATE_CNT] = [
+    /* UNIT_FRAME_HEADER */ "Expected REGION_HEADER_{0-3}/UNIT_FRAME_TRAILER",
+    /* REGION_HEADER0 */ "Expected REGION_HEADER_{1-3}/DATA_0/UNIT_FRAME_TRAILER",
+    /* REGION_HEADER1 */ "Expected REGION_HEADER_{2-3}/DATA_0/UNIT_FRAME_TRAILER",
+    /* REGION_HEADER2 */ "Expected REGION_HEADER_3/DATA_0/UNIT_FRAME_TRAILER",
+    /* REGION_HEADER3 */ "Expected UNIT_FRAME_TRAILER/DATA_0/UNIT_FRAME_TRAILER",
+    /* DATA0 */ "Expected DATA_1",
+    /* DATA1 */ "Expected DATA_2",
+    /* DATA2 */ "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
+    /* IDLE */ "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
+];
 
-        _RegionHeader0 {
-            _UNIT_FRAME_HEADER_ => REGION_HEADER0_
-        }
+const REGION_HEADER0: u8 = 0xC0;
+const REGION_HEADER1: u8 = 0xC1;
+const REGION_HEADER2: u8 = 0xC2;
+const REGION_HEADER3: u8 = 0xC3;
 
-        _RegionHeader1 {
-            _UNIT_FRAME_HEADER_ => REGION_HEADER1_
-            REGION_HEADER0_ => REGION_HEADER1_
-            DATA2_ => REGION_HEADER1_
-            IDLE_ => REGION_HEADER1_
-        }
+/// Destination of the hits decoded by the FSM.
+///
+/// Allows decoding directly into different storage layouts without intermediate allocations.
+pub(crate) trait HitSink {
+    /// Add a fully decoded hit.
+    fn push_hit(&mut self, hit: MossHit);
+}
 
-        _RegionHeader2 {
-            _UNIT_FRAME_HEADER_ => REGION_HEADER2_
-            REGION_HEADER0_ => REGION_HEADER2_
-            REGION_HEADER1_ => REGION_HEADER2_
-            DATA2_ => REGION_HEADER2_
-            IDLE_ => REGION_HEADER2_
-        }
+impl HitSink for Vec<MossHit> {
+    #[inline]
+    fn push_hit(&mut self, hit: MossHit) {
+        self.push(hit);
+    }
+}
 
-        _RegionHeader3 {
-            _UNIT_FRAME_HEADER_ => REGION_HEADER3_
-            REGION_HEADER0_ => REGION_HEADER3_
-            REGION_HEADER1_ => REGION_HEADER3_
-            REGION_HEADER2_ => REGION_HEADER3_
-            DATA2_ => REGION_HEADER3_
-            IDLE_ => REGION_HEADER3_
-        }
+/// The result of feeding one byte to an [FsmStepper].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// The byte is valid in the current state.
+    Valid,
+    /// The byte completed a hit.
+    Hit(MossHit),
+    /// The byte is the Unit Frame Trailer that ends the event.
+    End,
+    /// The byte is invalid in the current state, see [FsmStepper::expected].
+    Error,
+}
 
-        _FrameTrailer {
-            _UNIT_FRAME_HEADER_ => FRAME_TRAILER_
-            REGION_HEADER0_ => FRAME_TRAILER_
-            REGION_HEADER1_ => FRAME_TRAILER_
-            REGION_HEADER2_ => FRAME_TRAILER_
-            REGION_HEADER3_ => FRAME_TRAILER_
-            DATA2_ => FRAME_TRAILER_
-            IDLE_ => FRAME_TRAILER_
+/// Steps through the bytes of an event one at a time with the [TRANSITIONS] of [extract_hits_into].
+///
+/// Used by tools that annotate each byte, the decoders use [extract_hits_into].
+#[derive(Debug, Clone)]
+pub(crate) struct FsmStepper {
+    state: State,
+    region: Option<u8>,
+    hit: MossHit,
+}
+
+impl FsmStepper {
+    /// Create a stepper positioned after a Unit Frame Header.
+    pub(crate) fn new() -> Self {
+        Self {
+            state: State::UnitFrameHeader,
+            region: None,
+            hit: MossHit::default(),
         }
     }
-}
 
-use MossDataFSM::Variant::*;
-use MossDataFSM::*;
+    /// The region of the last Region Header, if any.
+    pub(crate) fn region(&self) -> Option<u8> {
+        self.region
+    }
 
-const REGION_HEADER0: u8 = 0xC0;
-const REGION_HEADER1: u8 = 0xC1;
-const REGION_HEADER2: u8 = 0xC2;
-const REGION_HEADER3: u8 = 0xC3;
+    /// Describes the words that are valid in the current state.
+    pub(crate) fn expected(&self) -> &'static str {
+        ERROR_MESSAGES[self.state as usize]
+    }
+
+    /// Whether the bytes of a hit have been seen but not its DATA_2 byte.
+    pub(crate) fn in_hit(&self) -> bool {
+        matches!(self.state, State::Data0 | State::Data1)
+    }
+
+    /// Feeds the next byte of the event, on an error the byte is ignored and the state is unchanged.
+    pub(crate) fn step(&mut self, b: u8) -> Step {
+        let next_state = TRANSITIONS[self.state as usize][BYTE_CLASSES[b as usize] as usize];
+        let step = match next_state {
+            State::Error => return Step::Error,
+            State::FrameTrailer => Step::End,
+            State::RegionHeader0
+            | State::RegionHeader1
+            | State::RegionHeader2
+            | State::RegionHeader3 => {
+                self.region = Some(b & 0x3);
+                Step::Valid
+            }
+            State::Data0 => {
+                self.hit.region = self.region.unwrap_or(0xff);
+                self.hit.row = ((b & 0x3F) as u16) << 3;
+                Step::Valid
+            }
+            State::Data1 => {
+                self.hit.row |= ((b & 0x38) >> 3) as u16;
+                self.hit.column = ((b & 0x07) as u16) << 6;
+                Step::Valid
+            }
+            State::Data2 => {
+                self.hit.column |= (b & 0x3F) as u16;
+                Step::Hit(self.hit)
+            }
+            State::Idle | State::UnitFrameHeader => Step::Valid,
+        };
+        self.state = next_state;
+        step
+    }
+}
 
 /// Take an iterator that should be advanced to the position after a unit frame header.
 /// Advances the iterator and decodes any observed hits until a Unit Frame Trailer is encountered at which point the iteration stops.
 /// Returns all the decoded [MossHit]s if any.
+#[cfg(test)]
 #[inline]
 pub(crate) fn extract_hits<'a>(
-    bytes: &mut (impl Iterator<Item = &'a u8>
-              + std::iter::DoubleEndedIterator
-              + std::iter::ExactSizeIterator),
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
 ) -> Result<Vec<MossHit>, ParseError> {
-    let total_bytes = bytes.len();
-    let mut sm = MossDataFSM::Machine::new(_UNIT_FRAME_HEADER_).as_enum();
     let mut hits = Vec::<MossHit>::new();
+    extract_hits_into(bytes, &mut hits)?;
+    if hits.is_empty() {
+        Ok(Vec::with_capacity(0))
+    } else {
+        Ok(hits)
+    }
+}
+
+/// Same as [extract_hits] but adds the hits to the given [HitSink].
+///
+/// On error, the hits of the event that were decoded before the error are already added to the sink.
+#[inline]
+pub(crate) fn extract_hits_into<'a>(
+    bytes: &mut (impl std::iter::DoubleEndedIterator<Item = &'a u8> + std::iter::ExactSizeIterator),
+    hits: &mut impl HitSink,
+) -> Result<(), ParseError> {
+    let total_bytes = bytes.len();
+    let mut state = State::UnitFrameHeader;
 
-    let mut is_trailer_seen = false;
     let mut current_region = 0xff;
+    // The hit currently being decoded, only pushed to `hits` once DATA_2 is seen
+    let mut hit = MossHit::default();
 
     for (i, b) in bytes.enumerate() {
-        sm = match sm {
-            Initial_UNIT_FRAME_HEADER_(st) => match *b {
-                REGION_HEADER0 => {
-                    current_region = 0;
-                    st.transition(_RegionHeader0).as_enum()
-                }
-                REGION_HEADER1 => {
-                    current_region = 1;
-                    st.transition(_RegionHeader1).as_enum()
-                }
-                REGION_HEADER2 => {
-                    current_region = 2;
-                    st.transition(_RegionHeader2).as_enum()
-                }
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_{0-3}/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            REGION_HEADER0_By_RegionHeader0(st) => match *b {
-                REGION_HEADER1 => {
-                    current_region = 1;
-                    st.transition(_RegionHeader1).as_enum()
-                }
-                REGION_HEADER2 => {
-                    current_region = 2;
-                    st.transition(_RegionHeader2).as_enum()
-                }
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_{1-3}/DATA_0/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            DATA0_By_Data(st) => {
-                if MossWord::DATA_1_RANGE.contains(b) {
-                    add_data1(&mut hits, *b);
-                    st.transition(_Data).as_enum()
-                } else {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected DATA_1",
-                        i,
-                    ));
-                }
+        let class = BYTE_CLASSES[*b as usize];
+        let next_state = TRANSITIONS[state as usize][class as usize];
+
+        match next_state {
+            State::Error => {
+                return Err(ParseError::new(
+                    ParseErrorKind::ProtocolError,
+                    ERROR_MESSAGES[state as usize],
+                    i,
+                ))
             }
-            DATA1_By_Data(st) => {
-                if MossWord::DATA_2_RANGE.contains(b) {
-                    add_data2(&mut hits, *b);
-                    st.transition(_Data).as_enum()
-                } else {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected DATA_2",
-                        i,
-                    ));
-                }
+            State::FrameTrailer => return Ok(()),
+            State::RegionHeader0
+            | State::RegionHeader1
+            | State::RegionHeader2
+            | State::RegionHeader3 => current_region = *b & 0x3,
+            State::Data0 => {
+                hit.region = current_region; // region id
+                hit.row = ((*b & 0x3F) as u16) << 3; // row position [8:3]
             }
-            DATA2_By_Data(st) => match *b {
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                MossWord::IDLE => st.transition(_Idle).as_enum(),
-                REGION_HEADER1 => {
-                    current_region = 1;
-                    st.transition(_RegionHeader1).as_enum()
-                }
-                REGION_HEADER2 => {
-                    current_region = 2;
-                    st.transition(_RegionHeader2).as_enum()
-                }
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            IDLE_By_Idle(st) => match *b {
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                REGION_HEADER1 => {
-                    current_region = 1;
-                    st.transition(_RegionHeader1).as_enum()
-                }
-                REGION_HEADER2 => {
-                    current_region = 2;
-                    st.transition(_RegionHeader2).as_enum()
-                }
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                MossWord::IDLE => st.transition(_Idle).as_enum(),
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_{1-3}/DATA_0/IDLE/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            REGION_HEADER1_By_RegionHeader1(st) => match *b {
-                REGION_HEADER2 => {
-                    current_region = 2;
-                    st.transition(_RegionHeader2).as_enum()
-                }
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_{2-3}/DATA_0/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            REGION_HEADER2_By_RegionHeader2(st) => match *b {
-                REGION_HEADER3 => {
-                    current_region = 3;
-                    st.transition(_RegionHeader3).as_enum()
-                }
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected REGION_HEADER_3/DATA_0/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            REGION_HEADER3_By_RegionHeader3(st) => match *b {
-                b if MossWord::DATA_0_RANGE.contains(&b) => {
-                    add_data0(&mut hits, b, current_region);
-                    st.transition(_Data).as_enum()
-                }
-                MossWord::UNIT_FRAME_TRAILER => {
-                    is_trailer_seen = true;
-                    break;
-                }
-                _ => {
-                    return Err(ParseError::new(
-                        ParseErrorKind::ProtocolError,
-                        "Expected UNIT_FRAME_TRAILER/DATA_0/UNIT_FRAME_TRAILER",
-                        i,
-                    ))
-                }
-            },
-            FRAME_TRAILER_By_FrameTrailer(_) => {
-                unreachable!("State machine should have already been used at this point")
+            State::Data1 => {
+                hit.row |= ((*b & 0x38) >> 3) as u16; // row position [2:0]
+                hit.column = ((*b & 0x07) as u16) << 6; // col position [8:6]
             }
-        };
-    }
-
-    if is_trailer_seen {
-        if hits.is_empty() {
-            Ok(Vec::with_capacity(0))
-        } else {
-            Ok(hits)
+            State::Data2 => {
+                hit.column |= (*b & 0x3F) as u16; // col position [5:0]
+                hits.push_hit(hit);
+            }
+            State::Idle | State::UnitFrameHeader => (),
         }
-    } else {
-        Err(ParseError::new(
-            ParseErrorKind::EndOfBufferNoTrailer,
-            "Reached end with no UNIT_FRAME_TRAILER",
-            total_bytes - 1,
-        ))
+        state = next_state;
     }
-}
-
-#[inline]
-fn add_data0(moss_hits: &mut Vec<MossHit>, data0: u8, region: u8) {
-    moss_hits.push(MossHit {
-        region,                            // region id
-        row: ((data0 & 0x3F) as u16) << 3, // row position [8:3]
-        column: 0,                         // placeholder
-    })
-}
 
-#[inline]
-fn add_data1(moss_hits: &mut [MossHit], data1: u8) {
-    moss_hits
-        .last_mut()
-        .unwrap() // row position [2:0]
-        .row |= ((data1 & 0x38) >> 3) as u16;
-
-    moss_hits
-        .last_mut()
-        .unwrap() // col position [8:6]
-        .column = ((data1 & 0x07) as u16) << 6;
-}
-
-#[inline]
-fn add_data2(moss_hits: &mut [MossHit], data2: u8) {
-    moss_hits.last_mut().unwrap().column |= (data2 & 0x3F) as u16;
+    Err(ParseError::new(
+        ParseErrorKind::EndOfBufferNoTrailer,
+        "Reached end with no UNIT_FRAME_TRAILER",
+        // No bytes at all if the header is the last byte
+        total_bytes.saturating_sub(1),
+    ))
 }
 
 #[cfg(test)]
@@ -425,7 +368,7 @@ mod tests {
     #[test]
     fn test_extract_packet() {
         let packet = fake_event_simple();
-        let p = extract_packet_from_buf(&packet, None);
+        let p = extract_packet_from_buf(&packet, None, &crate::FramingConfig::default());
         println!("{p:?}");
         assert!(p.is_ok());
         let (p, trailer_idx) = p.unwrap();
@@ -433,11 +376,82 @@ mod tests {
         assert_eq!(trailer_idx, 18);
     }
 
+    #[test]
+    fn test_byte_classes_match_moss_words() {
+        for b in 0..=u8::MAX {
+            let class = BYTE_CLASSES[b as usize];
+            assert_eq!(
+                class == ByteClass::Data0,
+                MossWord::DATA_0_RANGE.contains(&b),
+                "{b:#X}"
+            );
+            assert_eq!(
+                class == ByteClass::Data1,
+                MossWord::DATA_1_RANGE.contains(&b),
+                "{b:#X}"
+            );
+            assert_eq!(
+                class == ByteClass::Data2,
+                MossWord::DATA_2_RANGE.contains(&b),
+                "{b:#X}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_stepper_matches_extract_hits() {
+        let event = fake_event_simple();
+        let mut stepper = FsmStepper::new();
+        let mut hits = Vec::new();
+        for &b in &event[1..event.len() - 1] {
+            match stepper.step(b) {
+                Step::Hit(hit) => hits.push(hit),
+                step => assert_eq!(step, Step::Valid),
+            }
+        }
+        assert_eq!(stepper.step(MossWord::UNIT_FRAME_TRAILER), Step::End);
+        assert_eq!(hits, extract_hits(&mut event[1..].iter()).unwrap());
+
+        // Errors leave the state unchanged
+        let mut stepper = FsmStepper::new();
+        assert_eq!(stepper.step(REGION_HEADER1), Step::Valid);
+        assert_eq!(stepper.step(REGION_HEADER0), Step::Error);
+        assert_eq!(stepper.step(MossWord::IDLE), Step::Error);
+        assert_eq!(
+            stepper.expected(),
+            ERROR_MESSAGES[State::RegionHeader1 as usize]
+        );
+        assert_eq!(stepper.region(), Some(1));
+    }
+
+    #[test]
+    fn test_error_message_and_index() {
+        let packet = fake_event_protocol_error();
+        let mut byte_iter = packet.iter().skip(1);
+
+        let err = extract_hits(&mut byte_iter).unwrap_err();
+
+        assert_eq!(err.kind(), ParseErrorKind::ProtocolError);
+        assert_eq!(err.message(), "Expected DATA_1");
+        assert_eq!(packet[err.err_index() + 1], 0xF0);
+    }
+
+    #[test]
+    fn test_no_trailer() {
+        let mut packet = fake_event_simple();
+        _ = packet.pop();
+        let mut byte_iter = packet.iter().skip(1);
+
+        let err = extract_hits(&mut byte_iter).unwrap_err();
+
+        assert_eq!(err.kind(), ParseErrorKind::EndOfBufferNoTrailer);
+    }
+
     #[test]
     fn test_protocol_error() {
         let packet = fake_event_protocol_error();
 
-        if let Err(e) = extract_packet_from_buf(&packet, None) {
+        if let Err(e) = extract_packet_from_buf(&packet, None, &crate::FramingConfig::default()) {
             println!("{e:?}");
         } else {
             panic!("Expected error, got OK")
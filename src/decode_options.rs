@@ -0,0 +1,756 @@
+//! Options accepted by the unified decoding entry points [decode](crate::decode), [decode_file](crate::decode_file) and [decode_stream](crate::decode_stream).
+//!
+//! All entry points run the same chunked decoding loop, so skipping, taking, error handling, masking and the output format
+//! behave the same regardless of where the data comes from.
+use pyo3::exceptions::{PyAssertionError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::collections::HashSet;
+use std::fmt::{write, Display};
+use std::io::Read;
+use std::path::Path;
+
+use crate::parse_error::ParseErrorKind;
+use crate::parse_util::{find_header, find_resume_idx, find_trailer_n_idx};
+use crate::py_reader::into_py_err;
+use crate::sink::{PacketSink, SinkControl};
+use crate::{FramingConfig, MossEventBatch, MossHit, MossPacket, PacketMetadata};
+
+/// What to do when an event fails to decode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Raise an exception, given as `"raise"`.
+    #[default]
+    Raise,
+    /// Record the error in [DecodeResult::errors] and resume at the next Unit Frame Header, given as `"skip"`.
+    Skip,
+}
+
+impl ErrorPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Raise => "raise",
+            Self::Skip => "skip",
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for ErrorPolicy {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "raise" => Ok(Self::Raise),
+            "skip" => Ok(Self::Skip),
+            other => Err(PyValueError::new_err(format!(
+                "Invalid error policy {other:?}, expected \"raise\" or \"skip\""
+            ))),
+        }
+    }
+}
+
+impl IntoPy<PyObject> for ErrorPolicy {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.as_str().into_py(py)
+    }
+}
+
+/// How the decoded events are returned.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A list of [MossPacket]s in [DecodeResult::packets], given as `"packets"`.
+    #[default]
+    Packets,
+    /// A [MossEventBatch] in [DecodeResult::batch], given as `"batch"`.
+    Batch,
+}
+
+impl OutputFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Packets => "packets",
+            Self::Batch => "batch",
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for OutputFormat {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "packets" => Ok(Self::Packets),
+            "batch" => Ok(Self::Batch),
+            other => Err(PyValueError::new_err(format!(
+                "Invalid output format {other:?}, expected \"packets\" or \"batch\""
+            ))),
+        }
+    }
+}
+
+impl IntoPy<PyObject> for OutputFormat {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        self.as_str().into_py(py)
+    }
+}
+
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Options for decoding with [decode](crate::decode), [decode_file](crate::decode_file) and [decode_stream](crate::decode_stream).
+pub struct DecodeOptions {
+    /// Number of events to skip before decoding, the skipped events are not validated.
+    #[pyo3(get, set)]
+    pub skip: usize,
+    /// Maximum number of events to decode, or `None` to decode all events.
+    #[pyo3(get, set)]
+    pub take: Option<usize>,
+    /// Bytes decoded before the input, e.g. the [DecodeResult::remainder] of the previous call.
+    pub prepend: Vec<u8>,
+    /// What to do when an event fails to decode.
+    #[pyo3(get, set)]
+    pub errors: ErrorPolicy,
+    /// Hits removed from the decoded events of all units, e.g. noisy pixels.
+    pub mask: HashSet<MossHit>,
+    /// How the decoded events are returned.
+    #[pyo3(get, set)]
+    pub output: OutputFormat,
+    /// Attach [PacketMetadata] to each [MossPacket], only supported for the `"packets"` output format.
+    #[pyo3(get, set)]
+    pub metadata: bool,
+}
+
+impl DecodeOptions {
+    fn validate(&self) -> PyResult<()> {
+        if self.metadata && self.output == OutputFormat::Batch {
+            return Err(PyValueError::new_err(
+                "metadata is only supported for the \"packets\" output format",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Collects the hits of an iterable of [MossHit]s into a mask.
+fn extract_mask(hits: &PyAny) -> PyResult<HashSet<MossHit>> {
+    hits.iter()?.map(|hit| hit?.extract()).collect()
+}
+
+#[pymethods]
+impl DecodeOptions {
+    #[new]
+    #[pyo3(signature = (skip = 0, take = None, prepend = None, errors = ErrorPolicy::Raise, mask = None, output = OutputFormat::Packets, metadata = false))]
+    fn py_new(
+        skip: usize,
+        take: Option<usize>,
+        prepend: Option<Vec<u8>>,
+        errors: ErrorPolicy,
+        mask: Option<&PyAny>,
+        output: OutputFormat,
+        metadata: bool,
+    ) -> PyResult<Self> {
+        let options = Self {
+            skip,
+            take,
+            prepend: prepend.unwrap_or_default(),
+            errors,
+            mask: mask.map(extract_mask).transpose()?.unwrap_or_default(),
+            output,
+            metadata,
+        };
+        options.validate()?;
+        Ok(options)
+    }
+
+    #[getter]
+    fn get_prepend<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.prepend)
+    }
+
+    #[setter]
+    fn set_prepend(&mut self, prepend: Vec<u8>) {
+        self.prepend = prepend;
+    }
+
+    #[getter]
+    fn get_mask(&self) -> HashSet<MossHit> {
+        self.mask.clone()
+    }
+
+    #[setter]
+    fn set_mask(&mut self, mask: &PyAny) -> PyResult<()> {
+        self.mask = extract_mask(mask)?;
+        Ok(())
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for DecodeOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "skip={skip} take={take:?} prepend={prepend} bytes errors={errors} mask={mask} hits output={output} metadata={metadata}",
+                skip = self.skip,
+                take = self.take,
+                prepend = self.prepend.len(),
+                errors = self.errors.as_str(),
+                mask = self.mask.len(),
+                output = self.output.as_str(),
+                metadata = self.metadata
+            ),
+        )
+    }
+}
+
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The result of decoding with [DecodeOptions].
+pub struct DecodeResult {
+    /// The decoded events, empty if the output format is `"batch"`.
+    #[pyo3(get)]
+    pub packets: Vec<MossPacket>,
+    /// The decoded events if the output format is `"batch"`.
+    #[pyo3(get)]
+    pub batch: Option<MossEventBatch>,
+    /// Number of events skipped with [DecodeOptions::skip].
+    #[pyo3(get)]
+    pub skipped: usize,
+    /// Number of bytes consumed, counted from the start of [DecodeOptions::prepend].
+    ///
+    /// That is the end of the last event that was decoded, skipped or failed to decode, including the filler bytes following it
+    /// unless decoding stopped after [DecodeOptions::take] events.
+    #[pyo3(get)]
+    pub bytes_consumed: usize,
+    /// The bytes of the partial event at the end of the input, pass them as [DecodeOptions::prepend] to continue decoding.
+    ///
+    /// Empty if there is no partial event or decoding stopped after [DecodeOptions::take] events.
+    pub remainder: Vec<u8>,
+    /// The errors skipped with the `"skip"` error policy.
+    #[pyo3(get)]
+    pub errors: Vec<String>,
+}
+
+impl DecodeResult {
+    /// Number of decoded events.
+    pub fn len(&self) -> usize {
+        self.batch.as_ref().map_or(self.packets.len(), |b| b.len())
+    }
+
+    /// Whether no events were decoded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[pymethods]
+impl DecodeResult {
+    #[getter]
+    fn get_remainder<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.remainder)
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for DecodeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Events: {events} Skipped: {skipped} Bytes consumed: {consumed} Remainder: {remainder} bytes Errors: {errors}",
+                events = self.len(),
+                skipped = self.skipped,
+                consumed = self.bytes_consumed,
+                remainder = self.remainder.len(),
+                errors = self.errors.len()
+            ),
+        )
+    }
+}
+
+/// Decodes the events of a stream that arrives in chunks according to [DecodeOptions] into a [PacketSink].
+struct OptionsDecoder<'a> {
+    options: &'a DecodeOptions,
+    framing: &'a FramingConfig,
+    source_file: Option<&'a Path>,
+    sink: &'a mut dyn PacketSink,
+    /// Everything but the decoded events, those are in the sink.
+    result: DecodeResult,
+    /// Number of events seen so far, including skipped events.
+    events_seen: usize,
+    /// Stream offset of the end of the last event that was decoded, skipped or failed to decode.
+    events_end: usize,
+    /// Set once [DecodeOptions::take] events are decoded or the sink stopped decoding.
+    done: bool,
+    /// Whether the hits are decoded directly into the batch of the sink, see [PacketSink::as_batch].
+    into_batch: bool,
+    /// Whether metadata is attached to the packets, see [PacketSink::needs_metadata].
+    metadata: bool,
+}
+
+impl<'a> OptionsDecoder<'a> {
+    fn new(
+        options: &'a DecodeOptions,
+        framing: &'a FramingConfig,
+        source_file: Option<&'a Path>,
+        sink: &'a mut dyn PacketSink,
+    ) -> Self {
+        let metadata = options.metadata || sink.needs_metadata();
+        let into_batch = options.mask.is_empty() && !metadata && sink.as_batch().is_some();
+        Self {
+            options,
+            framing,
+            source_file,
+            sink,
+            result: DecodeResult::default(),
+            events_seen: 0,
+            events_end: 0,
+            done: options.take == Some(0),
+            into_batch,
+            metadata,
+        }
+    }
+
+    /// Decodes the complete events at the start of `buf`, the bytes following the bytes consumed so far.
+    ///
+    /// Returns the number of bytes consumed, the rest should be passed again with more bytes appended.
+    /// Filler bytes are consumed, so only the bytes from the Unit Frame Header of an incomplete event on are kept.
+    fn decode(&mut self, buf: &[u8]) -> PyResult<usize> {
+        let mut pos = 0;
+        while !self.done && pos < buf.len() {
+            if self.events_seen < self.options.skip {
+                let Some(header_idx) = find_header(&buf[pos..]) else {
+                    // Only filler bytes left
+                    pos = buf.len();
+                    break;
+                };
+                // Wait for more bytes if the event is incomplete
+                let Ok(event_end) = find_trailer_n_idx(&buf[pos + header_idx..], 1, self.framing)
+                else {
+                    pos += header_idx;
+                    break;
+                };
+                pos += header_idx + event_end + 1;
+                self.events_end = self.result.bytes_consumed + pos;
+                self.events_seen += 1;
+                self.result.skipped += 1;
+                continue;
+            }
+            let into_batch = self.into_batch;
+            let extracted = match self.sink.as_batch().filter(|_| into_batch) {
+                Some(batch) => {
+                    crate::rust_only::extract_packet_into_batch(&buf[pos..], batch, self.framing)
+                        .map(|event_end| (None, event_end))
+                }
+                None => crate::rust_only::extract_packet_with_header_idx(&buf[pos..], self.framing)
+                    .map(|(moss_packet, header_idx, trailer_idx, event_end)| {
+                        (Some((moss_packet, header_idx, trailer_idx)), event_end)
+                    }),
+            };
+            match extracted {
+                Ok((decoded, event_end)) => {
+                    let stream_offset = self.result.bytes_consumed + pos;
+                    match decoded {
+                        Some((moss_packet, header_idx, trailer_idx)) => self.push(
+                            moss_packet,
+                            stream_offset + header_idx,
+                            stream_offset + trailer_idx,
+                        )?,
+                        // The hits are already in the batch
+                        None => self.count_decoded(SinkControl::Continue),
+                    }
+                    pos += event_end + 1;
+                    self.events_end = self.result.bytes_consumed + pos;
+                }
+                // Only filler bytes left
+                Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => pos = buf.len(),
+                // Wait for more bytes to complete the event
+                Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
+                    pos += find_header(&buf[pos..]).unwrap_or_default();
+                    break;
+                }
+                Err(e) => {
+                    let msg = format!(
+                        "Failed decoding packet #{packet_cnt}: {e}",
+                        packet_cnt = self.events_seen + 1
+                    );
+                    match self.options.errors {
+                        ErrorPolicy::Raise => return Err(PyAssertionError::new_err(msg)),
+                        ErrorPolicy::Skip => {
+                            let offset = self.result.bytes_consumed + pos + e.err_index();
+                            self.sink.error(&msg, offset)?;
+                            self.result.errors.push(msg);
+                            pos += find_resume_idx(&buf[pos..], e.err_index());
+                            self.events_end = self.result.bytes_consumed + pos;
+                        }
+                    }
+                }
+            }
+        }
+        self.result.bytes_consumed += pos;
+        Ok(pos)
+    }
+
+    fn push(
+        &mut self,
+        mut moss_packet: MossPacket,
+        header_offset: usize,
+        trailer_offset: usize,
+    ) -> PyResult<()> {
+        if !self.options.mask.is_empty() {
+            moss_packet
+                .hits
+                .retain(|hit| !self.options.mask.contains(hit));
+        }
+        if self.metadata {
+            let mut metadata = PacketMetadata::new(header_offset, trailer_offset, self.events_seen);
+            metadata.source_file = self.source_file.map(Path::to_path_buf);
+            moss_packet.metadata = Some(Box::new(metadata));
+        }
+        let control = self.sink.consume(moss_packet)?;
+        self.count_decoded(control);
+        Ok(())
+    }
+
+    /// Counts a decoded event, `control` is what the sink returned for it.
+    fn count_decoded(&mut self, control: SinkControl) {
+        self.events_seen += 1;
+        self.done = control == SinkControl::Stop
+            || self
+                .options
+                .take
+                .is_some_and(|take| self.events_seen - self.result.skipped >= take);
+    }
+
+    /// Finishes decoding, `leftover` are the bytes that were not consumed.
+    ///
+    /// Returns the result and the stream offset of the end of the last event that was decoded, skipped or failed to decode.
+    fn finish(mut self, leftover: &[u8]) -> PyResult<(DecodeResult, usize)> {
+        if !self.done && find_header(leftover).is_some() {
+            self.result.remainder = leftover.to_vec();
+        }
+        self.sink.finish()?;
+        Ok((self.result, self.events_end))
+    }
+}
+
+/// Runs `decode` with a sink collecting the decoded events in the [OutputFormat] of the `options`.
+fn collect_output(
+    options: &DecodeOptions,
+    decode: impl FnOnce(&mut dyn PacketSink) -> PyResult<DecodeResult>,
+) -> PyResult<DecodeResult> {
+    options.validate()?;
+    match options.output {
+        OutputFormat::Packets => {
+            let mut packets = Vec::new();
+            let result = decode(&mut packets)?;
+            Ok(DecodeResult { packets, ..result })
+        }
+        OutputFormat::Batch => {
+            let mut batch = MossEventBatch::default();
+            let result = decode(&mut batch)?;
+            Ok(DecodeResult {
+                batch: Some(batch),
+                ..result
+            })
+        }
+    }
+}
+
+/// Decodes `bytes` preceded by [DecodeOptions::prepend].
+pub(crate) fn decode_bytes(
+    bytes: &[u8],
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+) -> PyResult<DecodeResult> {
+    collect_output(options, |sink| {
+        decode_bytes_into(bytes, options, framing, sink)
+    })
+}
+
+/// Decodes the data from `reader` preceded by [DecodeOptions::prepend], in chunks of [READER_BUFFER_CAPACITY](crate::READER_BUFFER_CAPACITY).
+///
+/// Stops reading once [DecodeOptions::take] events are decoded.
+pub(crate) fn decode_reader(
+    reader: impl Read,
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    source_file: Option<&Path>,
+) -> PyResult<DecodeResult> {
+    collect_output(options, |sink| {
+        decode_reader_into(reader, options, framing, source_file, sink)
+    })
+}
+
+/// Decodes `bytes` preceded by [DecodeOptions::prepend] into `sink`.
+///
+/// The returned [DecodeResult] has no events, [DecodeOptions::output] is ignored.
+pub fn decode_bytes_into(
+    bytes: &[u8],
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    sink: &mut dyn PacketSink,
+) -> PyResult<DecodeResult> {
+    let (result, _) = decode_bytes_to_events_end(bytes, options, framing, sink)?;
+    Ok(result)
+}
+
+/// Same as [decode_bytes_into] but also returns the end of the last event that was decoded, skipped or failed to decode,
+/// that is [DecodeResult::bytes_consumed] without the filler bytes following the event.
+pub(crate) fn decode_bytes_to_events_end(
+    bytes: &[u8],
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    sink: &mut dyn PacketSink,
+) -> PyResult<(DecodeResult, usize)> {
+    let mut decoder = OptionsDecoder::new(options, framing, None, sink);
+    if options.prepend.is_empty() {
+        let consumed = decoder.decode(bytes)?;
+        decoder.finish(&bytes[consumed..])
+    } else {
+        let bytes = [&options.prepend[..], bytes].concat();
+        let consumed = decoder.decode(&bytes)?;
+        decoder.finish(&bytes[consumed..])
+    }
+}
+
+/// Decodes the data from `reader` preceded by [DecodeOptions::prepend] into `sink`, in chunks of 10 MiB.
+///
+/// Stops reading once [DecodeOptions::take] events are decoded or the sink stops decoding.
+/// The returned [DecodeResult] has no events, [DecodeOptions::output] is ignored.
+pub fn decode_reader_into(
+    reader: impl Read,
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    source_file: Option<&Path>,
+    sink: &mut dyn PacketSink,
+) -> PyResult<DecodeResult> {
+    let (result, _) = decode_reader_to_events_end(reader, options, framing, source_file, sink)?;
+    Ok(result)
+}
+
+/// Same as [decode_reader_into] but also returns the end of the last event that was decoded, skipped or failed to decode,
+/// see [decode_bytes_to_events_end].
+pub(crate) fn decode_reader_to_events_end(
+    mut reader: impl Read,
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    source_file: Option<&Path>,
+    sink: &mut dyn PacketSink,
+) -> PyResult<(DecodeResult, usize)> {
+    let mut decoder = OptionsDecoder::new(options, framing, source_file, sink);
+
+    let mut buf = vec![0; crate::READER_BUFFER_CAPACITY];
+    let mut bytes_to_decode = Vec::with_capacity(crate::READER_BUFFER_CAPACITY);
+    bytes_to_decode.extend_from_slice(&options.prepend);
+    loop {
+        let consumed = decoder.decode(&bytes_to_decode)?;
+        _ = bytes_to_decode.drain(..consumed);
+        if decoder.done {
+            break;
+        }
+        let bytes_read = reader.read(&mut buf).map_err(into_py_err)?;
+        if bytes_read == 0 {
+            break;
+        }
+        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
+    }
+    decoder.finish(&bytes_to_decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    fn events(n: usize) -> Vec<u8> {
+        fake_event_simple().repeat(n)
+    }
+
+    #[test]
+    fn test_skip_take_and_remainder() {
+        let event = fake_event_simple();
+        let mut bytes = events(5);
+        bytes.extend_from_slice(&event[..4]);
+        let options = DecodeOptions {
+            skip: 1,
+            take: Some(2),
+            ..Default::default()
+        };
+
+        let result = decode_bytes(&bytes, &options, &FramingConfig::default()).unwrap();
+        assert_eq!(result.packets.len(), 2);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.bytes_consumed, 3 * event.len());
+        assert!(result.remainder.is_empty());
+
+        let all = DecodeOptions {
+            skip: 1,
+            ..Default::default()
+        };
+        let result = decode_bytes(&bytes, &all, &FramingConfig::default()).unwrap();
+        assert_eq!(result.packets.len(), 4);
+        assert_eq!(result.remainder, &event[..4]);
+
+        // Continue with the rest of the partial event
+        let next = DecodeOptions {
+            prepend: result.remainder,
+            ..Default::default()
+        };
+        let result = decode_bytes(&event[4..], &next, &FramingConfig::default()).unwrap();
+        assert_eq!(result.packets.len(), 1);
+        assert_eq!(result.bytes_consumed, event.len());
+    }
+
+    #[test]
+    fn test_reader_matches_bytes() {
+        let bytes = events(7);
+        let options = DecodeOptions {
+            skip: 2,
+            metadata: true,
+            ..Default::default()
+        };
+        let framing = FramingConfig::default();
+
+        let from_bytes = decode_bytes(&bytes, &options, &framing).unwrap();
+        let from_reader = decode_reader(&bytes[..], &options, &framing, None).unwrap();
+        assert_eq!(from_reader, from_bytes);
+        let metadata = from_bytes.packets[0].metadata.as_ref().unwrap();
+        assert_eq!(metadata.sequence, 2);
+        assert_eq!(metadata.header_offset, 2 * fake_event_simple().len());
+    }
+
+    #[test]
+    fn test_error_policy_and_mask() {
+        let mut bytes = fake_event_simple();
+        bytes.insert(3, 0xD5); // Unexpected header in the first event
+        bytes.extend(events(2));
+        let framing = FramingConfig::default();
+
+        let raise = DecodeOptions::default();
+        assert!(decode_bytes(&bytes, &raise, &framing).is_err());
+
+        let packets = decode_bytes(&events(1), &raise, &framing).unwrap().packets;
+        let masked_hit = packets[0].hits[0];
+        let skip = DecodeOptions {
+            errors: ErrorPolicy::Skip,
+            mask: HashSet::from([masked_hit]),
+            output: OutputFormat::Batch,
+            ..Default::default()
+        };
+        let result = decode_bytes(&bytes, &skip, &framing).unwrap();
+        assert!(!result.errors.is_empty());
+        let batch = result.batch.unwrap();
+        assert!(batch.len() >= 2);
+        assert!(batch
+            .to_packets()
+            .iter()
+            .all(|p| !p.hits.contains(&masked_hit)));
+    }
+
+    #[test]
+    fn test_filler_is_consumed() {
+        let event = fake_event_simple();
+        let filler = [0xFA; 100]; // Default filler bytes
+        let framing = FramingConfig::default();
+        for skip in [0, 1] {
+            let options = DecodeOptions {
+                skip,
+                ..Default::default()
+            };
+            let mut bytes = event.clone();
+            bytes.extend(filler);
+
+            // Only filler bytes after the event
+            let result = decode_reader(&bytes[..], &options, &framing, None).unwrap();
+            assert_eq!(result.bytes_consumed, bytes.len());
+            assert!(result.remainder.is_empty());
+
+            // Only the bytes from the header of the partial event are kept
+            bytes.extend_from_slice(&event[..4]);
+            let result = decode_bytes(&bytes, &options, &framing).unwrap();
+            assert_eq!(result.bytes_consumed, event.len() + filler.len());
+            assert_eq!(result.remainder, &event[..4]);
+            let (_, events_end) =
+                decode_bytes_to_events_end(&bytes, &options, &framing, &mut Vec::new()).unwrap();
+            assert_eq!(events_end, event.len());
+        }
+    }
+
+    #[test]
+    fn test_skipped_event_split_in_padding() {
+        pyo3::prepare_freethreaded_python();
+        let framing = FramingConfig::try_new([0xFA], 16, true).unwrap();
+        let mut event = fake_event_simple();
+        // Padding can have any value, including Unit Frame Header values
+        assert_ne!(event.len() % 16, 0);
+        event.resize(event.len().next_multiple_of(16), 0xD5);
+        let bytes = event.repeat(3);
+        let options = DecodeOptions {
+            skip: 1,
+            metadata: true,
+            ..Default::default()
+        };
+        let expected = decode_bytes(&bytes, &options, &framing).unwrap();
+        assert_eq!(expected.packets.len(), 2);
+        assert_eq!(expected.bytes_consumed, bytes.len());
+
+        for split in 0..=bytes.len() {
+            let reader = (&bytes[..split]).chain(&bytes[split..]);
+            let result = decode_reader(reader, &options, &framing, None)
+                .unwrap_or_else(|e| panic!("split at {split}: {e}"));
+            assert_eq!(result, expected, "split at {split}");
+        }
+    }
+
+    #[test]
+    fn test_batch_output_matches_packets() {
+        let mut bytes = fake_event_simple();
+        bytes.insert(3, 0xD5); // Unexpected header in the first event
+        bytes.extend(events(3));
+        let framing = FramingConfig::default();
+        for take in [None, Some(2)] {
+            let options = DecodeOptions {
+                take,
+                errors: ErrorPolicy::Skip,
+                ..Default::default()
+            };
+            let packets = decode_bytes(&bytes, &options, &framing).unwrap();
+            let batch_options = DecodeOptions {
+                output: OutputFormat::Batch,
+                ..options
+            };
+            let batch = decode_bytes(&bytes, &batch_options, &framing).unwrap();
+            assert_eq!(batch.batch.unwrap().to_packets(), packets.packets);
+            assert_eq!(batch.bytes_consumed, packets.bytes_consumed);
+            assert_eq!(batch.errors, packets.errors);
+        }
+    }
+
+    #[test]
+    fn test_metadata_requires_packets_output() {
+        let options = DecodeOptions {
+            output: OutputFormat::Batch,
+            metadata: true,
+            ..Default::default()
+        };
+        assert!(decode_bytes(&events(1), &options, &FramingConfig::default()).is_err());
+    }
+}
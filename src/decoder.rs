@@ -0,0 +1,143 @@
+//! A reusable decoder for a MOSS stream that is read out in many small chunks.
+//!
+//! Keeps the bytes of a partial event, the statistics and its allocations between calls,
+//! instead of the caller passing the remainder of each call as `prepend_buffer` of the next.
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList};
+use std::fmt::{write, Display};
+
+use crate::stream::{StreamState, StreamStats};
+use crate::{FramingConfig, MossPacket};
+
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone)]
+/// Decodes a MOSS stream chunk by chunk, returning the packets completed by each chunk.
+///
+/// An event split across chunks is completed by the chunk containing its trailer.
+/// Protocol errors are recorded in the [StreamStats] and decoding resumes at the next Unit Frame Header.
+pub struct MossDecoder {
+    framing: FramingConfig,
+    state: StreamState,
+    /// Packets decoded from the last chunk, reused to avoid an allocation per chunk.
+    packets: Vec<MossPacket>,
+}
+
+impl MossDecoder {
+    /// Create a decoder for data with the given framing.
+    pub fn new(framing: FramingConfig) -> Self {
+        Self {
+            framing,
+            ..Default::default()
+        }
+    }
+
+    /// Decodes the complete MOSS events in the next chunk of the stream.
+    ///
+    /// The chunk does not have to end on an event boundary, the bytes of a partial event are kept for the next chunk.
+    pub fn feed(&mut self, bytes: &[u8]) -> std::vec::Drain<'_, MossPacket> {
+        self.packets.clear();
+        self.state
+            .feed_into(bytes, &self.framing, &mut self.packets);
+        self.packets.drain(..)
+    }
+
+    /// The statistics of the stream so far.
+    pub fn stats(&self) -> &StreamStats {
+        self.state.stats()
+    }
+
+    /// The bytes of the partial event at the end of the stream so far.
+    pub fn remainder(&self) -> &[u8] {
+        self.state.remainder()
+    }
+
+    /// Discard the partial event and reset the statistics, e.g. to start a new run.
+    pub fn reset(&mut self) {
+        self.state.reset();
+    }
+}
+
+#[pymethods]
+impl MossDecoder {
+    #[new]
+    fn py_new(framing: Option<FramingConfig>) -> Self {
+        Self::new(framing.unwrap_or_default())
+    }
+
+    /// Decodes the complete MOSS events in the next chunk of the stream, returns a list of `MossPacket`s.
+    #[pyo3(name = "feed")]
+    fn py_feed<'py>(
+        &mut self,
+        py: Python<'py>,
+        #[pyo3(from_py_with = "crate::buffer_input::bytes_from_buffer")] bytes: &[u8],
+    ) -> &'py PyList {
+        PyList::new(py, self.feed(bytes).map(|packet| packet.into_py(py)))
+    }
+
+    #[getter]
+    fn get_stats(&self) -> StreamStats {
+        self.stats().clone()
+    }
+
+    #[getter]
+    fn get_remainder<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.remainder())
+    }
+
+    #[pyo3(name = "reset")]
+    fn py_reset(&mut self) {
+        self.reset();
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for MossDecoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "{stats} Remainder: {remainder} bytes",
+                stats = self.stats(),
+                remainder = self.remainder().len()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_feed_small_chunks() {
+        let bytes = fake_event_simple().repeat(5);
+        let (expected, _) = crate::decode_all_events(&bytes, None).unwrap();
+        let mut decoder = MossDecoder::default();
+
+        let packets: Vec<MossPacket> = bytes
+            .chunks(3)
+            .flat_map(|chunk| decoder.feed(chunk).collect::<Vec<_>>())
+            .collect();
+
+        assert_eq!(packets, expected);
+        assert_eq!(decoder.stats().packets, 5);
+        assert_eq!(decoder.stats().bytes, bytes.len());
+        // Only the trailing filler byte is left
+        assert!(decoder.remainder().len() <= 1);
+
+        _ = decoder.feed(&fake_event_simple()[..4]).count();
+        decoder.reset();
+        assert!(decoder.remainder().is_empty());
+        assert_eq!(decoder.stats(), &StreamStats::default());
+    }
+}
@@ -0,0 +1,362 @@
+//! Demultiplexing of an interleaved stream from several MOSS chips, e.g. the planes of a telescope.
+//!
+//! The stream is a sequence of link records, each carrying a chunk of the raw data of one chip.
+//! The layout of the records depends on the readout system and is described by a [LinkRecordFormat],
+//! the default is an example layout, not the format of any particular readout system:
+//!
+//! | Offset | Size | Field                      |
+//! |--------|------|----------------------------|
+//! | 0      | 1    | Chip/link ID               |
+//! | 1      | 2    | Payload length `N`, little-endian |
+//! | 3      | N    | Payload, raw MOSS data     |
+//!
+//! A MOSS event can be split across several records of the same chip.
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::{write, Display};
+
+use crate::stream::{StreamState, StreamStats};
+use crate::{FramingConfig, MossPacket};
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Describes the header of the link records of an interleaved stream.
+///
+/// - `length_size`: Number of bytes of the payload length following the 1-byte chip ID, 1, 2 or 4. Default is 2.
+/// - `big_endian`: Whether the payload length is big-endian. Default is false.
+pub struct LinkRecordFormat {
+    length_size: usize,
+    big_endian: bool,
+}
+
+impl Default for LinkRecordFormat {
+    fn default() -> Self {
+        Self {
+            length_size: 2,
+            big_endian: false,
+        }
+    }
+}
+
+impl LinkRecordFormat {
+    /// Create a new link record format.
+    ///
+    /// Returns an error if `length_size` is not 1, 2 or 4.
+    pub fn new(length_size: usize, big_endian: bool) -> PyResult<Self> {
+        Self::try_new(length_size, big_endian).map_err(PyValueError::new_err)
+    }
+
+    /// Same as [Self::new] but the error is a plain message, for callers without a Python interpreter.
+    pub(crate) fn try_new(length_size: usize, big_endian: bool) -> Result<Self, String> {
+        if ![1, 2, 4].contains(&length_size) {
+            return Err(format!("length_size must be 1, 2 or 4, got: {length_size}"));
+        }
+        Ok(Self {
+            length_size,
+            big_endian,
+        })
+    }
+
+    /// Number of bytes before the payload of a link record.
+    pub fn header_len(&self) -> usize {
+        1 + self.length_size
+    }
+
+    /// Parses a link record header of [Self::header_len] bytes, returns the chip ID and the payload length.
+    pub fn parse_header(&self, header: &[u8]) -> (u8, usize) {
+        let mut len_bytes = [0; 4];
+        let len_field = &header[1..self.header_len()];
+        let payload_len = if self.big_endian {
+            len_bytes[4 - self.length_size..].copy_from_slice(len_field);
+            u32::from_be_bytes(len_bytes)
+        } else {
+            len_bytes[..self.length_size].copy_from_slice(len_field);
+            u32::from_le_bytes(len_bytes)
+        };
+        (header[0], payload_len as usize)
+    }
+}
+
+#[pymethods]
+impl LinkRecordFormat {
+    #[new]
+    #[pyo3(signature = (length_size = 2, big_endian = false))]
+    fn py_new(length_size: usize, big_endian: bool) -> PyResult<Self> {
+        Self::new(length_size, big_endian)
+    }
+
+    #[getter(length_size)]
+    fn py_length_size(&self) -> usize {
+        self.length_size
+    }
+
+    #[getter(big_endian)]
+    fn py_big_endian(&self) -> bool {
+        self.big_endian
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for LinkRecordFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "length_size: {length_size}, big_endian: {big_endian}",
+                length_size = self.length_size,
+                big_endian = self.big_endian
+            ),
+        )
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Default, Clone)]
+/// Splits an interleaved multi-chip stream into independent per-chip decoders.
+///
+/// Each chip keeps its own partial event between records and its own [StreamStats],
+/// a protocol error in the data of one chip does not affect the others.
+pub struct StreamDemux {
+    framing: FramingConfig,
+    link_format: LinkRecordFormat,
+    chips: BTreeMap<u8, StreamState>,
+    /// Bytes of a partial link record header at the end of the last chunk
+    partial_header: Vec<u8>,
+    /// The chip and the number of payload bytes still to come of the record the last chunk ended in
+    payload_left: Option<(u8, usize)>,
+}
+
+impl StreamDemux {
+    /// Create a demultiplexer decoding the data of each chip with the given framing.
+    pub fn new(framing: FramingConfig) -> Self {
+        Self::with_link_format(framing, LinkRecordFormat::default())
+    }
+
+    /// Create a demultiplexer for link records with the given format.
+    pub fn with_link_format(framing: FramingConfig, link_format: LinkRecordFormat) -> Self {
+        Self {
+            framing,
+            link_format,
+            ..Default::default()
+        }
+    }
+
+    /// Decodes the complete MOSS events in the next chunk of the interleaved stream.
+    ///
+    /// The chunk does not have to end on a record boundary. Payloads are passed to the chips as they arrive,
+    /// only a partial record header is kept between chunks.
+    /// Returns the decoded packets tagged with their chip ID in the order their last byte arrived.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<(u8, MossPacket)> {
+        let header_len = self.link_format.header_len();
+        let mut tagged_packets = Vec::new();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            if let Some((chip_id, payload_len)) = self.payload_left.take() {
+                let payload = &bytes[pos..bytes.len().min(pos + payload_len)];
+                let chip = self.chips.entry(chip_id).or_default();
+                tagged_packets.extend(
+                    chip.feed(payload, &self.framing)
+                        .into_iter()
+                        .map(|packet| (chip_id, packet)),
+                );
+                pos += payload.len();
+                if payload.len() < payload_len {
+                    self.payload_left = Some((chip_id, payload_len - payload.len()));
+                }
+                continue;
+            }
+            let (chip_id, payload_len) =
+                if self.partial_header.is_empty() && bytes.len() - pos >= header_len {
+                    pos += header_len;
+                    self.link_format.parse_header(&bytes[pos - header_len..pos])
+                } else {
+                    let missing = (header_len - self.partial_header.len()).min(bytes.len() - pos);
+                    self.partial_header
+                        .extend_from_slice(&bytes[pos..pos + missing]);
+                    pos += missing;
+                    if self.partial_header.len() < header_len {
+                        break;
+                    }
+                    let header = self.link_format.parse_header(&self.partial_header);
+                    self.partial_header.clear();
+                    header
+                };
+            // Records without payload still make the chip known
+            _ = self.chips.entry(chip_id).or_default();
+            if payload_len > 0 {
+                self.payload_left = Some((chip_id, payload_len));
+            }
+        }
+        tagged_packets
+    }
+
+    /// The format of the link records.
+    pub fn link_format(&self) -> LinkRecordFormat {
+        self.link_format
+    }
+
+    /// The IDs of the chips seen so far in ascending order.
+    pub fn chip_ids(&self) -> Vec<u8> {
+        self.chips.keys().copied().collect()
+    }
+
+    /// The statistics of the given chip, if it has been seen.
+    pub fn stats(&self, chip_id: u8) -> Option<&StreamStats> {
+        self.chips.get(&chip_id).map(StreamState::stats)
+    }
+
+    /// The bytes of the partial event of the given chip, if it has been seen.
+    pub fn remainder(&self, chip_id: u8) -> Option<&[u8]> {
+        self.chips.get(&chip_id).map(StreamState::remainder)
+    }
+}
+
+#[pymethods]
+impl StreamDemux {
+    #[new]
+    fn py_new(framing: Option<FramingConfig>, link_format: Option<LinkRecordFormat>) -> Self {
+        Self::with_link_format(framing.unwrap_or_default(), link_format.unwrap_or_default())
+    }
+
+    #[getter(link_format)]
+    fn py_link_format(&self) -> LinkRecordFormat {
+        self.link_format
+    }
+
+    /// Decodes the complete MOSS events in the next chunk of the interleaved stream,
+    /// returns a list of `(chip ID, MossPacket)` tuples.
+    #[pyo3(name = "feed")]
+    fn py_feed(
+        &mut self,
+        #[pyo3(from_py_with = "crate::buffer_input::bytes_from_buffer")] bytes: &[u8],
+    ) -> Vec<(u8, MossPacket)> {
+        self.feed(bytes)
+    }
+
+    #[pyo3(name = "chip_ids")]
+    fn py_chip_ids(&self) -> Vec<u8> {
+        self.chip_ids()
+    }
+
+    /// Returns the statistics of each chip seen so far.
+    fn all_stats(&self) -> BTreeMap<u8, StreamStats> {
+        self.chips
+            .iter()
+            .map(|(&chip_id, chip)| (chip_id, chip.stats().clone()))
+            .collect()
+    }
+
+    #[pyo3(name = "stats")]
+    fn py_stats(&self, chip_id: u8) -> PyResult<StreamStats> {
+        self.stats(chip_id)
+            .cloned()
+            .ok_or_else(|| PyKeyError::new_err(format!("No data seen from chip {chip_id}")))
+    }
+
+    #[pyo3(name = "remainder")]
+    fn py_remainder(&self, chip_id: u8) -> PyResult<Vec<u8>> {
+        self.remainder(chip_id)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| PyKeyError::new_err(format!("No data seen from chip {chip_id}")))
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!(
+            "{}(chips: {:?})",
+            class_name,
+            slf.borrow().chip_ids()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    const LINK_HEADER_LEN: usize = 3;
+
+    fn link_record(chip_id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut record = vec![chip_id];
+        record.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        record.extend_from_slice(payload);
+        record
+    }
+
+    #[test]
+    fn test_demux_interleaved_chips() {
+        let event = fake_event_simple();
+        let mut stream = link_record(1, &event[..5]);
+        stream.extend(link_record(2, &event));
+        stream.extend(link_record(1, &event[5..]));
+        // Corrupt data on chip 3 does not affect the other chips
+        stream.extend(link_record(3, &[0xD1, 0x12, 0xE0]));
+        stream.extend(link_record(2, &event[..10]));
+
+        let mut demux = StreamDemux::default();
+        // Split the stream in the middle of a record header
+        let mut packets = demux.feed(&stream[..LINK_HEADER_LEN + 6]);
+        packets.extend(demux.feed(&stream[LINK_HEADER_LEN + 6..]));
+
+        assert_eq!(
+            packets.iter().map(|(chip, _)| *chip).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert!(packets.iter().all(|(_, p)| p.hits.len() == 4));
+        assert_eq!(demux.chip_ids(), vec![1, 2, 3]);
+        assert_eq!(demux.stats(1).unwrap().packets, 1);
+        assert_eq!(demux.stats(2).unwrap().bytes, event.len() + 10);
+        assert_eq!(demux.remainder(2).unwrap(), &event[..10]);
+        assert_eq!(demux.stats(3).unwrap().protocol_errors, 1);
+        assert!(demux.stats(4).is_none());
+    }
+
+    #[test]
+    fn test_demux_byte_by_byte() {
+        let event = fake_event_simple();
+        let mut stream = link_record(1, &event[..5]);
+        stream.extend(link_record(2, &[]));
+        stream.extend(link_record(1, &event[5..]));
+
+        let mut demux = StreamDemux::default();
+        let packets: Vec<_> = stream.chunks(1).flat_map(|b| demux.feed(b)).collect();
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].0, 1);
+        assert_eq!(demux.chip_ids(), vec![1, 2]);
+        assert_eq!(demux.stats(1).unwrap().bytes, event.len());
+    }
+
+    #[test]
+    fn test_link_record_format() {
+        let event = fake_event_simple();
+        let format = LinkRecordFormat::try_new(4, true).unwrap();
+        assert_eq!(format.header_len(), 5);
+        let mut stream = vec![7];
+        stream.extend_from_slice(&(event.len() as u32).to_be_bytes());
+        stream.extend_from_slice(&event);
+
+        let mut demux = StreamDemux::with_link_format(FramingConfig::default(), format);
+        let packets = demux.feed(&stream);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].0, 7);
+        assert_eq!(
+            LinkRecordFormat::try_new(1, false)
+                .unwrap()
+                .parse_header(&[3, 200]),
+            (3, 200)
+        );
+        assert!(LinkRecordFormat::try_new(3, false).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+//! Event by event comparison of two decoding results, e.g. before and after a firmware or decoder change.
+//!
+//! Events are matched in order. When two events differ, the next few events of both sides are searched
+//! for a match so that an event missing from one side doesn't make every following event differ.
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use crate::{MossHit, MossPacket};
+
+/// How many events ahead to look for a matching event when two events differ.
+pub const RESYNC_WINDOW: usize = 16;
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A difference between an event of the first (`a`) and the second (`b`) decoding result.
+///
+/// An event missing from one side has no index, unit ID or hits on that side.
+pub struct EventDiff {
+    /// Index of the event in `a`.
+    pub index_a: Option<usize>,
+    /// Index of the event in `b`.
+    pub index_b: Option<usize>,
+    /// Unit ID of the event in `a`.
+    pub unit_id_a: Option<u8>,
+    /// Unit ID of the event in `b`.
+    pub unit_id_b: Option<u8>,
+    /// Hits in `b` that are not in `a`.
+    pub added_hits: Vec<MossHit>,
+    /// Hits in `a` that are not in `b`.
+    pub removed_hits: Vec<MossHit>,
+}
+
+impl EventDiff {
+    fn missing_in_b(index_a: usize, packet: &MossPacket) -> Self {
+        Self {
+            index_a: Some(index_a),
+            unit_id_a: Some(packet.unit_id),
+            removed_hits: packet.hits.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn missing_in_a(index_b: usize, packet: &MossPacket) -> Self {
+        Self {
+            index_b: Some(index_b),
+            unit_id_b: Some(packet.unit_id),
+            added_hits: packet.hits.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn changed(index_a: usize, a: &MossPacket, index_b: usize, b: &MossPacket) -> Self {
+        let (removed_hits, added_hits) = hit_difference(&a.hits, &b.hits);
+        Self {
+            index_a: Some(index_a),
+            index_b: Some(index_b),
+            unit_id_a: Some(a.unit_id),
+            unit_id_b: Some(b.unit_id),
+            added_hits,
+            removed_hits,
+        }
+    }
+}
+
+#[pymethods]
+impl EventDiff {
+    /// Returns true if the event is only in `a`.
+    pub fn is_missing_in_b(&self) -> bool {
+        self.index_b.is_none()
+    }
+
+    /// Returns true if the event is only in `b`.
+    pub fn is_missing_in_a(&self) -> bool {
+        self.index_a.is_none()
+    }
+
+    /// Returns true if the event is in both results but with different unit IDs.
+    pub fn is_unit_id_mismatch(&self) -> bool {
+        self.unit_id_a.is_some() && self.unit_id_b.is_some() && self.unit_id_a != self.unit_id_b
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for EventDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.index_a, self.index_b) {
+            (Some(index_a), None) => write(
+                f,
+                format_args!(
+                    "Event a#{index_a} (unit {unit}) missing in b",
+                    unit = self.unit_id_a.unwrap_or_default()
+                ),
+            )?,
+            (None, Some(index_b)) => write(
+                f,
+                format_args!(
+                    "Event b#{index_b} (unit {unit}) missing in a",
+                    unit = self.unit_id_b.unwrap_or_default()
+                ),
+            )?,
+            (index_a, index_b) => {
+                write(
+                    f,
+                    format_args!(
+                        "Event a#{a} / b#{b}:",
+                        a = index_a.unwrap_or_default(),
+                        b = index_b.unwrap_or_default()
+                    ),
+                )?;
+                if self.is_unit_id_mismatch() {
+                    write(
+                        f,
+                        format_args!(
+                            " unit ID {a} != {b}",
+                            a = self.unit_id_a.unwrap_or_default(),
+                            b = self.unit_id_b.unwrap_or_default()
+                        ),
+                    )?;
+                }
+                if !self.removed_hits.is_empty() {
+                    write(f, format_args!(" removed hits: {:?}", self.removed_hits))?;
+                }
+                if !self.added_hits.is_empty() {
+                    write(f, format_args!(" added hits: {:?}", self.added_hits))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The result of comparing two decoding results event by event, with summary counts.
+pub struct DecodeDiff {
+    /// Number of events in `a`.
+    pub events_a: usize,
+    /// Number of events in `b`.
+    pub events_b: usize,
+    /// Number of events that are identical in both results.
+    pub identical_events: usize,
+    /// Number of events only in `b`.
+    pub missing_in_a: usize,
+    /// Number of events only in `a`.
+    pub missing_in_b: usize,
+    /// Number of matched events with different unit IDs.
+    pub unit_id_mismatches: usize,
+    /// Number of matched events with different hits.
+    pub events_with_hit_differences: usize,
+    /// Total number of hits in matched events of `b` that are not in `a`.
+    pub added_hits: usize,
+    /// Total number of hits in matched events of `a` that are not in `b`.
+    pub removed_hits: usize,
+    /// The differing events in order.
+    pub differences: Vec<EventDiff>,
+}
+
+impl DecodeDiff {
+    fn push(&mut self, diff: EventDiff) {
+        if diff.is_missing_in_a() {
+            self.missing_in_a += 1;
+        } else if diff.is_missing_in_b() {
+            self.missing_in_b += 1;
+        } else {
+            if diff.is_unit_id_mismatch() {
+                self.unit_id_mismatches += 1;
+            }
+            if !diff.added_hits.is_empty() || !diff.removed_hits.is_empty() {
+                self.events_with_hit_differences += 1;
+                self.added_hits += diff.added_hits.len();
+                self.removed_hits += diff.removed_hits.len();
+            }
+        }
+        self.differences.push(diff);
+    }
+}
+
+#[pymethods]
+impl DecodeDiff {
+    /// Returns true if both results contain the same events.
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for DecodeDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Events a: {events_a} b: {events_b} Identical: {identical} Missing in a: {missing_a} Missing in b: {missing_b} \
+                Unit ID mismatches: {unit_ids} Hit differences: {hit_diffs} (added: {added} removed: {removed})",
+                events_a = self.events_a,
+                events_b = self.events_b,
+                identical = self.identical_events,
+                missing_a = self.missing_in_a,
+                missing_b = self.missing_in_b,
+                unit_ids = self.unit_id_mismatches,
+                hit_diffs = self.events_with_hit_differences,
+                added = self.added_hits,
+                removed = self.removed_hits
+            ),
+        )
+    }
+}
+
+/// Compares the decoded events `a` and `b` event by event.
+///
+/// Only the unit IDs and hits are compared, not the [PacketMetadata](crate::PacketMetadata).
+pub fn diff_packets(a: &[MossPacket], b: &[MossPacket]) -> DecodeDiff {
+    let mut diff = DecodeDiff {
+        events_a: a.len(),
+        events_b: b.len(),
+        ..Default::default()
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            diff.identical_events += 1;
+            i += 1;
+            j += 1;
+            continue;
+        }
+        // Look for the closest match further ahead in either result
+        let resync = (1..=RESYNC_WINDOW).find_map(|k| {
+            if a.get(i + k).is_some_and(|p| *p == b[j]) {
+                Some((k, 0))
+            } else if b.get(j + k).is_some_and(|p| a[i] == *p) {
+                Some((0, k))
+            } else {
+                None
+            }
+        });
+        match resync {
+            Some((skip_a, skip_b)) => {
+                (i..i + skip_a).for_each(|idx| diff.push(EventDiff::missing_in_b(idx, &a[idx])));
+                (j..j + skip_b).for_each(|idx| diff.push(EventDiff::missing_in_a(idx, &b[idx])));
+                i += skip_a;
+                j += skip_b;
+            }
+            None => {
+                diff.push(EventDiff::changed(i, &a[i], j, &b[j]));
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    (i..a.len()).for_each(|idx| diff.push(EventDiff::missing_in_b(idx, &a[idx])));
+    (j..b.len()).for_each(|idx| diff.push(EventDiff::missing_in_a(idx, &b[idx])));
+
+    diff
+}
+
+/// Returns the hits only in `a` and the hits only in `b`, counting repeated hits.
+fn hit_difference(a: &[MossHit], b: &[MossHit]) -> (Vec<MossHit>, Vec<MossHit>) {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_unstable();
+    b.sort_unstable();
+
+    let (mut only_a, mut only_b) = (Vec::new(), Vec::new());
+    let (mut a_iter, mut b_iter) = (a.into_iter().peekable(), b.into_iter().peekable());
+    loop {
+        match (a_iter.peek(), b_iter.peek()) {
+            (Some(hit_a), Some(hit_b)) => match hit_a.cmp(hit_b) {
+                std::cmp::Ordering::Less => only_a.extend(a_iter.next()),
+                std::cmp::Ordering::Greater => only_b.extend(b_iter.next()),
+                std::cmp::Ordering::Equal => {
+                    _ = a_iter.next();
+                    _ = b_iter.next();
+                }
+            },
+            (Some(_), None) => only_a.extend(a_iter.by_ref()),
+            (None, Some(_)) => only_b.extend(b_iter.by_ref()),
+            (None, None) => break,
+        }
+    }
+    (only_a, only_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn packet(unit_id: u8, rows: &[u16]) -> MossPacket {
+        MossPacket {
+            hits: rows
+                .iter()
+                .map(|&row| MossHit {
+                    region: 0,
+                    row,
+                    column: 1,
+                })
+                .collect(),
+            ..MossPacket::new(unit_id)
+        }
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let a = vec![packet(1, &[1, 2]), packet(2, &[3])];
+
+        let diff = diff_packets(&a, &a.clone());
+
+        assert!(diff.is_identical());
+        assert_eq!(diff.identical_events, 2);
+    }
+
+    #[test]
+    fn test_diff_missing_and_changed_events() {
+        let a = vec![
+            packet(1, &[1]),
+            packet(2, &[2]),
+            packet(3, &[3]),
+            packet(4, &[4, 5]),
+            packet(5, &[6]),
+        ];
+        // Event 1 dropped, event 3 with a changed hit, event 4 with a wrong unit ID, an extra event at the end
+        let b = vec![
+            packet(1, &[1]),
+            packet(3, &[3]),
+            packet(4, &[4, 7]),
+            packet(6, &[6]),
+            packet(7, &[]),
+        ];
+
+        let diff = diff_packets(&a, &b);
+        println!("{diff}");
+        diff.differences.iter().for_each(|d| println!("{d}"));
+
+        assert_eq!(diff.identical_events, 2);
+        assert_eq!(diff.missing_in_b, 1);
+        assert!(diff.differences[0].is_missing_in_b());
+        assert_eq!(diff.differences[0].index_a, Some(1));
+        assert_eq!(diff.events_with_hit_differences, 1);
+        assert_eq!(diff.differences[1].removed_hits, packet(4, &[5]).hits);
+        assert_eq!(diff.differences[1].added_hits, packet(4, &[7]).hits);
+        assert_eq!(diff.unit_id_mismatches, 1);
+        assert!(diff.differences[2].is_unit_id_mismatch());
+        assert_eq!(diff.missing_in_a, 1);
+        assert_eq!(diff.differences[3].index_b, Some(4));
+        assert_eq!((diff.added_hits, diff.removed_hits), (1, 1));
+    }
+}
@@ -0,0 +1,152 @@
+//! Groups the per-unit [MossPacket]s of a stitched sensor into one [MossEvent] per trigger.
+//!
+//! Packets carrying a trigger ID in their [PacketMetadata](crate::PacketMetadata) are grouped by trigger ID.
+//! Without a trigger ID a new event starts when the unit ID is lower than the previous one,
+//! as the units are read out in ascending order.
+//! A unit ID repeated right after itself is therefor flagged as a duplicate,
+//! while a repeat of an earlier unit ID starts a new event.
+use crate::moss_protocol::{MossEvent, MossPacket};
+
+/// The unit IDs of a full stitched sensor, from the Unit Frame Headers `0xD1..=0xDA`.
+pub const ALL_UNITS: std::ops::RangeInclusive<u8> = 1..=10;
+
+/// Builds [MossEvent]s from a stream of [MossPacket]s.
+#[derive(Debug, Clone)]
+pub struct EventBuilder {
+    expected_units: Vec<u8>,
+    current: Vec<MossPacket>,
+}
+
+impl Default for EventBuilder {
+    fn default() -> Self {
+        Self::new(ALL_UNITS.collect())
+    }
+}
+
+impl EventBuilder {
+    /// Create an event builder that flags events missing any of `expected_units`.
+    pub fn new(expected_units: Vec<u8>) -> Self {
+        Self {
+            expected_units,
+            current: Vec::new(),
+        }
+    }
+
+    /// Add the next packet, returns the previous event if `packet` belongs to a new event.
+    pub fn push(&mut self, packet: MossPacket) -> Option<MossEvent> {
+        let starts_new_event =
+            self.current
+                .last()
+                .is_some_and(|prev| match (trigger_id(prev), trigger_id(&packet)) {
+                    (None, None) => packet.unit_id < prev.unit_id,
+                    (prev_trigger, trigger) => prev_trigger != trigger,
+                });
+        let finished = if starts_new_event {
+            self.finish()
+        } else {
+            None
+        };
+        self.current.push(packet);
+        finished
+    }
+
+    /// Returns the event being built, if any packets were added since the last event was returned.
+    pub fn finish(&mut self) -> Option<MossEvent> {
+        if self.current.is_empty() {
+            return None;
+        }
+        let packets = std::mem::take(&mut self.current);
+
+        let mut unit_cnt = [0usize; 16];
+        packets
+            .iter()
+            .for_each(|p| unit_cnt[usize::from(p.unit_id & 0xF)] += 1);
+        let missing_units = self
+            .expected_units
+            .iter()
+            .copied()
+            .filter(|&unit| unit_cnt[usize::from(unit & 0xF)] == 0)
+            .collect();
+        let duplicate_units = (0..16u8)
+            .filter(|&unit| unit_cnt[usize::from(unit)] > 1)
+            .collect();
+        let metadata = packets[0].metadata.as_ref();
+
+        Some(MossEvent {
+            trigger_id: metadata.and_then(|m| m.trigger_id),
+            timestamp: metadata.and_then(|m| m.timestamp),
+            packets,
+            missing_units,
+            duplicate_units,
+        })
+    }
+}
+
+fn trigger_id(packet: &MossPacket) -> Option<u32> {
+    packet.metadata.as_ref().and_then(|m| m.trigger_id)
+}
+
+/// Groups `packets` into [MossEvent]s, flagging events missing any of `expected_units`.
+pub fn build_events(
+    packets: impl IntoIterator<Item = MossPacket>,
+    expected_units: Vec<u8>,
+) -> Vec<MossEvent> {
+    let mut builder = EventBuilder::new(expected_units);
+    let mut events: Vec<MossEvent> = packets
+        .into_iter()
+        .filter_map(|packet| builder.push(packet))
+        .collect();
+    events.extend(builder.finish());
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PacketMetadata;
+    use pretty_assertions::assert_eq;
+
+    fn packets(unit_ids: &[u8]) -> Vec<MossPacket> {
+        unit_ids.iter().map(|&id| MossPacket::new(id)).collect()
+    }
+
+    #[test]
+    fn test_build_events_by_unit_wrap_around() {
+        let events = build_events(packets(&[1, 2, 3, 1, 2, 2, 3, 2, 3]), vec![1, 2, 3]);
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].unit_ids(), vec![1, 2, 3]);
+        assert!(events[0].is_complete());
+        assert_eq!(events[1].unit_ids(), vec![1, 2, 2, 3]);
+        assert_eq!(events[1].duplicate_units, vec![2]);
+        assert_eq!(events[2].unit_ids(), vec![2, 3]);
+        assert_eq!(events[2].missing_units, vec![1]);
+        assert!(events.iter().all(|e| e.trigger_id.is_none()));
+    }
+
+    #[test]
+    fn test_build_events_by_trigger_id() {
+        let packets =
+            [(1, 7), (2, 7), (1, 8), (3, 9), (4, 9)]
+                .into_iter()
+                .map(|(unit_id, trigger_id)| {
+                    let mut metadata = PacketMetadata::new(0, 0, 0);
+                    metadata.trigger_id = Some(trigger_id);
+                    MossPacket {
+                        metadata: Some(Box::new(metadata)),
+                        ..MossPacket::new(unit_id)
+                    }
+                });
+
+        let events = build_events(packets, ALL_UNITS.collect());
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events.iter().map(|e| e.trigger_id).collect::<Vec<_>>(),
+            vec![Some(7), Some(8), Some(9)]
+        );
+        // A new trigger ID starts a new event even if the unit IDs do not wrap around
+        assert_eq!(events[2].unit_ids(), vec![3, 4]);
+        assert_eq!(events[2].missing_units, vec![1, 2, 5, 6, 7, 8, 9, 10]);
+    }
+}
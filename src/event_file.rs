@@ -0,0 +1,627 @@
+//! A compact, versioned binary container for decoded events, written once after decoding and loaded back without decoding.
+//!
+//! The file stores a [MossEventBatch] column by column, all integers little-endian:
+//!
+//! | Offset | Size | Content |
+//! |---|---|---|
+//! | 0 | 8 | Magic `MOSSEVT\0` |
+//! | 8 | 2 | Major format version |
+//! | 10 | 2 | Minor format version |
+//! | 12 | 4 | Header length, the offset of the column table |
+//! | 16 | 8 | Number of events |
+//! | 24 | 8 | Number of hits |
+//! | 32 | 4 | Number of run metadata entries, followed by each key and value as a u32 length and UTF-8 bytes |
+//!
+//! The column table at the header length has a u32 number of columns, 4 reserved bytes and a 32 byte entry per column:
+//! kind (u16), encoding (u16), reserved (u32), offset (u64), stored length (u64) and uncompressed length (u64) in bytes.
+//! The columns are the unit ID of each event (u8, kind 1), the event offsets (u64, kind 2, one more than there are events),
+//! and the region (u8, kind 3), row (u16, kind 4) and column (u16, kind 5) of each hit.
+//! A column is stored as is (encoding 0) or zlib compressed (encoding 1), and starts at an offset aligned to 8 bytes
+//! so uncompressed columns can be memory-mapped, e.g. with `numpy.memmap`.
+//!
+//! Readers reject files with a newer major version. Minor versions may append fields to the header,
+//! which readers skip using the header length, and add column kinds, which readers ignore.
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+use crate::{MossEventBatch, MossPacket};
+
+/// The first bytes of an event file.
+pub const MAGIC: [u8; 8] = *b"MOSSEVT\0";
+/// Files with a different major version can't be read.
+pub const FORMAT_VERSION_MAJOR: u16 = 1;
+/// Written to new files, files with a newer minor version can still be read.
+pub const FORMAT_VERSION_MINOR: u16 = 0;
+
+/// Size of the fixed fields at the start of the header.
+const FIXED_HEADER_LEN: usize = 36;
+const COLUMN_ENTRY_LEN: usize = 32;
+/// Columns start at offsets aligned to this many bytes.
+const ALIGNMENT: usize = 8;
+/// zlib doesn't compress data by more than this factor, larger uncompressed lengths are invalid.
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+/// The kinds of the columns in a version 1 file, with their name and element size.
+const COLUMNS: [(u16, &str, usize); 5] = [
+    (1, "unit_ids", 1),
+    (2, "event_offsets", 8),
+    (3, "regions", 1),
+    (4, "rows", 2),
+    (5, "columns", 2),
+];
+
+/// How the columns are stored.
+///
+/// Uncompressed by default, as memory-mapping the columns is the fastest way to load large files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnCompression {
+    /// Stored as is so they can be memory-mapped, given as `"none"`.
+    #[default]
+    None,
+    /// zlib compressed for smaller files, given as `"deflate"`.
+    Deflate,
+}
+
+impl ColumnCompression {
+    fn encoding(self) -> u16 {
+        match self {
+            Self::None => 0,
+            Self::Deflate => 1,
+        }
+    }
+}
+
+impl<'source> FromPyObject<'source> for ColumnCompression {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "none" => Ok(Self::None),
+            "deflate" => Ok(Self::Deflate),
+            other => Err(PyValueError::new_err(format!(
+                "Invalid compression {other:?}, expected \"none\" or \"deflate\""
+            ))),
+        }
+    }
+}
+
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// The header of an event file.
+pub struct EventFileInfo {
+    /// Major and minor format version of the file.
+    pub version: (u16, u16),
+    /// Number of events.
+    pub events: usize,
+    /// Number of hits.
+    pub hits: usize,
+    /// Run metadata stored with the events.
+    pub metadata: BTreeMap<String, String>,
+    /// Byte offset of each column stored uncompressed, by name.
+    pub column_offsets: BTreeMap<String, usize>,
+}
+
+#[pymethods]
+impl EventFileInfo {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}(version: {}.{} events: {} hits: {} metadata: {:?})",
+            class_name, slf.version.0, slf.version.1, slf.events, slf.hits, slf.metadata
+        ))
+    }
+}
+
+/// The events to write, either a batch or the packets to fill one with.
+#[derive(Debug, Clone, FromPyObject)]
+pub enum EventFileInput {
+    /// A batch from [decode_all_events_batch](crate::decode_all_events_batch).
+    #[pyo3(transparent, annotation = "MossEventBatch")]
+    Batch(MossEventBatch),
+    /// Packets, each stored as an event.
+    #[pyo3(transparent, annotation = "list[MossPacket]")]
+    Packets(Vec<MossPacket>),
+}
+
+impl EventFileInput {
+    /// The events as a batch.
+    pub fn into_batch(self) -> MossEventBatch {
+        match self {
+            Self::Batch(batch) => batch,
+            Self::Packets(packets) => MossEventBatch::from(packets.as_slice()),
+        }
+    }
+}
+
+fn invalid_data(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+fn padding(len: usize) -> usize {
+    (ALIGNMENT - len % ALIGNMENT) % ALIGNMENT
+}
+
+/// Encodes `batch` as an event file with the run `metadata`.
+pub fn encode_event_file(
+    batch: &MossEventBatch,
+    metadata: &BTreeMap<String, String>,
+    compression: ColumnCompression,
+) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(FIXED_HEADER_LEN);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION_MAJOR.to_le_bytes());
+    header.extend_from_slice(&FORMAT_VERSION_MINOR.to_le_bytes());
+    // Header length, filled in below
+    header.extend_from_slice(&[0; 4]);
+    header.extend_from_slice(&(batch.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(batch.hit_count() as u64).to_le_bytes());
+    header.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    for field in metadata.iter().flat_map(|(key, value)| [key, value]) {
+        header.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        header.extend_from_slice(field.as_bytes());
+    }
+    header.resize(header.len() + padding(header.len()), 0);
+    let header_len = header.len() as u32;
+    header[12..16].copy_from_slice(&header_len.to_le_bytes());
+
+    let event_offsets: Vec<u8> = batch
+        .event_offsets()
+        .iter()
+        .flat_map(|&offset| (offset as u64).to_le_bytes())
+        .collect();
+    let rows: Vec<u8> = batch.rows().iter().flat_map(|r| r.to_le_bytes()).collect();
+    let columns: Vec<u8> = batch
+        .columns()
+        .iter()
+        .flat_map(|c| c.to_le_bytes())
+        .collect();
+    let raw_columns: [&[u8]; 5] = [
+        batch.unit_ids(),
+        &event_offsets,
+        batch.regions(),
+        &rows,
+        &columns,
+    ];
+
+    let table_len = 8 + COLUMNS.len() * COLUMN_ENTRY_LEN;
+    let mut table = Vec::with_capacity(table_len);
+    table.extend_from_slice(&(COLUMNS.len() as u32).to_le_bytes());
+    table.extend_from_slice(&[0; 4]);
+    let mut data = Vec::new();
+    for ((kind, _, _), raw) in COLUMNS.iter().zip(raw_columns) {
+        let stored = match compression {
+            ColumnCompression::None => raw.to_vec(),
+            ColumnCompression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(raw)?;
+                encoder.finish()?
+            }
+        };
+        let offset = header.len() + table_len + data.len();
+        table.extend_from_slice(&kind.to_le_bytes());
+        table.extend_from_slice(&compression.encoding().to_le_bytes());
+        table.extend_from_slice(&[0; 4]);
+        table.extend_from_slice(&(offset as u64).to_le_bytes());
+        table.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        table.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+        data.extend_from_slice(&stored);
+        data.resize(data.len() + padding(data.len()), 0);
+    }
+
+    Ok([header, table, data].concat())
+}
+
+/// Reads the little-endian integers and strings of the header and column table.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> std::io::Result<&'a [u8]> {
+        let field = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| invalid_data("Event file is truncated"))?;
+        self.pos += len;
+        Ok(field)
+    }
+
+    fn u16(&mut self) -> std::io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> std::io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> std::io::Result<usize> {
+        let value = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(value).map_err(|_| invalid_data("Event file field is too large"))
+    }
+
+    fn string(&mut self) -> std::io::Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| invalid_data("Event file metadata is not UTF-8"))
+    }
+}
+
+/// A column table entry.
+struct ColumnEntry {
+    kind: u16,
+    encoding: u16,
+    offset: usize,
+    stored_len: usize,
+    raw_len: usize,
+}
+
+/// Parses the header and column table of an event file.
+fn decode_header(bytes: &[u8]) -> std::io::Result<(EventFileInfo, Vec<ColumnEntry>)> {
+    let mut cursor = Cursor { bytes, pos: 0 };
+    if cursor.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(invalid_data("Not a MOSS event file"));
+    }
+    let version = (cursor.u16()?, cursor.u16()?);
+    if version.0 != FORMAT_VERSION_MAJOR {
+        return Err(invalid_data(format!(
+            "Unsupported event file version {}.{}, this version of moss_decoder reads version {FORMAT_VERSION_MAJOR}.x",
+            version.0, version.1
+        )));
+    }
+    let header_len = cursor.u32()? as usize;
+    let mut info = EventFileInfo {
+        version,
+        events: cursor.u64()?,
+        hits: cursor.u64()?,
+        ..Default::default()
+    };
+    for _ in 0..cursor.u32()? {
+        let key = cursor.string()?;
+        _ = info.metadata.insert(key, cursor.string()?);
+    }
+    if header_len < cursor.pos {
+        return Err(invalid_data("Event file header length is too small"));
+    }
+
+    // Skip header fields added by newer minor versions
+    cursor.pos = header_len;
+    let column_count = cursor.u32()?;
+    _ = cursor.take(4)?;
+    let mut entries = Vec::new();
+    for _ in 0..column_count {
+        let kind = cursor.u16()?;
+        let encoding = cursor.u16()?;
+        _ = cursor.take(4)?;
+        let entry = ColumnEntry {
+            kind,
+            encoding,
+            offset: cursor.u64()?,
+            stored_len: cursor.u64()?,
+            raw_len: cursor.u64()?,
+        };
+        if let Some((_, name, _)) = COLUMNS.iter().find(|(kind, _, _)| *kind == entry.kind) {
+            if entry.encoding == ColumnCompression::None.encoding() {
+                _ = info.column_offsets.insert(name.to_string(), entry.offset);
+            }
+        }
+        entries.push(entry);
+    }
+    Ok((info, entries))
+}
+
+/// Returns the uncompressed bytes of the column of `kind` with `len` elements of `element_size` bytes.
+fn column_bytes(
+    bytes: &[u8],
+    entries: &[ColumnEntry],
+    (kind, name, element_size): (u16, &str, usize),
+    len: usize,
+) -> std::io::Result<Vec<u8>> {
+    let entry = entries
+        .iter()
+        .find(|entry| entry.kind == kind)
+        .ok_or_else(|| invalid_data(format!("Event file has no {name} column")))?;
+    let expected_len = len
+        .checked_mul(element_size)
+        .ok_or_else(|| invalid_data(format!("Event file {name} column is too large")))?;
+    if entry.raw_len != expected_len {
+        return Err(invalid_data(format!(
+            "Event file {name} column has {} bytes, expected {expected_len}",
+            entry.raw_len,
+        )));
+    }
+    let stored = entry
+        .offset
+        .checked_add(entry.stored_len)
+        .and_then(|end| bytes.get(entry.offset..end))
+        .ok_or_else(|| invalid_data(format!("Event file {name} column is truncated")))?;
+    // The lengths come from the file, check them before allocating for the uncompressed column
+    let max_raw_len = match entry.encoding {
+        0 => entry.stored_len,
+        _ => entry.stored_len.saturating_mul(MAX_DEFLATE_RATIO),
+    };
+    if entry.raw_len > max_raw_len {
+        return Err(invalid_data(format!(
+            "Event file {name} column of {} bytes can't hold {} bytes uncompressed",
+            entry.stored_len, entry.raw_len
+        )));
+    }
+    match entry.encoding {
+        0 => Ok(stored.to_vec()),
+        1 => {
+            let mut raw = Vec::new();
+            _ = ZlibDecoder::new(stored)
+                .take(entry.raw_len as u64 + 1)
+                .read_to_end(&mut raw)?;
+            if raw.len() != entry.raw_len {
+                return Err(invalid_data(format!(
+                    "Event file {name} column decompresses to the wrong length"
+                )));
+            }
+            Ok(raw)
+        }
+        other => Err(invalid_data(format!(
+            "Event file {name} column has unknown encoding {other}"
+        ))),
+    }
+}
+
+/// Decodes an event file into the [EventFileInfo] from its header and the stored [MossEventBatch].
+pub fn decode_event_file(bytes: &[u8]) -> std::io::Result<(EventFileInfo, MossEventBatch)> {
+    let (info, entries) = decode_header(bytes)?;
+    let [unit_ids, event_offsets, regions, rows, columns] = COLUMNS;
+    let offsets_len = info
+        .events
+        .checked_add(1)
+        .ok_or_else(|| invalid_data("Event file has too many events"))?;
+    let event_offsets = column_bytes(bytes, &entries, event_offsets, offsets_len)?
+        .chunks_exact(8)
+        .map(|offset| usize::try_from(u64::from_le_bytes(offset.try_into().unwrap())))
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| invalid_data("Event file event offset is too large"))?;
+    let to_u16 = |bytes: Vec<u8>| -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|value| u16::from_le_bytes([value[0], value[1]]))
+            .collect()
+    };
+    let batch = MossEventBatch::from_columns(
+        column_bytes(bytes, &entries, unit_ids, info.events)?,
+        column_bytes(bytes, &entries, regions, info.hits)?,
+        to_u16(column_bytes(bytes, &entries, rows, info.hits)?),
+        to_u16(column_bytes(bytes, &entries, columns, info.hits)?),
+        event_offsets,
+    )
+    .map_err(invalid_data)?;
+    Ok((info, batch))
+}
+
+/// Writes `batch` to an event file at `path` with the run `metadata`, overwriting an existing file.
+///
+/// The version of moss_decoder is added to the metadata as `moss_decoder_version`.
+pub fn write_event_file(
+    path: impl AsRef<Path>,
+    batch: &MossEventBatch,
+    metadata: &BTreeMap<String, String>,
+    compression: ColumnCompression,
+) -> std::io::Result<EventFileInfo> {
+    let mut metadata = metadata.clone();
+    _ = metadata
+        .entry("moss_decoder_version".to_string())
+        .or_insert_with(|| env!("CARGO_PKG_VERSION").to_string());
+    let bytes = encode_event_file(batch, &metadata, compression)?;
+    std::fs::write(path, &bytes)?;
+    let (info, _) = decode_header(&bytes)?;
+    Ok(info)
+}
+
+/// Reads the events of the event file at `path`.
+pub fn read_event_file(path: impl AsRef<Path>) -> std::io::Result<(EventFileInfo, MossEventBatch)> {
+    decode_event_file(&std::fs::read(path)?)
+}
+
+/// Reads only the header of the event file at `path`.
+pub fn read_event_file_info(path: impl AsRef<Path>) -> std::io::Result<EventFileInfo> {
+    let mut file = std::fs::File::open(path)?;
+    let mut start = vec![0; FIXED_HEADER_LEN];
+    file.read_exact(&mut start)
+        .map_err(|_| invalid_data("Event file is truncated"))?;
+    let header_len = u32::from_le_bytes(start[12..16].try_into().unwrap()) as usize;
+    let column_count = file_u32_at(&mut file, header_len)? as usize;
+    let len = column_count
+        .checked_mul(COLUMN_ENTRY_LEN)
+        .and_then(|table_len| table_len.checked_add(header_len + 8))
+        .ok_or_else(|| invalid_data("Event file is truncated"))?;
+    if len as u64 > file.metadata()?.len() {
+        return Err(invalid_data("Event file is truncated"));
+    }
+    let mut header = vec![0; len];
+    std::io::Seek::rewind(&mut file)?;
+    file.read_exact(&mut header)
+        .map_err(|_| invalid_data("Event file is truncated"))?;
+    Ok(decode_header(&header)?.0)
+}
+
+fn file_u32_at(file: &mut std::fs::File, offset: usize) -> std::io::Result<u32> {
+    let mut value = [0; 4];
+    _ = std::io::Seek::seek(file, std::io::SeekFrom::Start(offset as u64))?;
+    file.read_exact(&mut value)
+        .map_err(|_| invalid_data("Event file is truncated"))?;
+    Ok(u32::from_le_bytes(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MossHit;
+    use pretty_assertions::assert_eq;
+
+    fn batch() -> MossEventBatch {
+        let mut packet = MossPacket::new(3);
+        packet.hits = vec![
+            MossHit {
+                region: 1,
+                row: 300,
+                column: 7,
+            },
+            MossHit {
+                region: 2,
+                row: 5,
+                column: 511,
+            },
+        ];
+        MossEventBatch::from([packet.clone(), MossPacket::new(4), packet].as_slice())
+    }
+
+    #[test]
+    fn test_round_trip_both_compressions() {
+        let metadata = BTreeMap::from([("run".to_string(), "42".to_string())]);
+        for compression in [ColumnCompression::None, ColumnCompression::Deflate] {
+            let bytes = encode_event_file(&batch(), &metadata, compression).unwrap();
+            let (info, decoded) = decode_event_file(&bytes).unwrap();
+            assert_eq!(decoded, batch());
+            assert_eq!(info.version, (FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR));
+            assert_eq!((info.events, info.hits), (3, 4));
+            assert_eq!(info.metadata, metadata);
+            assert_eq!(
+                info.column_offsets.len(),
+                if compression == ColumnCompression::None {
+                    5
+                } else {
+                    0
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_columns_are_aligned() {
+        let bytes = encode_event_file(&batch(), &BTreeMap::new(), ColumnCompression::None).unwrap();
+        let (info, _) = decode_event_file(&bytes).unwrap();
+        let rows = info.column_offsets["rows"];
+        assert_eq!(rows % ALIGNMENT, 0);
+        assert_eq!(u16::from_le_bytes([bytes[rows], bytes[rows + 1]]), 300);
+    }
+
+    #[test]
+    fn test_reads_newer_minor_version() {
+        // A newer minor version with an extra header field and an extra column appended
+        let bytes = encode_event_file(&batch(), &BTreeMap::new(), ColumnCompression::None).unwrap();
+        let header_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let mut newer = bytes[..header_len].to_vec();
+        newer[10..12].copy_from_slice(&7u16.to_le_bytes());
+        newer[12..16].copy_from_slice(&(header_len as u32 + 8).to_le_bytes());
+        newer.extend_from_slice(&[0xAB; 8]);
+        let shift = 8 + COLUMN_ENTRY_LEN;
+        let table = &bytes[header_len..];
+        newer.extend_from_slice(&6u32.to_le_bytes());
+        newer.extend_from_slice(&[0; 4]);
+        for entry in table[8..8 + 5 * COLUMN_ENTRY_LEN].chunks(COLUMN_ENTRY_LEN) {
+            let mut entry = entry.to_vec();
+            let offset = u64::from_le_bytes(entry[8..16].try_into().unwrap()) + shift as u64;
+            entry[8..16].copy_from_slice(&offset.to_le_bytes());
+            newer.extend_from_slice(&entry);
+        }
+        // Unknown column kind with an unknown encoding
+        newer.extend_from_slice(&99u16.to_le_bytes());
+        newer.extend_from_slice(&9u16.to_le_bytes());
+        newer.extend_from_slice(&[0; 28]);
+        newer.extend_from_slice(&table[8 + 5 * COLUMN_ENTRY_LEN..]);
+
+        let (info, decoded) = decode_event_file(&newer).unwrap();
+        assert_eq!(info.version, (1, 7));
+        assert_eq!(decoded, batch());
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        let bytes =
+            encode_event_file(&batch(), &BTreeMap::new(), ColumnCompression::Deflate).unwrap();
+        let mut newer_major = bytes.clone();
+        newer_major[8..10].copy_from_slice(&2u16.to_le_bytes());
+        assert!(decode_event_file(&newer_major)
+            .unwrap_err()
+            .to_string()
+            .contains("version 2.0"));
+        assert!(decode_event_file(&bytes[..bytes.len() - 10]).is_err());
+        assert!(decode_event_file(b"MOSS").is_err());
+        assert!(decode_event_file(&[0; 64]).is_err());
+    }
+
+    /// Returns the position of the column table entry of `kind` in `bytes`.
+    fn column_entry(bytes: &[u8], kind: u16) -> usize {
+        let header_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        (header_len + 8..)
+            .step_by(COLUMN_ENTRY_LEN)
+            .find(|&entry| bytes[entry..entry + 2] == kind.to_le_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rejects_event_count_overflow() {
+        let mut bytes =
+            encode_event_file(&batch(), &BTreeMap::new(), ColumnCompression::None).unwrap();
+        bytes[16..24].copy_from_slice(&(usize::MAX as u64).to_le_bytes());
+        assert!(decode_event_file(&bytes)
+            .unwrap_err()
+            .to_string()
+            .contains("too many events"));
+    }
+
+    #[test]
+    fn test_rejects_column_length_overflow() {
+        let mut bytes =
+            encode_event_file(&batch(), &BTreeMap::new(), ColumnCompression::None).unwrap();
+        // The event offsets column has 8 bytes per element
+        bytes[16..24].copy_from_slice(&(usize::MAX as u64 / 8).to_le_bytes());
+        assert!(decode_event_file(&bytes)
+            .unwrap_err()
+            .to_string()
+            .contains("event_offsets column is too large"));
+    }
+
+    #[test]
+    fn test_rejects_uncompressed_length_larger_than_stored() {
+        let (kind, name, element_size) = COLUMNS[3];
+        let hits = 1 << 40;
+        for compression in [ColumnCompression::None, ColumnCompression::Deflate] {
+            let mut bytes = encode_event_file(&batch(), &BTreeMap::new(), compression).unwrap();
+            let (_, entries) = decode_header(&bytes).unwrap();
+            let entry = column_entry(&bytes, kind);
+            bytes[entry + 24..entry + 32]
+                .copy_from_slice(&(hits as u64 * element_size as u64).to_le_bytes());
+            let (_, patched) = decode_header(&bytes).unwrap();
+            let stored_len = entries.iter().find(|e| e.kind == kind).unwrap().stored_len;
+            let err = column_bytes(&bytes, &patched, COLUMNS[3], hits).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "Event file {name} column of {stored_len} bytes can't hold {} bytes uncompressed",
+                    hits * element_size
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_file() {
+        let path = std::env::temp_dir().join(format!("moss_event_file_{}.mev", std::process::id()));
+        let written =
+            write_event_file(&path, &batch(), &BTreeMap::new(), ColumnCompression::None).unwrap();
+        assert_eq!(
+            written.metadata["moss_decoder_version"],
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(read_event_file_info(&path).unwrap(), written);
+        let (info, decoded) = read_event_file(&path).unwrap();
+        assert_eq!(info, written);
+        assert_eq!(decoded, batch());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
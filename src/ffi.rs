@@ -0,0 +1,383 @@
+//! C ABI over [MossDecoder] for DAQ frameworks that can't call Python, declared in `include/moss_decoder.h`.
+//!
+//! The header is generated from this module with cbindgen, see `cbindgen.toml`, the item docs are copied into it
+//! and refer to the C names.
+//!
+//! A decoder handle is created with [moss_decoder_new], fed chunks of raw data with [moss_decoder_feed],
+//! and the decoded packets are copied into caller-provided arrays with [moss_decoder_poll].
+//! All functions return a [MossStatus], the message of the last error on the calling thread is returned by [moss_last_error].
+//!
+//! None of the functions need a Python interpreter, but the library links to `libpython` unless built as an extension module.
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr, CString};
+
+use crate::{FramingConfig, MossDecoder, MossPacket};
+
+/// Status code returned by the C functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MossStatus {
+    /// Success.
+    Ok = 0,
+    /// A required pointer argument was NULL.
+    NullPointer = 1,
+    /// An argument has an invalid value, e.g. an invalid framing.
+    InvalidArgument = 2,
+    /// The next packet has more hits than fit in the hits array.
+    BufferTooSmall = 3,
+    /// The fed data contained protocol errors, the decoder recovered and decoded the valid events.
+    Protocol = 4,
+}
+
+/// A hit as copied to C, the same fields as `MossHit` of the Rust and Python API.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MossCHit {
+    /// The region ID of the hit.
+    pub region: u8,
+    /// The row of the hit.
+    pub row: u16,
+    /// The column of the hit.
+    pub column: u16,
+}
+
+/// A packet as copied to C, its hits are the `hit_count` hits from index `first_hit` of the hits array of the same poll.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MossCPacket {
+    /// The unit ID of the packet.
+    pub unit_id: u8,
+    /// Index of the first hit of the packet in the hits array.
+    pub first_hit: usize,
+    /// Number of hits of the packet.
+    pub hit_count: usize,
+}
+
+/// The statistics of a decoder as copied to C, the same fields as `StreamStats` of the Rust and Python API.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MossCStats {
+    /// Number of bytes fed to the decoder.
+    pub bytes: usize,
+    /// Number of decoded packets.
+    pub packets: usize,
+    /// Number of decoded hits.
+    pub hits: usize,
+    /// Number of protocol errors the decoder recovered from.
+    pub protocol_errors: usize,
+}
+
+/// The decoder handle, opaque to C.
+#[derive(Debug, Default)]
+pub struct MossCDecoder {
+    decoder: MossDecoder,
+    /// Decoded packets not yet polled.
+    pending: VecDeque<MossPacket>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(status: MossStatus, msg: impl Into<Vec<u8>>) -> MossStatus {
+    let msg = CString::new(msg).unwrap_or_else(|_| c"Error message contains a NUL byte".to_owned());
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(msg));
+    status
+}
+
+/// The version of the library as a NUL-terminated string.
+#[no_mangle]
+pub extern "C" fn moss_decoder_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// The message of the last error on the calling thread, or NULL if there was none.
+///
+/// The string is valid until the next call of a function of the library on the same thread.
+#[no_mangle]
+pub extern "C" fn moss_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_deref()
+            .map_or(std::ptr::null(), CStr::as_ptr)
+    })
+}
+
+/// Create a decoder with the default framing, free it with `moss_decoder_free()`.
+///
+/// # Safety
+/// `decoder` must be NULL or valid for writing a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_new(decoder: *mut *mut MossCDecoder) -> MossStatus {
+    if decoder.is_null() {
+        return set_last_error(MossStatus::NullPointer, "decoder is NULL");
+    }
+    *decoder = Box::into_raw(Box::default());
+    MossStatus::Ok
+}
+
+/// Create a decoder with the given framing, see `FramingConfig` of the Python API, free it with `moss_decoder_free()`.
+///
+/// # Safety
+/// `filler_bytes` must be valid for reading `filler_len` bytes, it may be NULL if `filler_len` is 0.
+/// `decoder` must be NULL or valid for writing a pointer.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_new_with_framing(
+    filler_bytes: *const u8,
+    filler_len: usize,
+    alignment: usize,
+    allow_leading_trailer: bool,
+    decoder: *mut *mut MossCDecoder,
+) -> MossStatus {
+    if decoder.is_null() || (filler_bytes.is_null() && filler_len > 0) {
+        return set_last_error(MossStatus::NullPointer, "decoder or filler_bytes is NULL");
+    }
+    let filler_bytes = if filler_len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(filler_bytes, filler_len)
+    };
+    match FramingConfig::try_new(
+        filler_bytes.iter().copied(),
+        alignment,
+        allow_leading_trailer,
+    ) {
+        Ok(framing) => {
+            *decoder = Box::into_raw(Box::new(MossCDecoder {
+                decoder: MossDecoder::new(framing),
+                pending: VecDeque::new(),
+            }));
+            MossStatus::Ok
+        }
+        Err(msg) => set_last_error(MossStatus::InvalidArgument, msg),
+    }
+}
+
+/// Free a decoder created with `moss_decoder_new()` or `moss_decoder_new_with_framing()`, NULL is ignored.
+///
+/// # Safety
+/// `decoder` must be NULL or a decoder that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_free(decoder: *mut MossCDecoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+/// Decode the complete events in the next chunk of raw data, the packets are retrieved with `moss_decoder_poll()`.
+///
+/// Returns `MOSS_STATUS_PROTOCOL` if the chunk contained protocol errors, the valid events are still decoded
+/// and must still be polled.
+///
+/// # Safety
+/// `decoder` must be NULL or a valid decoder, `data` must be valid for reading `len` bytes, it may be NULL if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_feed(
+    decoder: *mut MossCDecoder,
+    data: *const u8,
+    len: usize,
+) -> MossStatus {
+    let Some(decoder) = decoder.as_mut() else {
+        return set_last_error(MossStatus::NullPointer, "decoder is NULL");
+    };
+    if len == 0 {
+        return MossStatus::Ok;
+    }
+    if data.is_null() {
+        return set_last_error(MossStatus::NullPointer, "data is NULL");
+    }
+    let errors_before = decoder.decoder.stats().protocol_errors;
+    let packets = decoder.decoder.feed(std::slice::from_raw_parts(data, len));
+    decoder.pending.extend(packets);
+    let stats = decoder.decoder.stats();
+    if stats.protocol_errors > errors_before {
+        let msg = stats.last_error.clone().unwrap_or_default();
+        return set_last_error(MossStatus::Protocol, msg);
+    }
+    MossStatus::Ok
+}
+
+/// The number of decoded packets not yet polled, 0 if `decoder` is NULL.
+///
+/// # Safety
+/// `decoder` must be NULL or a valid decoder.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_pending(decoder: *const MossCDecoder) -> usize {
+    decoder.as_ref().map_or(0, |decoder| decoder.pending.len())
+}
+
+/// Copy as many decoded packets and their hits as fit into the `packets` and `hits` arrays, oldest first.
+///
+/// The number of packets and hits written are stored in `packets_written` and `hits_written`.
+/// Returns `MOSS_STATUS_BUFFER_TOO_SMALL` if no packet was written because the next packet has more hits than `max_hits`.
+///
+/// # Safety
+/// `decoder` must be NULL or a valid decoder. `packets` and `hits` must be valid for writing `max_packets` and `max_hits` elements,
+/// they may be NULL if the maximum is 0. `packets_written` and `hits_written` must be NULL or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_poll(
+    decoder: *mut MossCDecoder,
+    packets: *mut MossCPacket,
+    max_packets: usize,
+    hits: *mut MossCHit,
+    max_hits: usize,
+    packets_written: *mut usize,
+    hits_written: *mut usize,
+) -> MossStatus {
+    let Some(decoder) = decoder.as_mut() else {
+        return set_last_error(MossStatus::NullPointer, "decoder is NULL");
+    };
+    if packets_written.is_null()
+        || hits_written.is_null()
+        || (packets.is_null() && max_packets > 0)
+        || (hits.is_null() && max_hits > 0)
+    {
+        return set_last_error(MossStatus::NullPointer, "Output pointer is NULL");
+    }
+    let (mut packet_cnt, mut hit_cnt) = (0, 0);
+    while packet_cnt < max_packets {
+        let Some(packet) = decoder.pending.front() else {
+            break;
+        };
+        if hit_cnt + packet.hits.len() > max_hits {
+            break;
+        }
+        let packet = decoder.pending.pop_front().expect("front exists");
+        *packets.add(packet_cnt) = MossCPacket {
+            unit_id: packet.unit_id,
+            first_hit: hit_cnt,
+            hit_count: packet.hits.len(),
+        };
+        for hit in packet.hits {
+            *hits.add(hit_cnt) = MossCHit {
+                region: hit.region,
+                row: hit.row,
+                column: hit.column,
+            };
+            hit_cnt += 1;
+        }
+        packet_cnt += 1;
+    }
+    *packets_written = packet_cnt;
+    *hits_written = hit_cnt;
+    match decoder.pending.front() {
+        Some(packet) if packet_cnt == 0 && max_packets > 0 => set_last_error(
+            MossStatus::BufferTooSmall,
+            format!(
+                "The next packet has {} hits, the hits array holds {max_hits}",
+                packet.hits.len()
+            ),
+        ),
+        _ => MossStatus::Ok,
+    }
+}
+
+/// Copy the statistics of the decoder to `stats`.
+///
+/// # Safety
+/// `decoder` must be NULL or a valid decoder, `stats` must be NULL or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_stats(
+    decoder: *const MossCDecoder,
+    stats: *mut MossCStats,
+) -> MossStatus {
+    let (Some(decoder), Some(stats)) = (decoder.as_ref(), stats.as_mut()) else {
+        return set_last_error(MossStatus::NullPointer, "decoder or stats is NULL");
+    };
+    let decoder_stats = decoder.decoder.stats();
+    *stats = MossCStats {
+        bytes: decoder_stats.bytes,
+        packets: decoder_stats.packets,
+        hits: decoder_stats.hits,
+        protocol_errors: decoder_stats.protocol_errors,
+    };
+    MossStatus::Ok
+}
+
+/// Discard the partial event, the packets not yet polled and reset the statistics.
+///
+/// # Safety
+/// `decoder` must be NULL or a valid decoder.
+#[no_mangle]
+pub unsafe extern "C" fn moss_decoder_reset(decoder: *mut MossCDecoder) -> MossStatus {
+    let Some(decoder) = decoder.as_mut() else {
+        return set_last_error(MossStatus::NullPointer, "decoder is NULL");
+    };
+    decoder.decoder.reset();
+    decoder.pending.clear();
+    MossStatus::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_feed_and_poll() {
+        let bytes = fake_event_simple().repeat(3);
+        let (expected, _) = crate::decode_all_events(&bytes, None).unwrap();
+        let hits_per_packet = expected[0].hits.len();
+        let mut packets = [MossCPacket::default(); 2];
+        let mut hits = vec![MossCHit::default(); 2 * hits_per_packet];
+        let (mut packets_written, mut hits_written) = (0, 0);
+
+        unsafe {
+            let mut decoder = std::ptr::null_mut();
+            assert_eq!(moss_decoder_new(&mut decoder), MossStatus::Ok);
+            assert_eq!(
+                moss_decoder_feed(decoder, bytes.as_ptr(), bytes.len()),
+                MossStatus::Ok
+            );
+            assert_eq!(moss_decoder_pending(decoder), 3);
+
+            let status = moss_decoder_poll(
+                decoder,
+                packets.as_mut_ptr(),
+                packets.len(),
+                hits.as_mut_ptr(),
+                hits.len(),
+                &mut packets_written,
+                &mut hits_written,
+            );
+            assert_eq!(status, MossStatus::Ok);
+            assert_eq!((packets_written, hits_written), (2, 2 * hits_per_packet));
+            assert_eq!(packets[1].first_hit, hits_per_packet);
+            assert_eq!(packets[1].unit_id, expected[1].unit_id);
+            assert_eq!(hits[0].row, expected[0].hits[0].row);
+
+            // The last packet doesn't fit in a hits array of one hit
+            let status = moss_decoder_poll(
+                decoder,
+                packets.as_mut_ptr(),
+                packets.len(),
+                hits.as_mut_ptr(),
+                hits_per_packet - 1,
+                &mut packets_written,
+                &mut hits_written,
+            );
+            assert_eq!(status, MossStatus::BufferTooSmall);
+            assert!(!moss_last_error().is_null());
+
+            let mut stats = MossCStats::default();
+            assert_eq!(moss_decoder_stats(decoder, &mut stats), MossStatus::Ok);
+            assert_eq!(stats.packets, 3);
+            assert_eq!(moss_decoder_reset(decoder), MossStatus::Ok);
+            assert_eq!(moss_decoder_pending(decoder), 0);
+            moss_decoder_free(decoder);
+
+            let mut decoder = std::ptr::null_mut();
+            let filler = [0xE0];
+            let status = moss_decoder_new_with_framing(filler.as_ptr(), 1, 1, true, &mut decoder);
+            assert_eq!(status, MossStatus::InvalidArgument);
+            assert!(decoder.is_null());
+            assert_eq!(
+                moss_decoder_feed(decoder, bytes.as_ptr(), bytes.len()),
+                MossStatus::NullPointer
+            );
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Configuration of the framing the readout FPGA adds around MOSS events.
+//!
+//! The filler bytes between events and the padding after events are not part of the MOSS protocol,
+//! they are implementation details of the readout firmware and differ between firmware versions.
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use crate::moss_protocol::MossWord;
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Describes how MOSS events are framed in the data stream.
+///
+/// - `filler_bytes`: Bytes that are allowed between events, the default is `0xFA`.
+/// - `alignment`: Each event, from the Unit Frame Header to the Unit Frame Trailer, is padded to a multiple of `alignment` bytes.
+///   The padding directly follows the trailer and can have any value. The default of 1 means no padding.
+///   With padding the decoders return the index of the last padding byte of an event instead of the index of its trailer.
+/// - `allow_leading_trailer`: Whether the first byte may be a Unit Frame Trailer, e.g. left over from a previous event. Default is true.
+pub struct FramingConfig {
+    /// Bitmap of the allowed filler bytes, bit `b % 64` of word `b / 64` is set if `b` is allowed
+    filler_bytes: [u64; 4],
+    alignment: usize,
+    allow_leading_trailer: bool,
+}
+
+impl Default for FramingConfig {
+    fn default() -> Self {
+        Self::new([MossWord::DELIMITER], 1, true).expect("default framing is valid")
+    }
+}
+
+impl FramingConfig {
+    /// Create a new framing configuration.
+    ///
+    /// Returns an error if `alignment` is zero or if a filler byte could be mistaken for a Unit Frame Header or Trailer.
+    pub fn new(
+        filler_bytes: impl IntoIterator<Item = u8>,
+        alignment: usize,
+        allow_leading_trailer: bool,
+    ) -> PyResult<Self> {
+        Self::try_new(filler_bytes, alignment, allow_leading_trailer).map_err(PyValueError::new_err)
+    }
+
+    /// Same as [Self::new] but the error is a plain message, for callers without a Python interpreter.
+    pub(crate) fn try_new(
+        filler_bytes: impl IntoIterator<Item = u8>,
+        alignment: usize,
+        allow_leading_trailer: bool,
+    ) -> Result<Self, String> {
+        if alignment == 0 {
+            return Err("alignment must be greater than 0".to_owned());
+        }
+        let mut bitmap = [0; 4];
+        for b in filler_bytes {
+            if MossWord::UNIT_FRAME_HEADER_RANGE.contains(&b) || b == MossWord::UNIT_FRAME_TRAILER {
+                return Err(format!(
+                    "0x{b:02X} cannot be used as a filler byte, it is a Unit Frame Header or Trailer"
+                ));
+            }
+            bitmap[usize::from(b / 64)] |= 1 << (b % 64);
+        }
+        Ok(Self {
+            filler_bytes: bitmap,
+            alignment,
+            allow_leading_trailer,
+        })
+    }
+
+    /// Returns true if `b` is allowed between events.
+    #[inline]
+    pub fn is_filler(&self, b: u8) -> bool {
+        self.filler_bytes[usize::from(b / 64)] & (1 << (b % 64)) != 0
+    }
+
+    /// The bytes that are allowed between events in ascending order.
+    pub fn filler_bytes(&self) -> Vec<u8> {
+        (0..=u8::MAX).filter(|&b| self.is_filler(b)).collect()
+    }
+
+    /// The number of bytes each event is padded to a multiple of.
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+
+    /// Whether the first byte may be a Unit Frame Trailer.
+    pub fn allow_leading_trailer(&self) -> bool {
+        self.allow_leading_trailer
+    }
+
+    /// The number of padding bytes following the trailer of an event that is `event_len` bytes long.
+    #[inline]
+    pub fn padding_after(&self, event_len: usize) -> usize {
+        (self.alignment - event_len % self.alignment) % self.alignment
+    }
+}
+
+#[pymethods]
+impl FramingConfig {
+    #[new]
+    #[pyo3(signature = (filler_bytes = vec![MossWord::DELIMITER], alignment = 1, allow_leading_trailer = true))]
+    fn py_new(
+        filler_bytes: Vec<u8>,
+        alignment: usize,
+        allow_leading_trailer: bool,
+    ) -> PyResult<Self> {
+        Self::new(filler_bytes, alignment, allow_leading_trailer)
+    }
+
+    #[getter(filler_bytes)]
+    fn py_filler_bytes(&self) -> Vec<u8> {
+        self.filler_bytes()
+    }
+
+    #[getter(alignment)]
+    fn py_alignment(&self) -> usize {
+        self.alignment
+    }
+
+    #[getter(allow_leading_trailer)]
+    fn py_allow_leading_trailer(&self) -> bool {
+        self.allow_leading_trailer
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for FramingConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "filler_bytes: [{fillers}], alignment: {alignment}, allow_leading_trailer: {leading_trailer}",
+                fillers = self
+                    .filler_bytes()
+                    .iter()
+                    .map(|b| format!("0x{b:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                alignment = self.alignment,
+                leading_trailer = self.allow_leading_trailer
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_framing() {
+        let framing = FramingConfig::default();
+        assert_eq!(framing.filler_bytes(), vec![0xFA]);
+        assert!(framing.is_filler(0xFA));
+        assert!(!framing.is_filler(0xFF));
+        assert_eq!(framing.padding_after(7), 0);
+        assert!(framing.allow_leading_trailer());
+    }
+
+    #[test]
+    fn test_padding_after() {
+        let framing = FramingConfig::new([0x00, 0xFA, 0xFF], 4, false).unwrap();
+        assert_eq!(framing.filler_bytes(), vec![0x00, 0xFA, 0xFF]);
+        assert_eq!(framing.padding_after(2), 2);
+        assert_eq!(framing.padding_after(4), 0);
+        assert_eq!(framing.padding_after(5), 3);
+    }
+
+    #[test]
+    fn test_invalid_framing() {
+        pyo3::prepare_freethreaded_python();
+        assert!(FramingConfig::new([0xFA], 0, true).is_err());
+        assert!(FramingConfig::new([0xD3], 1, true).is_err());
+        assert!(FramingConfig::new([MossWord::UNIT_FRAME_TRAILER], 1, true).is_err());
+    }
+}
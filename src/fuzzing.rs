@@ -0,0 +1,52 @@
+//! Entry points into the internal decoding functions for the fuzz targets in `fuzz/` and the property tests.
+//!
+//! Not part of the public API, errors are returned as strings.
+use crate::{DecodeOptions, FramingConfig, MossPacket};
+
+/// Calls the internal `extract_packet_from_buf`.
+pub fn extract_packet_from_buf(
+    bytes: &[u8],
+    prepend_bytes: Option<Vec<u8>>,
+    framing: &FramingConfig,
+) -> Result<(MossPacket, usize), String> {
+    crate::rust_only::extract_packet_from_buf(bytes, prepend_bytes, framing)
+        .map_err(|e| e.to_string())
+}
+
+/// Decodes all events in `bytes` with the decoding loop of [decode_options](crate::decode_options),
+/// returns the packets and the end of the last event.
+pub fn get_all_packets_from_buf(
+    bytes: &[u8],
+    framing: &FramingConfig,
+) -> Result<(Vec<MossPacket>, usize), String> {
+    // Errors of the decoding loop are Python exceptions, formatting them requires the interpreter
+    pyo3::prepare_freethreaded_python();
+    let mut packets = Vec::new();
+    let (_, events_end) = crate::decode_options::decode_bytes_to_events_end(
+        bytes,
+        &DecodeOptions::default(),
+        framing,
+        &mut packets,
+    )
+    .map_err(|e| e.to_string())?;
+    Ok((packets, events_end))
+}
+
+/// Calls the internal `debug_decode_event`, returns the invalid words as error messages.
+pub fn debug_decode_event(
+    bytes: &[u8],
+    framing: &FramingConfig,
+) -> Result<(MossPacket, usize, Vec<String>), String> {
+    crate::debug_decode::debug_decode_event(bytes, framing)
+        .map(|(packet, trailer_idx, invalid_words)| {
+            (
+                packet,
+                trailer_idx,
+                invalid_words
+                    .into_iter()
+                    .map(|w| w.to_error_msg())
+                    .collect(),
+            )
+        })
+        .map_err(|(e, _)| e.to_string())
+}
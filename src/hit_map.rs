@@ -0,0 +1,251 @@
+//! Per-pixel hit counts accumulated while decoding, e.g. to find noisy pixels during a run.
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::{write, Display};
+
+use crate::sink::{PacketSink, SinkControl};
+use crate::{MossHit, MossPacket};
+
+/// Number of regions of a MOSS unit.
+const REGIONS: usize = 4;
+/// Rows and columns are 9 bits wide in the hit encoding.
+const MATRIX_SIZE: usize = 512;
+const REGION_PIXELS: usize = MATRIX_SIZE * MATRIX_SIZE;
+
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Counts the hits of each pixel of each unit, hits outside the pixel matrix are ignored.
+///
+/// Implements [PacketSink] so it can be filled by the decoding loop without calling into Python for each packet.
+pub struct HitMap {
+    /// Hit counts of each unit indexed by `region * 512 * 512 + row * 512 + column`, allocated on the first hit of a unit.
+    counts: BTreeMap<u8, Vec<u32>>,
+    /// Number of packets added.
+    #[pyo3(get)]
+    pub events: usize,
+    /// Number of hits counted.
+    #[pyo3(get)]
+    pub hits: usize,
+}
+
+fn pixel_index(hit: &MossHit) -> Option<usize> {
+    let (region, row, column) = (hit.region as usize, hit.row as usize, hit.column as usize);
+    (region < REGIONS && row < MATRIX_SIZE && column < MATRIX_SIZE)
+        .then_some(region * REGION_PIXELS + row * MATRIX_SIZE + column)
+}
+
+impl HitMap {
+    /// Count the hits of `packet`.
+    pub fn add(&mut self, packet: &MossPacket) {
+        self.events += 1;
+        let counts = self
+            .counts
+            .entry(packet.unit_id)
+            .or_insert_with(|| vec![0; REGIONS * REGION_PIXELS]);
+        for idx in packet.hits.iter().filter_map(pixel_index) {
+            counts[idx] += 1;
+            self.hits += 1;
+        }
+    }
+
+    /// The number of hits of a pixel of a unit.
+    pub fn get(&self, unit_id: u8, hit: &MossHit) -> u32 {
+        self.counts
+            .get(&unit_id)
+            .zip(pixel_index(hit))
+            .map_or(0, |(counts, idx)| counts[idx])
+    }
+
+    /// Iterate over the pixels with at least one hit as `(unit_id, hit, count)`.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, MossHit, u32)> + '_ {
+        self.counts.iter().flat_map(|(&unit_id, counts)| {
+            counts
+                .iter()
+                .enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(move |(idx, &count)| {
+                    let hit = MossHit {
+                        region: (idx / REGION_PIXELS) as u8,
+                        row: (idx % REGION_PIXELS / MATRIX_SIZE) as u16,
+                        column: (idx % MATRIX_SIZE) as u16,
+                    };
+                    (unit_id, hit, count)
+                })
+        })
+    }
+}
+
+/// A [HitMap] as serialized, with only the pixels that have hits instead of the dense per-unit counts.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SparseHitMap {
+    events: usize,
+    hits: usize,
+    /// `(unit_id, hit, count)` of each pixel with at least one hit.
+    pixels: Vec<(u8, MossHit, u32)>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HitMap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SparseHitMap {
+            events: self.events,
+            hits: self.hits,
+            pixels: self.iter().collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HitMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let sparse = SparseHitMap::deserialize(deserializer)?;
+        let mut hit_map = Self {
+            counts: BTreeMap::new(),
+            events: sparse.events,
+            hits: sparse.hits,
+        };
+        for (unit_id, hit, count) in sparse.pixels {
+            let idx = pixel_index(&hit).ok_or_else(|| {
+                serde::de::Error::custom(format!("Pixel {hit} is outside the pixel matrix"))
+            })?;
+            hit_map
+                .counts
+                .entry(unit_id)
+                .or_insert_with(|| vec![0; REGIONS * REGION_PIXELS])[idx] = count;
+        }
+        Ok(hit_map)
+    }
+}
+
+#[pymethods]
+impl HitMap {
+    #[new]
+    fn py_new() -> Self {
+        Self::default()
+    }
+
+    /// Count the hits of a packet.
+    #[pyo3(name = "add")]
+    fn py_add(&mut self, packet: &MossPacket) {
+        self.add(packet);
+    }
+
+    /// The number of hits of a pixel of a unit.
+    fn count(&self, unit_id: u8, region: u8, row: u16, column: u16) -> u32 {
+        self.get(
+            unit_id,
+            &MossHit {
+                region,
+                row,
+                column,
+            },
+        )
+    }
+
+    /// The IDs of the units with at least one packet.
+    fn unit_ids(&self) -> Vec<u8> {
+        self.counts.keys().copied().collect()
+    }
+
+    /// The hit counts of a region of a unit as a list of 512 rows of 512 columns.
+    fn region_map(&self, unit_id: u8, region: u8) -> Vec<Vec<u32>> {
+        let region = region as usize;
+        match self.counts.get(&unit_id) {
+            Some(counts) if region < REGIONS => counts
+                [region * REGION_PIXELS..(region + 1) * REGION_PIXELS]
+                .chunks(MATRIX_SIZE)
+                .map(<[u32]>::to_vec)
+                .collect(),
+            _ => vec![vec![0; MATRIX_SIZE]; MATRIX_SIZE],
+        }
+    }
+
+    /// The `n` pixels with the most hits as `(unit_id, hit, count)`, most hits first.
+    fn hottest(&self, n: usize) -> Vec<(u8, MossHit, u32)> {
+        let mut pixels: Vec<_> = self.iter().collect();
+        pixels.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| (a.0, a.1).cmp(&(b.0, b.1))));
+        pixels.truncate(n);
+        pixels
+    }
+
+    /// Reset all counts.
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl PacketSink for HitMap {
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl> {
+        self.add(&packet);
+        Ok(SinkControl::Continue)
+    }
+}
+
+impl Display for HitMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Events: {events} Hits: {hits} Units: {units:?}",
+                events = self.events,
+                hits = self.hits,
+                units = self.counts.keys().collect::<Vec<_>>()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_options::{decode_bytes_into, DecodeOptions};
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use crate::FramingConfig;
+
+    #[test]
+    fn test_hit_map_sink() {
+        let bytes = fake_event_simple().repeat(3);
+        let mut hit_map = HitMap::default();
+        _ = decode_bytes_into(
+            &bytes,
+            &DecodeOptions::default(),
+            &FramingConfig::default(),
+            &mut hit_map,
+        )
+        .unwrap();
+
+        let packets = crate::decode_options::decode_bytes(
+            &fake_event_simple(),
+            &DecodeOptions::default(),
+            &FramingConfig::default(),
+        )
+        .unwrap()
+        .packets;
+        assert_eq!(hit_map.events, 3);
+        assert_eq!(hit_map.hits, 3 * packets[0].hits.len());
+        let unit_id = packets[0].unit_id;
+        for hit in &packets[0].hits {
+            assert_eq!(hit_map.get(unit_id, hit), 3);
+        }
+        assert_eq!(hit_map.iter().count(), packets[0].hits.len());
+        assert_eq!(hit_map.hottest(1)[0].2, 3);
+
+        let outside = MossHit {
+            region: 4,
+            row: 0,
+            column: 0,
+        };
+        assert_eq!(hit_map.get(unit_id, &outside), 0);
+    }
+}
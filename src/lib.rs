@@ -1,5 +1,6 @@
 //! A Python module for decoding raw MOSS data implemented in Rust.
-#![forbid(unused_extern_crates)]
+// Not `forbid` as the code generated by the serde derive macros allows it locally
+#![deny(unused_extern_crates)]
 #![deny(missing_docs)]
 #![warn(missing_copy_implementations)]
 #![warn(trivial_casts, trivial_numeric_casts)]
@@ -20,34 +21,122 @@
     clippy::mem_forget,
     clippy::maybe_infinite_iter
 )]
+// The `#[pymethods]` expansion of pyo3 0.19 trips this lint on newer compilers
+#![allow(non_local_definitions)]
 
 pub use moss_protocol::MossPacket;
-use parse_error::ParseErrorKind;
-use parse_util::find_trailer_n_idx;
 use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyFileNotFoundError, PyValueError};
 use pyo3::prelude::*;
-use std::io::Read;
+use std::io::{Read, Seek};
 
 pub mod moss_protocol;
+pub use corry_export::{CorryExportStats, DetectorLayout};
+pub use decode_options::{DecodeOptions, DecodeResult};
+pub use decoder::MossDecoder;
+pub use demux::{LinkRecordFormat, StreamDemux};
+pub use diff::{DecodeDiff, EventDiff};
+pub use event_file::EventFileInfo;
+pub use framing::FramingConfig;
+pub use hit_map::HitMap;
+pub use moss_protocol::MossEvent;
 pub use moss_protocol::MossHit;
+pub use moss_protocol::PacketMetadata;
+pub use moss_protocol::{MossEventBatch, MossEventView};
+pub use outer_framing::ReadoutWrapper;
+pub use raw_filter::{EventFilter, RawFilterStats};
+pub use sink::{PacketSink, SinkControl};
+pub use stream::StreamStats;
+pub use text_export::TextExportStats;
+pub mod annotated_dump;
+mod buffer_input;
+pub mod corry_export;
 mod debug_decode;
 pub mod decode_hits_fsm;
+pub mod decode_options;
+pub mod decoder;
+pub mod demux;
+pub mod diff;
+pub mod event_builder;
+pub mod event_file;
+pub mod ffi;
+pub mod framing;
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod hit_map;
+pub mod outer_framing;
 pub(crate) mod parse_error;
 pub(crate) mod parse_util;
+mod py_reader;
+pub mod raw_filter;
+pub mod replay;
+pub mod sink;
+pub mod stream;
+pub mod text_export;
 
 /// A Python module for decoding raw MOSS data effeciently in Rust.
 #[pymodule]
-fn moss_decoder(_py: Python, m: &PyModule) -> PyResult<()> {
+fn moss_decoder(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(decode, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_to_sink, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_file_to_sink, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_stream_to_sink, m)?)?;
     m.add_function(wrap_pyfunction!(decode_event, m)?)?;
     m.add_function(wrap_pyfunction!(decode_all_events, m)?)?;
     m.add_function(wrap_pyfunction!(decode_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_all_events_with_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_from_file_with_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_from_stream, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_from_stream_with_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_all_events_batch, m)?)?;
     m.add_function(wrap_pyfunction!(decode_n_events, m)?)?;
     m.add_function(wrap_pyfunction!(skip_n_take_all, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_n_events_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(skip_n_take_all_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_run, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_wrapped_events, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_wrapped_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(build_events, m)?)?;
     m.add_function(wrap_pyfunction!(debug_decode_all_events, m)?)?;
     m.add_function(wrap_pyfunction!(debug_decode_all_events_from_file, m)?)?;
-
+    m.add_function(wrap_pyfunction!(hexdump, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_packets, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_files, m)?)?;
+    m.add_function(wrap_pyfunction!(filter_raw_file, m)?)?;
+    m.add_function(wrap_pyfunction!(export_corryvreckan, m)?)?;
+    m.add_function(wrap_pyfunction!(export_csv, m)?)?;
+    m.add_function(wrap_pyfunction!(export_jsonl, m)?)?;
+    m.add_function(wrap_pyfunction!(write_event_file, m)?)?;
+    m.add_function(wrap_pyfunction!(read_event_file, m)?)?;
+    m.add_function(wrap_pyfunction!(read_event_file_info, m)?)?;
+
+    m.add_class::<DecodeOptions>()?;
+    m.add_class::<DecodeResult>()?;
     m.add_class::<MossHit>()?;
     m.add_class::<MossPacket>()?;
+    m.add_class::<MossEvent>()?;
+    m.add_class::<PacketMetadata>()?;
+    m.add_class::<MossEventBatch>()?;
+    m.add_class::<moss_protocol::moss_event_batch::PyEventView>()?;
+    m.add_class::<moss_protocol::moss_event_batch::BatchColumn>()?;
+    m.add_class::<FramingConfig>()?;
+    m.add_class::<ReadoutWrapper>()?;
+    m.add_class::<MossDecoder>()?;
+    m.add_class::<StreamDemux>()?;
+    m.add_class::<LinkRecordFormat>()?;
+    m.add_class::<StreamStats>()?;
+    m.add_class::<EventDiff>()?;
+    m.add_class::<DecodeDiff>()?;
+    m.add_class::<EventFilter>()?;
+    m.add_class::<RawFilterStats>()?;
+    m.add_class::<HitMap>()?;
+    m.add_class::<DetectorLayout>()?;
+    m.add_class::<CorryExportStats>()?;
+    m.add_class::<TextExportStats>()?;
+    m.add_class::<EventFileInfo>()?;
+    m.add_class::<sink::StopDecoding>()?;
+    m.add("STOP", Py::new(py, sink::StopDecoding)?)?;
 
     Ok(())
 }
@@ -71,7 +160,11 @@ const MINIMUM_EVENT_SIZE: usize = 2;
 /// This function returns an error if no MOSS packet is found, therefor if there's any chance the argument does not contain a valid `MossPacket`
 /// the call should be enclosed in a try/except.
 #[pyfunction]
-pub fn decode_event(bytes: &[u8]) -> PyResult<Tuple_MossPacket_LastTrailerIdx> {
+pub fn decode_event(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    framing: Option<FramingConfig>,
+) -> PyResult<Tuple_MossPacket_LastTrailerIdx> {
+    let framing = framing.unwrap_or_default();
     let byte_cnt = bytes.len();
 
     if byte_cnt < MINIMUM_EVENT_SIZE {
@@ -80,7 +173,7 @@ pub fn decode_event(bytes: &[u8]) -> PyResult<Tuple_MossPacket_LastTrailerIdx> {
         ));
     }
 
-    match rust_only::extract_packet_from_buf(bytes, None) {
+    match rust_only::extract_packet_from_buf(bytes, None, &framing) {
         Ok((moss_packet, trailer_idx)) => Ok((moss_packet, trailer_idx)),
         Err(e) => Err(PyAssertionError::new_err(format!("Decoding failed: {e}",))),
     }
@@ -89,39 +182,11 @@ pub fn decode_event(bytes: &[u8]) -> PyResult<Tuple_MossPacket_LastTrailerIdx> {
 #[pyfunction]
 /// Decodes as many MOSS events as possible into a list of [MossPacket]s.
 /// Optimized for speed and memory usage.
-pub fn decode_all_events(bytes: &[u8]) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
-    let approx_moss_packets = rust_only::calc_prealloc_val(bytes)?;
-
-    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(approx_moss_packets);
-
-    let mut last_trailer_idx = 0;
-
-    while last_trailer_idx < bytes.len() - MINIMUM_EVENT_SIZE - 1 {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], None) {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
-                last_trailer_idx += trailer_idx + 1;
-            }
-            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                return Err(PyBytesWarning::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
-                )));
-            }
-            Err(e) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
-                )))
-            }
-        }
-    }
-
-    if moss_packets.is_empty() {
-        Err(PyAssertionError::new_err("No MOSS Packets in events"))
-    } else {
-        Ok((moss_packets, last_trailer_idx - 1))
-    }
+pub fn decode_all_events(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    framing: Option<FramingConfig>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    rust_only::decode_all_packets(bytes, &framing.unwrap_or_default(), false)
 }
 
 /// Decodes a file containing raw MOSS data into a list of [MossPacket]s.
@@ -130,7 +195,11 @@ pub fn decode_all_events(bytes: &[u8]) -> PyResult<Tuple_List_MossPackets_LastTr
 /// If any errors are encountered while reading the file, an exception is thrown.
 /// There's no attempt to run over errors.
 #[pyfunction]
-pub fn decode_from_file(path: std::path::PathBuf) -> PyResult<List_MossPackets> {
+pub fn decode_from_file(
+    path: std::path::PathBuf,
+    framing: Option<FramingConfig>,
+) -> PyResult<List_MossPackets> {
+    let framing = framing.unwrap_or_default();
     // Open file (get file descriptor)
     let file = match std::fs::File::open(path) {
         Ok(file) => file,
@@ -138,47 +207,95 @@ pub fn decode_from_file(path: std::path::PathBuf) -> PyResult<List_MossPackets>
     };
 
     // Create buffered reader with 1MB capacity to minimize syscalls to read
-    let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
 
-    let mut moss_packets = Vec::new();
+    rust_only::decode_from_reader(reader, &framing, false, None)
+}
 
-    let mut buf = vec![0; READER_BUFFER_CAPACITY];
-    let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
-    while let Ok(bytes_read) = reader.read(&mut buf) {
-        if bytes_read == 0 {
-            break;
-        }
+/// Same as [decode_all_events] but attaches [PacketMetadata] to each [MossPacket].
+///
+/// The offsets in the metadata are byte indexes in `bytes`.
+#[pyfunction]
+pub fn decode_all_events_with_metadata(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    framing: Option<FramingConfig>,
+) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
+    rust_only::decode_all_packets(bytes, &framing.unwrap_or_default(), true)
+}
 
-        // Extend bytes_to_decode with the new data
-        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
+/// Same as [decode_from_file] but attaches [PacketMetadata] to each [MossPacket].
+///
+/// The offsets in the metadata are byte offsets in the file.
+#[pyfunction]
+pub fn decode_from_file_with_metadata(
+    path: std::path::PathBuf,
+    framing: Option<FramingConfig>,
+) -> PyResult<List_MossPackets> {
+    let framing = framing.unwrap_or_default();
+    // Open file (get file descriptor)
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
 
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((extracted_packets, last_trailer_idx)) => {
-                moss_packets.extend(extracted_packets);
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-            }
-            Err((e, failed_packet_num)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                return Err(PyBytesWarning::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )));
-            }
-            Err((e, failed_packet_num)) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )))
-            }
-        }
-    }
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let source_file = Some(path);
 
-    if moss_packets.is_empty() {
-        Err(PyAssertionError::new_err("No MOSS Packets in events"))
-    } else {
-        Ok(moss_packets)
-    }
+    rust_only::decode_from_reader(reader, &framing, true, source_file.as_deref())
+}
+
+/// Decodes raw MOSS data read from a Python file-like object into a list of [MossPacket]s.
+///
+/// The stream can be any object with a `readinto` or `read` method returning bytes, e.g. an `io.BufferedReader`,
+/// a `gzip.GzipFile`, a socket file or a tar member. It is read in chunks of 10 MiB until it returns no more data.
+/// Exceptions raised by the stream are propagated.
+///
+/// Arguments: stream: `BinaryIO`, framing: `Optional[FramingConfig]`
+/// Returns: `list[MossPacket]`
+#[pyfunction]
+pub fn decode_from_stream(
+    stream: &PyAny,
+    framing: Option<FramingConfig>,
+) -> PyResult<List_MossPackets> {
+    let framing = framing.unwrap_or_default();
+    rust_only::decode_from_reader(py_reader::PyReader::new(stream)?, &framing, false, None)
+}
+
+/// Same as [decode_from_stream] but attaches [PacketMetadata] to each [MossPacket].
+///
+/// The offsets in the metadata are byte offsets from the position of the stream when the function was called.
+///
+/// Arguments: stream: `BinaryIO`, framing: `Optional[FramingConfig]`
+/// Returns: `list[MossPacket]`
+#[pyfunction]
+pub fn decode_from_stream_with_metadata(
+    stream: &PyAny,
+    framing: Option<FramingConfig>,
+) -> PyResult<List_MossPackets> {
+    let framing = framing.unwrap_or_default();
+    rust_only::decode_from_reader(py_reader::PyReader::new(stream)?, &framing, true, None)
+}
+
+/// Same as [decode_all_events] but decodes into a columnar [MossEventBatch] instead of a list of [MossPacket]s.
+///
+/// Avoids allocating for each event which makes it faster for large amounts of data.
+#[pyfunction]
+pub fn decode_all_events_batch(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    framing: Option<FramingConfig>,
+) -> PyResult<(MossEventBatch, LastTrailerIdx)> {
+    let framing = framing.unwrap_or_default();
+    // The hit columns grow as needed, reserving the upper bound of a hit per 3 bytes would reserve 5/3 of the input size
+    let mut batch = MossEventBatch::with_capacity(rust_only::calc_prealloc_val(bytes)?, 0);
+
+    let (result, events_end) = decode_options::decode_bytes_to_events_end(
+        bytes,
+        &DecodeOptions::default(),
+        &framing,
+        &mut batch,
+    )?;
+    let last_trailer_idx = rust_only::last_event_end(&result, events_end, batch.len())?;
+    Ok((batch, last_trailer_idx))
 }
 
 /// Decodes N events from the given bytes.
@@ -187,106 +304,73 @@ pub fn decode_from_file(path: std::path::PathBuf) -> PyResult<List_MossPackets>
 /// - prepending `prepend_buffer` to the bytes before decoding.
 #[pyfunction]
 pub fn decode_n_events(
-    bytes: &[u8],
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
     take: usize,
     skip: Option<usize>,
-    mut prepend_buffer: Option<Vec<u8>>,
+    prepend_buffer: Option<Vec<u8>>,
+    framing: Option<FramingConfig>,
 ) -> PyResult<Tuple_List_MossPackets_LastTrailerIdx> {
-    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(take);
+    let framing = framing.unwrap_or_default();
+    rust_only::check_skip_and_prepend(skip, prepend_buffer.as_ref())?;
+    let options = DecodeOptions {
+        skip: skip.unwrap_or(0),
+        take: Some(take),
+        prepend: prepend_buffer.unwrap_or_default(),
+        ..Default::default()
+    };
 
-    // Skip N events
-    if skip.is_some_and(|s| s == 0) {
-        return Err(PyValueError::new_err("skip value must be greater than 0"));
-    } else if skip.is_some() && prepend_buffer.is_some() {
-        return Err(PyValueError::new_err(
-            "skip and prepend_buffer cannot be used together",
+    let mut packets = Vec::new();
+    let (result, events_end) =
+        decode_options::decode_bytes_to_events_end(bytes, &options, &framing, &mut packets)?;
+    // The index is returned relative to `bytes`
+    let Some(last_trailer_idx) = events_end.checked_sub(options.prepend.len() + 1) else {
+        return Err(PyAssertionError::new_err(
+            "Event ended in the prepended bytes, they should only contain the start of an event",
         ));
-    }
-
-    let mut last_trailer_idx = if let Some(skip) = skip {
-        find_trailer_n_idx(bytes, skip)?
-    } else {
-        0
     };
-
-    for i in 0..take {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], prepend_buffer.take())
-        {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
-                last_trailer_idx += trailer_idx + 1;
-            }
-            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                return Err(PyBytesWarning::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
-                )))
-            }
-            Err(e) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = i + 1
-                )))
-            }
-        }
-    }
-
-    if moss_packets.is_empty() {
-        Err(PyAssertionError::new_err("No MOSS Packets in events"))
-    } else {
-        Ok((moss_packets, last_trailer_idx - 1))
-    }
+    Ok((
+        rust_only::take_n_events(DecodeResult { packets, ..result }, take)?,
+        last_trailer_idx,
+    ))
 }
 
 #[allow(non_camel_case_types)]
 type Remainder_Bytes = Vec<u8>;
 
+#[allow(non_camel_case_types)]
+type Tuple_List_MossPackets_Remainder = (Option<List_MossPackets>, Option<Remainder_Bytes>);
+
 /// Skips N events in the given bytes and decode as many packets as possible until end of buffer,
 /// If any packets are decoded, they are returned as a list of MOSS Packets.
 /// if the end of the buffer contains a partial event, those bytes are returned as a remainder.
 ///
-/// Arguments: bytes: `bytes`, skip: `int`
+/// Arguments: bytes: `bytes-like`, skip: `int`, framing: `Optional[FramingConfig]`
 ///
 /// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
 #[pyfunction]
 pub fn skip_n_take_all(
-    bytes: &[u8],
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
     skip: usize,
-) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
-    let mut moss_packets: Vec<MossPacket> = Vec::new();
-    let mut remainder: Option<Vec<u8>> = None;
-
-    // Skip N events
-    let mut last_trailer_idx = if skip > 0 {
-        find_trailer_n_idx(bytes, skip)?
-    } else {
-        0
+    framing: Option<FramingConfig>,
+) -> PyResult<Tuple_List_MossPackets_Remainder> {
+    let framing = framing.unwrap_or_default();
+    let options = DecodeOptions {
+        skip,
+        ..Default::default()
     };
 
-    while last_trailer_idx < bytes.len() - MINIMUM_EVENT_SIZE - 1 {
-        match rust_only::extract_packet_from_buf(&bytes[last_trailer_idx..], None) {
-            Ok((moss_packet, trailer_idx)) => {
-                moss_packets.push(moss_packet);
-                last_trailer_idx += trailer_idx + 1;
-            }
-            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                remainder = Some(bytes[last_trailer_idx..].to_vec());
-                break;
-            }
-            Err(e) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1
-                )))
-            }
-        }
-    }
-
-    if moss_packets.is_empty() {
-        Ok((None, remainder))
-    } else {
-        Ok((Some(moss_packets), remainder))
+    let mut packets = Vec::new();
+    let (result, events_end) =
+        decode_options::decode_bytes_to_events_end(bytes, &options, &framing, &mut packets)?;
+    if result.skipped < skip {
+        return Err(PyAssertionError::new_err(format!(
+            "Skipping {skip} events failed, only {skipped} events found",
+            skipped = result.skipped
+        )));
     }
+    Ok(rust_only::packets_and_remainder(packets, &result, || {
+        Ok(bytes[events_end..].to_vec())
+    })?)
 }
 
 /// Decodes N events from the given file.
@@ -294,188 +378,456 @@ pub fn skip_n_take_all(
 /// - skipping `skip` events before decoding.
 /// - prepending `prepend_buffer` to the bytes before decoding.
 ///
-/// Arguments: path: `str`, take: `int`, skip: `Optional[int]`, prepend_buffer: `Optional[bytes]`
+/// Arguments: path: `str`, take: `int`, skip: `Optional[int]`, prepend_buffer: `Optional[bytes]`, framing: `Optional[FramingConfig]`
 /// Returns: `List[MossPacket]`
 #[pyfunction]
 pub fn decode_n_events_from_file(
     path: std::path::PathBuf,
     take: usize,
     skip: Option<usize>,
-    mut prepend_buffer: Option<Vec<u8>>,
+    prepend_buffer: Option<Vec<u8>>,
+    framing: Option<FramingConfig>,
 ) -> PyResult<List_MossPackets> {
-    // Skip N events
-    if skip.is_some_and(|s| s == 0) {
-        return Err(PyValueError::new_err("skip value must be greater than 0"));
-    } else if skip.is_some() && prepend_buffer.is_some() {
-        return Err(PyValueError::new_err(
-            "skip and prepend_buffer cannot be used together",
-        ));
-    }
+    let framing = framing.unwrap_or_default();
+    rust_only::check_skip_and_prepend(skip, prepend_buffer.as_ref())?;
+    let options = DecodeOptions {
+        skip: skip.unwrap_or(0),
+        take: Some(take),
+        prepend: prepend_buffer.unwrap_or_default(),
+        ..Default::default()
+    };
     // Open file (get file descriptor)
     let file = match std::fs::File::open(path) {
         Ok(file) => file,
         Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
     };
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
 
-    // Create buffered reader with 1MB capacity to minimize syscalls to read
-    let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let result = decode_options::decode_reader(reader, &options, &framing, None)?;
+    rust_only::take_n_events_from_file(result, take)
+}
 
-    let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(take);
+/// Decodes all events from the given file, skipping the first `skip` events
+///  and returns the remainder bytes if a partial event was found in it.
+///
+/// Arguments: path: `str`, skip: `Optional[int]`, framing: `Optional[FramingConfig]`
+/// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
+#[pyfunction]
+pub fn skip_n_take_all_from_file(
+    path: std::path::PathBuf,
+    skip: usize,
+    framing: Option<FramingConfig>,
+) -> PyResult<Tuple_List_MossPackets_Remainder> {
+    let framing = framing.unwrap_or_default();
+    let options = DecodeOptions {
+        skip,
+        ..Default::default()
+    };
+    // Open file (get file descriptor)
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, &file);
+
+    let mut packets = Vec::new();
+    let (result, events_end) = decode_options::decode_reader_to_events_end(
+        reader,
+        &options,
+        &framing,
+        None,
+        &mut packets,
+    )?;
+    // The filler bytes before the partial event were consumed, read the remainder again from the end of the last event
+    rust_only::packets_and_remainder(packets, &result, || {
+        let mut file = file;
+        _ = file.seek(std::io::SeekFrom::Start(events_end as u64))?;
+        let mut remainder = Vec::new();
+        _ = file.read_to_end(&mut remainder)?;
+        Ok(remainder)
+    })
+    .map_err(py_reader::into_py_err)
+}
 
-    let mut buf = vec![0; READER_BUFFER_CAPACITY];
-    let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
-    if let Some(prepend_buffer) = prepend_buffer.take() {
-        bytes_to_decode.extend_from_slice(&prepend_buffer);
+/// Decodes `bytes` according to the [DecodeOptions].
+///
+/// This and [decode_file] and [decode_stream] are the main entry points, they run the same decoding loop
+/// so the options behave the same for all of them. The other decoding functions are kept for compatibility.
+/// A partial event at the end of `bytes` is returned as the remainder, to be passed as the `prepend` option of the next call.
+///
+/// Arguments: bytes: `bytes-like`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult`
+#[pyfunction]
+pub fn decode(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    decode_options::decode_bytes(bytes, &options.unwrap_or_default(), &framing)
+}
+
+/// Decodes the file at `path` according to the [DecodeOptions], reading it in chunks of 10 MiB.
+///
+/// The file is only read until `take` events are decoded.
+///
+/// Arguments: path: `str | Path`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult`
+#[pyfunction]
+pub fn decode_file(
+    path: std::path::PathBuf,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let source_file = Some(path);
+
+    decode_options::decode_reader(
+        reader,
+        &options.unwrap_or_default(),
+        &framing,
+        source_file.as_deref(),
+    )
+}
+
+/// Decodes the data read from a Python file-like object according to the [DecodeOptions].
+///
+/// The stream can be any object with a `readinto` or `read` method returning bytes, see [decode_from_stream].
+/// The stream is only read until `take` events are decoded.
+///
+/// Arguments: stream: `BinaryIO`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult`
+#[pyfunction]
+pub fn decode_stream(
+    stream: &PyAny,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    decode_options::decode_reader(
+        py_reader::PyReader::new(stream)?,
+        &options.unwrap_or_default(),
+        &framing,
+        None,
+    )
+}
+
+/// Decodes `bytes` according to the [DecodeOptions], handing each decoded packet to `sink` instead of returning them.
+///
+/// The `sink` is either a [HitMap], which is filled without calling into Python, or a callable.
+/// A callable is called with each `MossPacket`, or with a list of up to `batch_size` packets if given,
+/// and stops decoding by returning `moss_decoder.STOP`. The `output` option is ignored.
+/// As the callable could modify the input, writable buffers like `bytearray` are copied before decoding,
+/// read-only buffers like `bytes` are decoded in place.
+///
+/// Arguments: bytes: `bytes-like`, sink: `Callable | HitMap`, batch_size: `Optional[int]`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult` without events
+#[pyfunction]
+pub fn decode_to_sink(
+    #[pyo3(from_py_with = "buffer_input::readonly_bytes_from_buffer")] bytes: &[u8],
+    sink: &PyAny,
+    batch_size: Option<usize>,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    let options = options.unwrap_or_default();
+    sink::with_py_sink(sink, batch_size, |sink| {
+        decode_options::decode_bytes_into(bytes, &options, &framing, sink)
+    })
+}
+
+/// Decodes the file at `path` according to the [DecodeOptions], handing each decoded packet to `sink`, see [decode_to_sink].
+///
+/// The file is only read until the sink stops decoding or `take` events are decoded.
+///
+/// Arguments: path: `str | Path`, sink: `Callable | HitMap`, batch_size: `Optional[int]`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult` without events
+#[pyfunction]
+pub fn decode_file_to_sink(
+    path: std::path::PathBuf,
+    sink: &PyAny,
+    batch_size: Option<usize>,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    let options = options.unwrap_or_default();
+    let file = match std::fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    let reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let source_file = Some(path);
+
+    sink::with_py_sink(sink, batch_size, |sink| {
+        decode_options::decode_reader_into(reader, &options, &framing, source_file.as_deref(), sink)
+    })
+}
+
+/// Decodes the data read from a Python file-like object according to the [DecodeOptions], handing each decoded packet to `sink`, see [decode_to_sink].
+///
+/// The stream is only read until the sink stops decoding or `take` events are decoded.
+///
+/// Arguments: stream: `BinaryIO`, sink: `Callable | HitMap`, batch_size: `Optional[int]`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeResult` without events
+#[pyfunction]
+pub fn decode_stream_to_sink(
+    stream: &PyAny,
+    sink: &PyAny,
+    batch_size: Option<usize>,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeResult> {
+    let framing = framing.unwrap_or_default();
+    let options = options.unwrap_or_default();
+    let reader = py_reader::PyReader::new(stream)?;
+    sink::with_py_sink(sink, batch_size, |sink| {
+        decode_options::decode_reader_into(reader, &options, &framing, None, sink)
+    })
+}
+
+/// The files making up a run, either as an ordered list of paths or a glob pattern.
+///
+/// Files matched by a glob pattern are decoded in alphabetical order.
+#[derive(Debug, Clone, FromPyObject)]
+pub enum RunPaths {
+    /// A glob pattern e.g. `"run_42/*.raw"`
+    #[pyo3(transparent, annotation = "str")]
+    Glob(String),
+    /// An ordered list of paths
+    #[pyo3(transparent, annotation = "list[str | Path]")]
+    Files(Vec<std::path::PathBuf>),
+}
+
+impl RunPaths {
+    fn into_files(self) -> PyResult<Vec<std::path::PathBuf>> {
+        let files = match self {
+            RunPaths::Files(files) => files,
+            RunPaths::Glob(pattern) => match glob::glob(&pattern) {
+                Ok(paths) => paths
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| PyFileNotFoundError::new_err(e.to_string()))?,
+                Err(e) => return Err(PyValueError::new_err(format!("Invalid glob pattern: {e}"))),
+            },
+        };
+        if files.is_empty() {
+            Err(PyFileNotFoundError::new_err("No files in run"))
+        } else {
+            Ok(files)
+        }
     }
-    let mut packets_to_skip = skip.unwrap_or(0);
+}
 
-    while let Ok(bytes_read) = reader.read(&mut buf) {
-        if bytes_read == 0 {
-            break;
+/// Reads the files of a run one after the other as one continuous stream.
+struct RunReader<'a> {
+    files: &'a [std::path::PathBuf],
+    /// The file being read and its index in `files`.
+    current: Option<(std::fs::File, usize)>,
+    /// Stream offset of the first byte of each file opened so far.
+    file_starts: Vec<usize>,
+    stream_len: usize,
+}
+
+impl<'a> RunReader<'a> {
+    fn new(files: &'a [std::path::PathBuf]) -> Self {
+        Self {
+            files,
+            current: None,
+            file_starts: Vec::with_capacity(files.len()),
+            stream_len: 0,
         }
+    }
 
-        // Extend bytes_to_decode with the new data
-        bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
+    /// The index of the file containing the byte at `stream_offset` and the offset of the byte in that file.
+    fn file_offset(&self, stream_offset: usize) -> (usize, usize) {
+        // The file containing the byte is the last file that starts at or before it
+        let file_idx = self
+            .file_starts
+            .partition_point(|&start| start <= stream_offset)
+            - 1;
+        (file_idx, stream_offset - self.file_starts[file_idx])
+    }
+}
 
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((mut extracted_packets, last_trailer_idx)) => {
-                if packets_to_skip > 0 {
-                    if packets_to_skip > extracted_packets.len() {
-                        packets_to_skip -= extracted_packets.len();
-                        bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-                        continue;
-                    } else {
-                        for _ in 0..packets_to_skip {
-                            _ = extracted_packets.remove(0);
-                        }
-                        packets_to_skip = 0;
-                    }
-                }
-                moss_packets.extend(extracted_packets);
-                if moss_packets.len() >= take {
-                    break;
+impl Read for RunReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some((file, file_idx)) = self.current.as_mut() {
+                let bytes_read = file.read(buf).map_err(|e| {
+                    std::io::Error::new(
+                        e.kind(),
+                        format!("{path}: {e}", path = self.files[*file_idx].display()),
+                    )
+                })?;
+                if bytes_read > 0 || buf.is_empty() {
+                    self.stream_len += bytes_read;
+                    return Ok(bytes_read);
                 }
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-            }
-            Err((e, failed_packet_num)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                return Err(PyBytesWarning::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )));
-            }
-            Err((e, failed_packet_num)) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )))
             }
+            let next_idx = self.current.as_ref().map_or(0, |(_, idx)| idx + 1);
+            let Some(path) = self.files.get(next_idx) else {
+                return Ok(0);
+            };
+            let file = std::fs::File::open(path).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("{path}: {e}", path = path.display()))
+            })?;
+            self.current = Some((file, next_idx));
+            self.file_starts.push(self.stream_len);
         }
     }
+}
 
-    if moss_packets.is_empty() {
-        Err(PyAssertionError::new_err("No MOSS Packets in events"))
-    } else if moss_packets.len() < take {
+/// Decodes all events from a run consisting of multiple files, as if they were one continuous stream.
+///
+/// Events that span the boundary between two files are stitched together.
+/// The packets carry [PacketMetadata] with their source file and offsets relative to the start of that file,
+/// the trailer offset of an event spanning into the next file is therefor past the end of its source file.
+/// A partial event at the end of the last file is ignored.
+///
+/// Arguments: paths: `list[str | Path]` or a glob pattern `str`, framing: `Optional[FramingConfig]`
+/// Returns: `List[MossPacket]`
+#[pyfunction]
+pub fn decode_run(paths: RunPaths, framing: Option<FramingConfig>) -> PyResult<List_MossPackets> {
+    let framing = framing.unwrap_or_default();
+    let files = paths.into_files()?;
+
+    let mut reader = RunReader::new(&files);
+    let mut moss_packets = rust_only::decode_from_reader(&mut reader, &framing, true, None)?;
+    for metadata in moss_packets.iter_mut().filter_map(|p| p.metadata.as_mut()) {
+        let (file_idx, header_offset) = reader.file_offset(metadata.header_offset);
+        metadata.trailer_offset -= metadata.header_offset - header_offset;
+        metadata.header_offset = header_offset;
+        metadata.source_file = Some(files[file_idx].clone());
+    }
+    Ok(moss_packets)
+}
+
+/// Decodes all MOSS events from a stream of wrapper records, see [ReadoutWrapper] for the example record format.
+///
+/// Each [MossPacket] carries [PacketMetadata] with the trigger ID and timestamp of its record,
+/// the offsets in the metadata are byte indexes in `bytes`.
+///
+/// Arguments: bytes: `bytes-like`, wrapper: `Optional[ReadoutWrapper]`, framing: `Optional[FramingConfig]`
+/// Returns: `Tuple[List[MossPacket], int]` the packets and the index of the last byte of the last record
+#[pyfunction]
+pub fn decode_wrapped_events(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    wrapper: Option<ReadoutWrapper>,
+    framing: Option<FramingConfig>,
+) -> PyResult<(List_MossPackets, usize)> {
+    let wrapper = wrapper.unwrap_or_default();
+    let framing = framing.unwrap_or_default();
+
+    let (moss_packets, records_len) =
+        outer_framing::decode_wrapped_records(bytes, &wrapper, &framing, 0, 0)?;
+
+    if records_len < bytes.len() {
         Err(PyBytesWarning::new_err(format!(
-            "Taking {take} events failed, got {decoded_cnt} events",
-            decoded_cnt = moss_packets.len()
+            "Reached end of buffer before the end of the record at byte {records_len}"
         )))
+    } else if moss_packets.is_empty() {
+        Err(PyAssertionError::new_err("No MOSS Packets in events"))
     } else {
-        moss_packets.truncate(take); // Truncate to the requested number of events
-        Ok(moss_packets)
+        Ok((moss_packets, records_len - 1))
     }
 }
 
-/// Decodes all events from the given file, skipping the first `skip` events
-///  and returns the remainder bytes if a partial event was found in it.
+/// Same as [decode_wrapped_events] but decodes a file, the offsets in the metadata are byte offsets in the file.
 ///
-/// Arguments: path: `str`, skip: `Optional[int]`
-/// Returns: `Tuple[Optional[List[MossPacket]], Optional[bytes]]`
+/// The file is read in chunks of 10 MiB. Raises if reading the file fails or the file ends with a partial record.
+///
+/// Arguments: path: `str`, wrapper: `Optional[ReadoutWrapper]`, framing: `Optional[FramingConfig]`
+/// Returns: `List[MossPacket]`
 #[pyfunction]
-pub fn skip_n_take_all_from_file(
+pub fn decode_wrapped_from_file(
     path: std::path::PathBuf,
-    mut skip: usize,
-) -> PyResult<(Option<List_MossPackets>, Option<Remainder_Bytes>)> {
-    let mut moss_packets: Vec<MossPacket> = Vec::new();
-    let mut remainder: Option<Vec<u8>> = None;
-    // Open file (get file descriptor)
-    let file = match std::fs::File::open(path) {
+    wrapper: Option<ReadoutWrapper>,
+    framing: Option<FramingConfig>,
+) -> PyResult<List_MossPackets> {
+    let wrapper = wrapper.unwrap_or_default();
+    let framing = framing.unwrap_or_default();
+    let file = match std::fs::File::open(&path) {
         Ok(file) => file,
         Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
     };
 
-    // Create buffered reader with 1MB capacity to minimize syscalls to read
     let mut reader = std::io::BufReader::with_capacity(READER_BUFFER_CAPACITY, file);
+    let source_file = Some(path);
+
+    let mut moss_packets: Vec<MossPacket> = Vec::new();
 
     let mut buf = vec![0; READER_BUFFER_CAPACITY];
     let mut bytes_to_decode = Vec::with_capacity(READER_BUFFER_CAPACITY);
-
-    while let Ok(bytes_read) = reader.read(&mut buf) {
+    // File offset of the first byte in `bytes_to_decode`
+    let mut file_offset = 0;
+    loop {
+        let bytes_read = reader.read(&mut buf).map_err(py_reader::into_py_err)?;
         if bytes_read == 0 {
             break;
         }
 
-        // Extend bytes_to_decode with the new data
         bytes_to_decode.extend_from_slice(&buf[..bytes_read]);
 
-        // Decode the bytes one event at a time until there's no more events to decode
-        match rust_only::get_all_packets_from_buf(&bytes_to_decode) {
-            Ok((mut extracted_packets, last_trailer_idx)) => {
-                if skip > 0 {
-                    if skip > extracted_packets.len() {
-                        skip -= extracted_packets.len();
-                        bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-                        continue;
-                    } else {
-                        for _ in 0..skip {
-                            _ = extracted_packets.remove(0);
-                        }
-                        skip = 0;
-                    }
-                }
-                moss_packets.extend(extracted_packets);
-                // Remove the processed bytes from bytes_to_decode (it now contains the remaining bytes that could did not form a complete event)
-                bytes_to_decode = bytes_to_decode[last_trailer_idx..].to_vec();
-            }
-            Err((e, _)) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                break;
-            }
-            Err((e, _)) if e.kind() == ParseErrorKind::NoHeaderFound => {
-                break;
-            }
-            Err((e, failed_packet_num)) => {
-                return Err(PyAssertionError::new_err(format!(
-                    "Failed decoding packet #{packet_cnt}: {e}",
-                    packet_cnt = moss_packets.len() + 1 + failed_packet_num
-                )))
+        let (extracted_packets, records_len) = outer_framing::decode_wrapped_records(
+            &bytes_to_decode,
+            &wrapper,
+            &framing,
+            file_offset,
+            moss_packets.len(),
+        )?;
+        moss_packets.extend(extracted_packets.into_iter().map(|mut p| {
+            if let Some(metadata) = p.metadata.as_mut() {
+                metadata.source_file = source_file.clone();
             }
-        }
-    }
-
-    if !bytes_to_decode.is_empty()
-        && bytes_to_decode
-            .iter()
-            .any(|&b| moss_protocol::MossWord::UNIT_FRAME_HEADER_RANGE.contains(&b))
-    {
-        remainder = Some(bytes_to_decode.to_vec());
+            p
+        }));
+        _ = bytes_to_decode.drain(..records_len);
+        file_offset += records_len;
     }
 
-    if moss_packets.is_empty() {
-        Ok((None, remainder))
+    if !bytes_to_decode.is_empty() {
+        Err(PyBytesWarning::new_err(format!(
+            "Reached end of file before the end of the record at byte {file_offset}"
+        )))
+    } else if moss_packets.is_empty() {
+        Err(PyAssertionError::new_err("No MOSS Packets in events"))
     } else {
-        Ok((Some(moss_packets), remainder))
+        Ok(moss_packets)
     }
 }
 
+/// Groups the [MossPacket]s of the units of a stitched sensor into one [MossEvent] per trigger.
+///
+/// Packets are grouped by the trigger ID in their [PacketMetadata] if they have one,
+/// otherwise a new event starts when the unit ID is lower than the unit ID of the previous packet.
+/// Each event lists the units in `expected_units` (all 10 units by default) that have no packet and the units that have more than one.
+///
+/// Arguments: packets: `List[MossPacket]`, expected_units: `Optional[List[int]]`
+/// Returns: `List[MossEvent]`
+#[pyfunction]
+pub fn build_events(packets: Vec<MossPacket>, expected_units: Option<Vec<u8>>) -> Vec<MossEvent> {
+    event_builder::build_events(
+        packets,
+        expected_units.unwrap_or_else(|| event_builder::ALL_UNITS.collect()),
+    )
+}
+
 #[pyfunction]
 /// Decodes as many MOSS events as possible into a list of [MossPacket]s.
 /// Doesn't check for invalid state transitions. Runs over errors when possible and instead returns a list of invalid words.
 ///
 /// Useful for attempting to extract as many packets and debug based on packet analysis.
 pub fn debug_decode_all_events(
-    bytes: &[u8],
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    framing: Option<FramingConfig>,
 ) -> PyResult<(List_MossPackets, LastTrailerIdx, InvalidWordMsgs)> {
+    let framing = framing.unwrap_or_default();
     let approx_moss_packets = rust_only::calc_prealloc_val(bytes)?;
     let mut moss_packets: Vec<MossPacket> = Vec::with_capacity(approx_moss_packets);
 
@@ -489,7 +841,7 @@ pub fn debug_decode_all_events(
     let mut invalid_words = Vec::new();
 
     loop {
-        match debug_decode::debug_decode_event(&bytes[last_trailer_idx..]) {
+        match debug_decode::debug_decode_event(&bytes[last_trailer_idx..], &framing) {
             Ok((new_moss_packet, trailer_idx, new_invalid_words)) => {
                 new_invalid_words.into_iter().for_each(|mut invalid_word| {
                     invalid_word.set_index_offset(last_trailer_idx);
@@ -536,21 +888,409 @@ pub fn debug_decode_all_events(
 /// Useful for attempting to extract as many packets and debug based on packet analysis.
 pub fn debug_decode_all_events_from_file(
     path: std::path::PathBuf,
+    framing: Option<FramingConfig>,
 ) -> PyResult<(List_MossPackets, LastTrailerIdx, InvalidWordMsgs)> {
-    let bytes = std::fs::read(path).unwrap();
-    debug_decode_all_events(&bytes)
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    debug_decode_all_events(&bytes, framing)
+}
+
+#[pyfunction]
+/// Returns an annotated dump of raw MOSS data, one line per byte.
+///
+/// Each byte is shown with its MOSS word classification and the current region,
+/// along with the coordinates of each decoded hit, event boundaries and protocol errors (marked with `!!`).
+/// Select a range of bytes with `start` and `end`, or a range of events with `first_event` and `event_count`.
+///
+/// Arguments: bytes: `bytes-like`, start: `Optional[int]`, end: `Optional[int]`, first_event: `Optional[int]`, event_count: `Optional[int]`, framing: `Optional[FramingConfig]`
+/// Returns: `str`
+pub fn hexdump(
+    #[pyo3(from_py_with = "buffer_input::bytes_from_buffer")] bytes: &[u8],
+    start: Option<usize>,
+    end: Option<usize>,
+    first_event: Option<usize>,
+    event_count: Option<usize>,
+    framing: Option<FramingConfig>,
+) -> PyResult<String> {
+    let framing = framing.unwrap_or_default();
+    let selection = match (start, end, first_event, event_count) {
+        (None, None, None, None) => annotated_dump::DumpSelection::All,
+        (start, end, None, None) => {
+            annotated_dump::DumpSelection::Bytes(start.unwrap_or(0)..end.unwrap_or(bytes.len()))
+        }
+        (None, None, first, count) => annotated_dump::DumpSelection::Events {
+            first: first.unwrap_or(0),
+            count: count.unwrap_or(1),
+        },
+        _ => {
+            return Err(PyValueError::new_err(
+                "Select either a range of bytes or a range of events, not both",
+            ))
+        }
+    };
+    Ok(annotated_dump::annotated_hexdump(
+        bytes, &selection, &framing,
+    ))
+}
+
+#[pyfunction]
+/// Compares two lists of decoded [MossPacket]s event by event.
+///
+/// Reports events missing in either list, unit ID mismatches and the hits added or removed in each event,
+/// only the unit IDs and hits are compared.
+///
+/// Arguments: a: `List[MossPacket]`, b: `List[MossPacket]`
+/// Returns: `DecodeDiff`
+// The lists are converted from Python by value
+#[allow(clippy::needless_pass_by_value)]
+pub fn diff_packets(a: Vec<MossPacket>, b: Vec<MossPacket>) -> DecodeDiff {
+    diff::diff_packets(&a, &b)
+}
+
+#[pyfunction]
+/// Decodes two files and compares the decoded events event by event, see [diff_packets].
+///
+/// Arguments: path_a: `str | Path`, path_b: `str | Path`, framing: `Optional[FramingConfig]`
+/// Returns: `DecodeDiff`
+pub fn diff_files(
+    path_a: std::path::PathBuf,
+    path_b: std::path::PathBuf,
+    framing: Option<FramingConfig>,
+) -> PyResult<DecodeDiff> {
+    let a = decode_from_file(path_a, framing)?;
+    let b = decode_from_file(path_b, framing)?;
+    Ok(diff::diff_packets(&a, &b))
+}
+
+#[pyfunction]
+/// Streams through a raw file and writes the raw bytes of the events selected by `event_filter` to `output`.
+///
+/// All events are selected if no filter is given. The output can be split into several files after `split_events` events,
+/// or before exceeding `split_bytes` bytes, the files are then named after `output` with a file number appended to the stem.
+///
+/// Arguments: input: `str | Path`, output: `str | Path`, event_filter: `Optional[EventFilter]`,
+///            split_events: `Optional[int]`, split_bytes: `Optional[int]`, framing: `Optional[FramingConfig]`
+/// Returns: `RawFilterStats`
+pub fn filter_raw_file(
+    input: std::path::PathBuf,
+    output: std::path::PathBuf,
+    event_filter: Option<EventFilter>,
+    split_events: Option<usize>,
+    split_bytes: Option<usize>,
+    framing: Option<FramingConfig>,
+) -> PyResult<RawFilterStats> {
+    let framing = framing.unwrap_or_default();
+    let split = match (split_events, split_bytes) {
+        (None, None) => raw_filter::SplitBy::Never,
+        (Some(events), None) if events > 0 => raw_filter::SplitBy::Events(events),
+        (None, Some(bytes)) if bytes > 0 => raw_filter::SplitBy::Bytes(bytes),
+        _ => {
+            return Err(PyValueError::new_err(
+                "Split by a positive number of either events or bytes, not both",
+            ))
+        }
+    };
+    let writer = raw_filter::RawEventWriter::new(output, split)?;
+    Ok(raw_filter::filter_raw_file(
+        input,
+        &event_filter.unwrap_or_default(),
+        writer,
+        &framing,
+    )?)
+}
+
+#[pyfunction]
+/// Writes the hits of decoded events to pixel hit files for Corryvreckan, one per detector of the `layout`,
+/// an event index `events.txt` and a Corryvreckan geometry file `geometry.conf` with the size and pitch of each detector.
+/// The files are read by the Corryvreckan module `EventLoaderMOSS` in the `corryvreckan` directory of the repository.
+///
+/// Packets are grouped into events like [build_events] does. Events are numbered by trigger ID if available,
+/// otherwise by the sequence number of their first packet, or by their index if the packets have no metadata.
+/// By default each unit is exported as its own detector, with 256 x 256 pixel regions and a pitch of 22.5 µm for the top half-units
+/// and 320 x 320 pixel regions and a pitch of 18 µm for the bottom half-units.
+/// The hit files are written in `format` `"text"` (default) or `"binary"`, see [corry_export].
+///
+/// Arguments: events: `list[MossEvent] | list[MossPacket]`, output_dir: `str | Path`, layout: `Optional[DetectorLayout]`, format: `Optional[str]`
+/// Returns: `CorryExportStats`
+pub fn export_corryvreckan(
+    events: corry_export::ExportInput,
+    output_dir: std::path::PathBuf,
+    layout: Option<DetectorLayout>,
+    format: Option<corry_export::HitFileFormat>,
+) -> PyResult<CorryExportStats> {
+    let layout = match layout {
+        Some(layout) => layout,
+        None => DetectorLayout::per_unit(None, None, None, "MOSS_unit")?,
+    };
+    Ok(corry_export::export_events(
+        &events.into_events(),
+        output_dir,
+        &layout,
+        format.unwrap_or_default(),
+    )?)
+}
+
+#[pyfunction]
+/// Decodes the file at `path` according to the [DecodeOptions] and writes a line `event,unit,region,row,column` per hit to the CSV file `output`,
+/// while decoding so the decoded events are never held in memory.
+///
+/// The output is gzip compressed if `gzip` is True or, by default, if `output` ends in `.gz`. Existing files are overwritten.
+///
+/// Arguments: path: `str | Path`, output: `str | Path`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`, gzip: `Optional[bool]`
+/// Returns: `TextExportStats`
+pub fn export_csv(
+    path: std::path::PathBuf,
+    output: std::path::PathBuf,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+    gzip: Option<bool>,
+) -> PyResult<TextExportStats> {
+    text_export::export_file(
+        path,
+        output,
+        text_export::TextFormat::Csv,
+        &options.unwrap_or_default(),
+        &framing.unwrap_or_default(),
+        gzip,
+    )
+}
+
+#[pyfunction]
+/// Decodes the file at `path` according to the [DecodeOptions] and writes a JSON object per packet to the JSON Lines file `output`,
+/// with its event number, unit ID, byte offsets and hits, while decoding. Errors skipped with `errors="skip"` are written as `{"error": ..., "offset": ...}` lines.
+///
+/// The output is gzip compressed if `gzip` is True or, by default, if `output` ends in `.gz`. Existing files are overwritten.
+///
+/// Arguments: path: `str | Path`, output: `str | Path`, options: `Optional[DecodeOptions]`, framing: `Optional[FramingConfig]`, gzip: `Optional[bool]`
+/// Returns: `TextExportStats`
+pub fn export_jsonl(
+    path: std::path::PathBuf,
+    output: std::path::PathBuf,
+    options: Option<DecodeOptions>,
+    framing: Option<FramingConfig>,
+    gzip: Option<bool>,
+) -> PyResult<TextExportStats> {
+    text_export::export_file(
+        path,
+        output,
+        text_export::TextFormat::JsonLines,
+        &options.unwrap_or_default(),
+        &framing.unwrap_or_default(),
+        gzip,
+    )
+}
+
+#[pyfunction]
+/// Writes decoded events to a compact binary event file at `path` with the run `metadata`, to be loaded later with [read_event_file]
+/// without decoding again. Existing files are overwritten.
+///
+/// The hits are stored column by column, as is with `compression="none"` (default) so the columns can be memory-mapped,
+/// e.g. with `numpy.memmap` at the offsets in [EventFileInfo], or zlib compressed for smaller files with `"deflate"`.
+/// See [event_file] for the format.
+///
+/// Arguments: path: `str | Path`, events: `MossEventBatch | list[MossPacket]`, metadata: `Optional[dict[str, str]]`, compression: `Optional[str]`
+/// Returns: `EventFileInfo`
+pub fn write_event_file(
+    path: std::path::PathBuf,
+    events: event_file::EventFileInput,
+    metadata: Option<std::collections::BTreeMap<String, String>>,
+    compression: Option<event_file::ColumnCompression>,
+) -> PyResult<EventFileInfo> {
+    Ok(event_file::write_event_file(
+        path,
+        &events.into_batch(),
+        &metadata.unwrap_or_default(),
+        compression.unwrap_or_default(),
+    )?)
+}
+
+#[pyfunction]
+/// Reads the events of an event file written by [write_event_file], use `MossEventBatch.to_packets()` to get `MossPacket`s.
+///
+/// Files written by a newer minor version of the format can be read, a newer major version raises an `OSError`.
+///
+/// Arguments: path: `str | Path`
+/// Returns: `MossEventBatch`
+pub fn read_event_file(path: std::path::PathBuf) -> PyResult<MossEventBatch> {
+    let (_, batch) = event_file::read_event_file(path)?;
+    Ok(batch)
+}
+
+#[pyfunction]
+/// Reads only the header of an event file written by [write_event_file]: the format version, counts, run metadata and column offsets.
+///
+/// Arguments: path: `str | Path`
+/// Returns: `EventFileInfo`
+pub fn read_event_file_info(path: std::path::PathBuf) -> PyResult<EventFileInfo> {
+    Ok(event_file::read_event_file_info(path)?)
 }
 
 mod rust_only {
-    use pyo3::exceptions::PyValueError;
+    use pyo3::exceptions::{PyAssertionError, PyBytesWarning, PyValueError};
     use pyo3::PyResult;
+    use std::io::Read;
+    use std::path::Path;
 
-    use crate::decode_hits_fsm::extract_hits;
+    use crate::decode_hits_fsm::{extract_hits_into, HitSink};
+    use crate::decode_options;
     use crate::moss_protocol::MossWord;
     use crate::parse_error::{ParseError, ParseErrorKind};
-    use crate::{MossPacket, Tuple_MossPacket_LastTrailerIdx};
+    use crate::parse_util::{find_non_filler, find_trailer};
+    use crate::{
+        DecodeOptions, DecodeResult, FramingConfig, LastTrailerIdx, MossEventBatch, MossPacket,
+        Tuple_MossPacket_LastTrailerIdx,
+    };
 
-    /// Functions that are only used in Rust and not exposed to Python.
+    // Functions that are only used in Rust and not exposed to Python.
+
+    /// Checks the `skip` and `prepend_buffer` arguments of [decode_n_events](crate::decode_n_events) and [decode_n_events_from_file](crate::decode_n_events_from_file).
+    pub(super) fn check_skip_and_prepend(
+        skip: Option<usize>,
+        prepend_buffer: Option<&Vec<u8>>,
+    ) -> PyResult<()> {
+        if skip.is_some_and(|s| s == 0) {
+            Err(PyValueError::new_err("skip value must be greater than 0"))
+        } else if skip.is_some() && prepend_buffer.is_some() {
+            Err(PyValueError::new_err(
+                "skip and prepend_buffer cannot be used together",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The packets of `result` decoded from bytes if it has `take` of them.
+    ///
+    /// Raises a `BytesWarning` if the bytes end with a partial event, otherwise an `AssertionError`.
+    pub(super) fn take_n_events(result: DecodeResult, take: usize) -> PyResult<Vec<MossPacket>> {
+        let packet_cnt = result.packets.len() + 1;
+        if result.packets.len() >= take {
+            Ok(result.packets)
+        } else if !result.remainder.is_empty() {
+            Err(PyBytesWarning::new_err(format!(
+                "Failed decoding packet #{packet_cnt}: Reached end of bytes with a partial event"
+            )))
+        } else if result.packets.is_empty() {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else {
+            Err(PyAssertionError::new_err(format!(
+                "Failed decoding packet #{packet_cnt}: No Unit Frame Header found"
+            )))
+        }
+    }
+
+    /// The packets of `result` decoded from a file if it has `take` of them.
+    ///
+    /// Raises a `BytesWarning` if there are fewer packets, and an `AssertionError` if there are none.
+    pub(super) fn take_n_events_from_file(
+        result: DecodeResult,
+        take: usize,
+    ) -> PyResult<Vec<MossPacket>> {
+        if result.packets.is_empty() {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else if result.packets.len() < take {
+            Err(PyBytesWarning::new_err(format!(
+                "Taking {take} events failed, got {decoded_cnt} events",
+                decoded_cnt = result.packets.len()
+            )))
+        } else {
+            Ok(result.packets)
+        }
+    }
+
+    /// The `packets` and, if `result` has a partial event, the remainder returned by `events_tail`, `None` if they are empty.
+    ///
+    /// For compatibility the remainder starts right after the last event, including the filler bytes before the partial event,
+    /// while [DecodeResult::remainder] starts at the Unit Frame Header of the partial event.
+    pub(super) fn packets_and_remainder(
+        packets: Vec<MossPacket>,
+        result: &DecodeResult,
+        events_tail: impl FnOnce() -> std::io::Result<Vec<u8>>,
+    ) -> std::io::Result<super::Tuple_List_MossPackets_Remainder> {
+        let remainder = if result.remainder.is_empty() {
+            None
+        } else {
+            Some(events_tail()?)
+        };
+        Ok((
+            Some(packets).filter(|packets| !packets.is_empty()),
+            remainder,
+        ))
+    }
+
+    /// Decodes all events in `bytes` with the decoding loop of [decode_options], optionally attaching [PacketMetadata](crate::PacketMetadata).
+    ///
+    /// Returns the packets and the index of the last byte of the last event.
+    /// Raises a `BytesWarning` if `bytes` ends with a partial event, and an `AssertionError` if there are no events.
+    pub(super) fn decode_all_packets(
+        bytes: &[u8],
+        framing: &FramingConfig,
+        metadata: bool,
+    ) -> PyResult<(Vec<MossPacket>, LastTrailerIdx)> {
+        let mut moss_packets = Vec::with_capacity(calc_prealloc_val(bytes)?);
+        let options = DecodeOptions {
+            metadata,
+            ..Default::default()
+        };
+        let (result, events_end) = decode_options::decode_bytes_to_events_end(
+            bytes,
+            &options,
+            framing,
+            &mut moss_packets,
+        )?;
+        let last_trailer_idx = last_event_end(&result, events_end, moss_packets.len())?;
+        Ok((moss_packets, last_trailer_idx))
+    }
+
+    /// The index of the last byte of the last of `decoded_cnt` events decoded from bytes, see [decode_all_packets].
+    pub(super) fn last_event_end(
+        result: &DecodeResult,
+        events_end: usize,
+        decoded_cnt: usize,
+    ) -> PyResult<LastTrailerIdx> {
+        if !result.remainder.is_empty() {
+            Err(PyBytesWarning::new_err(format!(
+                "Failed decoding packet #{packet_cnt}: Reached end of bytes with a partial event",
+                packet_cnt = decoded_cnt + 1
+            )))
+        } else if decoded_cnt == 0 {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else {
+            Ok(events_end - 1)
+        }
+    }
+
+    /// Decodes all the data from `reader` with the decoding loop of [decode_options], optionally attaching [PacketMetadata](crate::PacketMetadata)
+    /// with the offsets from the start of `reader` to each [MossPacket].
+    ///
+    /// A partial event at the end of the data is ignored.
+    pub(crate) fn decode_from_reader(
+        reader: impl Read,
+        framing: &FramingConfig,
+        metadata: bool,
+        source_file: Option<&Path>,
+    ) -> PyResult<Vec<MossPacket>> {
+        let mut moss_packets = Vec::new();
+        let options = DecodeOptions {
+            metadata,
+            ..Default::default()
+        };
+        _ = decode_options::decode_reader_into(
+            reader,
+            &options,
+            framing,
+            source_file,
+            &mut moss_packets,
+        )?;
+
+        if moss_packets.is_empty() {
+            Err(PyAssertionError::new_err("No MOSS Packets in events"))
+        } else {
+            Ok(moss_packets)
+        }
+    }
 
     const MIN_PREALLOC: usize = 10;
     #[inline]
@@ -577,44 +1317,127 @@ mod rust_only {
     pub(crate) fn extract_packet_from_buf(
         bytes: &[u8],
         prepend_bytes: Option<Vec<u8>>,
+        framing: &FramingConfig,
     ) -> Result<Tuple_MossPacket_LastTrailerIdx, ParseError> {
         // Collect bytes from `bytes` until a header is seen
         if let Some(mut prepend) = prepend_bytes {
             let prepend_count = prepend.len();
-            prepend.extend(
-                bytes
-                    .iter()
-                    .take_while(|b| **b != MossWord::UNIT_FRAME_TRAILER),
-            );
-            prepend.push(MossWord::UNIT_FRAME_TRAILER); // Add the trailer back since `take_while` is EXCLUSIVE
-            extract_packet(&prepend, prepend_count)
+            if let Some(trailer_idx) = find_trailer(bytes) {
+                // Include the trailer and any padding following it
+                let event_end = bytes.len().min(trailer_idx + framing.alignment());
+                prepend.extend_from_slice(&bytes[..event_end]);
+            } else {
+                prepend.extend_from_slice(bytes);
+                prepend.push(MossWord::UNIT_FRAME_TRAILER);
+            }
+            extract_packet(&prepend, prepend_count, framing)
         } else {
-            extract_packet(bytes, 0)
+            extract_packet(bytes, 0, framing)
         }
     }
 
     /// Advances the iterator until a Unit Frame Header is encountered, saves the unit ID,
-    /// and extracts the hits with the [extract_hits] function, before returning a MossPacket if one is found.
+    /// and extracts the hits with the [extract_hits_into] function, before returning a MossPacket if one is found.
     #[inline]
     fn extract_packet(
         bytes: &[u8],
         prepend_byte_cnt: usize,
+        framing: &FramingConfig,
     ) -> Result<Tuple_MossPacket_LastTrailerIdx, ParseError> {
-        // Check that everything before the first header is delimiter bytes
+        // Check that everything before the first header is filler bytes
         //
-        // Takes bytes while they are equal to the delimiter byte
-        // and checks that the first byte that is not equal to the delimiter byte is a valid header byte.
-        let header_idx = find_header_index(bytes)?;
+        // Takes bytes while they are filler bytes
+        // and checks that the first byte that is not a filler byte is a valid header byte.
+        let header_idx = find_header_index(bytes, framing)?;
+
+        extract_packet_at_header(bytes, header_idx, prepend_byte_cnt, framing)
+    }
 
+    /// Same as [extract_packet_from_buf] without a prepend buffer, but also returns the index of the Unit Frame Header
+    /// and the index of the last byte of the event, which is past the trailer if the event is padded.
+    ///
+    /// Returns: `(MossPacket, header index, trailer index, event end index)`
+    #[inline]
+    pub(crate) fn extract_packet_with_header_idx(
+        bytes: &[u8],
+        framing: &FramingConfig,
+    ) -> Result<(MossPacket, usize, LastTrailerIdx, usize), ParseError> {
+        let header_idx = find_header_index(bytes, framing)?;
+        let mut hits = Vec::new();
+        let trailer_idx = extract_hits_at_header(bytes, header_idx, &mut hits)?;
+        let event_end = skip_padding(bytes, header_idx, trailer_idx, framing)?;
+        Ok((
+            MossPacket {
+                unit_id: bytes[header_idx] & 0xF,
+                hits,
+                metadata: None,
+            },
+            header_idx,
+            trailer_idx,
+            event_end,
+        ))
+    }
+
+    /// Extracts the hits following the Unit Frame Header at `header_idx` and returns the MossPacket and the index of the last byte of the event.
+    #[inline]
+    fn extract_packet_at_header(
+        bytes: &[u8],
+        header_idx: usize,
+        prepend_byte_cnt: usize,
+        framing: &FramingConfig,
+    ) -> Result<Tuple_MossPacket_LastTrailerIdx, ParseError> {
+        let mut hits = Vec::new();
+        let trailer_idx = extract_hits_at_header(bytes, header_idx, &mut hits)?;
+        let event_end = skip_padding(bytes, header_idx, trailer_idx, framing)?;
+        // The index is returned relative to the bytes following the prepended bytes
+        let Some(event_end) = event_end.checked_sub(prepend_byte_cnt) else {
+            return Err(ParseError::new(
+                ParseErrorKind::ProtocolError,
+                "Event ended in the prepended bytes, they should only contain the start of an event",
+                event_end,
+            ));
+        };
+        Ok((
+            MossPacket {
+                unit_id: bytes[header_idx] & 0xF,
+                hits,
+                metadata: None,
+            },
+            event_end,
+        ))
+    }
+
+    /// Returns the index of the last padding byte following the trailer at `trailer_idx`,
+    /// or the trailer index if the event is not padded.
+    #[inline]
+    fn skip_padding(
+        bytes: &[u8],
+        header_idx: usize,
+        trailer_idx: usize,
+        framing: &FramingConfig,
+    ) -> Result<usize, ParseError> {
+        let event_end = trailer_idx + framing.padding_after(trailer_idx - header_idx + 1);
+        if event_end < bytes.len() {
+            Ok(event_end)
+        } else {
+            Err(ParseError::new(
+                ParseErrorKind::EndOfBufferNoTrailer,
+                "Reached end of buffer before the end of the padding following the Unit Frame Trailer",
+                bytes.len(),
+            ))
+        }
+    }
+
+    /// Decodes the hits following the Unit Frame Header at `header_idx` into `hits` and returns the trailer index.
+    #[inline]
+    fn extract_hits_at_header(
+        bytes: &[u8],
+        header_idx: usize,
+        hits: &mut impl HitSink,
+    ) -> Result<LastTrailerIdx, ParseError> {
         let mut bytes_iter = bytes.iter().skip(header_idx + 1);
-        match extract_hits(&mut bytes_iter) {
-            Ok(hits) => Ok((
-                MossPacket {
-                    unit_id: bytes[header_idx] & 0xF,
-                    hits,
-                },
-                bytes.len() - bytes_iter.len() - 1 - prepend_byte_cnt,
-            )),
+        match extract_hits_into(&mut bytes_iter, hits) {
+            Ok(()) => Ok(bytes.len() - bytes_iter.len() - 1),
             Err(e) => Err(ParseError::new(
                 e.kind(),
                 &format_error_msg(e.message(), e.err_index() + 1, &bytes[header_idx..]),
@@ -623,39 +1446,65 @@ mod rust_only {
         }
     }
 
-    // Check that everything before the first header is delimiter bytes
-    //
-    // Takes bytes while they are equal to the delimiter byte
-    // and checks that the first byte that is not equal to the delimiter byte is a valid header byte.
-    // Allows the first byte to be the trailer byte, e.g. from a previous event.
+    /// Decodes the first event in `bytes` and appends it to `batch`, returns the trailer index.
+    ///
+    /// If decoding fails the batch is left unchanged.
     #[inline]
-    fn find_header_index(bytes: &[u8]) -> Result<usize, ParseError> {
-        for (i, &b) in bytes.iter().enumerate() {
-            // Allow the first byte to be the trailer byte, e.g. from a previous event.
-            if b == MossWord::DELIMITER || (i == 0 && b == MossWord::UNIT_FRAME_TRAILER) {
-                continue;
-            } else if MossWord::UNIT_FRAME_HEADER_RANGE.contains(&b) {
-                return Ok(i);
-            } else {
-                return Err(ParseError::new(
-                    ParseErrorKind::InvalidDelimiter,
-                    &format_error_msg("Invalid delimiter", i, bytes),
-                    i,
-                ));
+    pub(crate) fn extract_packet_into_batch(
+        bytes: &[u8],
+        batch: &mut MossEventBatch,
+        framing: &FramingConfig,
+    ) -> Result<LastTrailerIdx, ParseError> {
+        let header_idx = find_header_index(bytes, framing)?;
+        batch.begin_event(bytes[header_idx] & 0xF);
+        match extract_hits_at_header(bytes, header_idx, batch)
+            .and_then(|trailer_idx| skip_padding(bytes, header_idx, trailer_idx, framing))
+        {
+            Ok(event_end) => {
+                batch.end_event();
+                Ok(event_end)
+            }
+            Err(e) => {
+                batch.discard_event();
+                Err(e)
             }
         }
-        let byte_count = bytes.len();
-        Err(ParseError::new(
-            ParseErrorKind::NoHeaderFound,
-            "No Unit Frame Header found",
-            byte_count,
-        ))
+    }
+
+    // Check that everything before the first header is filler bytes
+    //
+    // Skips the filler bytes a block at a time
+    // and checks that the first byte that is not a filler byte is a valid header byte.
+    // If the framing allows it, the first byte can be the trailer byte, e.g. from a previous event.
+    #[inline]
+    fn find_header_index(bytes: &[u8], framing: &FramingConfig) -> Result<usize, ParseError> {
+        let start = usize::from(
+            bytes.first() == Some(&MossWord::UNIT_FRAME_TRAILER) && framing.allow_leading_trailer(),
+        );
+        let Some(i) = find_non_filler(&bytes[start..], framing).map(|idx| start + idx) else {
+            return Err(ParseError::new(
+                ParseErrorKind::NoHeaderFound,
+                "No Unit Frame Header found",
+                bytes.len(),
+            ));
+        };
+        if MossWord::UNIT_FRAME_HEADER_RANGE.contains(&bytes[i]) {
+            Ok(i)
+        } else {
+            Err(ParseError::new(
+                ParseErrorKind::InvalidDelimiter,
+                &format_error_msg("Invalid delimiter", i, bytes),
+                i,
+            ))
+        }
     }
 
     /// Formats an error message with an error description and the byte that triggered the error.
     ///
     /// Also includes a dump of the bytes from the header and 10 bytes past the error.
     fn format_error_msg(err_str: &str, err_idx: usize, bytes: &[u8]) -> String {
+        // Point at the last byte if the error is the end of the buffer
+        let err_idx = err_idx.min(bytes.len().saturating_sub(1));
         format!(
         "{err_str}, got: 0x{error_byte:02X}. Dump from header and 10 bytes past error: {prev} [ERROR = {error_byte:02X}] {next}",
         prev = bytes
@@ -664,7 +1513,7 @@ mod rust_only {
             .map(|b| format!("{b:02X}"))
             .collect::<Vec<_>>()
             .join(" "),
-        error_byte = bytes[err_idx],
+        error_byte = bytes.get(err_idx).copied().unwrap_or_default(),
         next = bytes
             .iter()
             .skip(err_idx+1)
@@ -673,49 +1522,41 @@ mod rust_only {
             .collect::<Vec<_>>()
             .join(" "))
     }
+}
 
-    // On error, returns the error and the number of the packet that failed to decode
-    // i.e. if 8 packets are decoded successfully and the 9th packet fails, the error will be returned with 9.
-    pub(crate) fn get_all_packets_from_buf(
-        buf: &[u8],
-    ) -> Result<(Vec<MossPacket>, usize), (ParseError, usize)> {
-        let prealloc = if buf.len() / 1024 > MIN_PREALLOC {
-            buf.len() / 1024
-        } else {
-            MIN_PREALLOC
-        };
-        let mut moss_packets = Vec::with_capacity(prealloc);
-        let mut last_trailer_idx = 0;
-        loop {
-            match extract_packet_from_buf(&buf[last_trailer_idx..], None) {
-                Ok((moss_packet, trailer_idx)) => {
-                    moss_packets.push(moss_packet);
-                    last_trailer_idx += trailer_idx + 1;
-                }
-                Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => {
-                    if moss_packets.is_empty() {
-                        return Err((e, 0));
-                    } else {
-                        break;
-                    }
-                }
-                Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => {
-                    if moss_packets.is_empty() {
-                        return Err((e, 0));
-                    } else {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    if moss_packets.is_empty() {
-                        return Err((e, 0));
-                    } else {
-                        // Return the number of the packet that failed to decode
-                        Err((e, moss_packets.len() + 1))?
-                    }
-                }
-            }
-        }
-        Ok((moss_packets, last_trailer_idx))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    /// Checks the `moss_decoder.pyi` stub against the module and the Rust signatures of the functions, see `tests/check_stub.py`.
+    #[test]
+    fn test_stub_matches_module() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("module", pyo3::wrap_pymodule!(moss_decoder)(py))
+                .unwrap();
+            globals
+                .set_item(
+                    "stub_path",
+                    concat!(env!("CARGO_MANIFEST_DIR"), "/moss_decoder.pyi"),
+                )
+                .unwrap();
+            globals
+                .set_item(
+                    "source_path",
+                    concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib.rs"),
+                )
+                .unwrap();
+            globals
+                .set_item("problems", pyo3::types::PyList::empty(py))
+                .unwrap();
+            py.run(include_str!("../tests/check_stub.py"), Some(globals), None)
+                .unwrap();
+            let problems: Vec<String> = globals.get_item("problems").unwrap().extract().unwrap();
+            assert!(problems.is_empty(), "{}", problems.join("\n"));
+        });
     }
 }
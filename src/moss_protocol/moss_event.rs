@@ -0,0 +1,95 @@
+//! A full-sensor event built from the MOSS packets of all units read out for one trigger.
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use super::{MossHit, MossPacket};
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The [MossPacket]s of all units read out for a single trigger.
+pub struct MossEvent {
+    /// Trigger ID from the readout wrapper, if the stream was wrapped.
+    pub trigger_id: Option<u32>,
+    /// Timestamp from the readout wrapper, if the stream was wrapped.
+    pub timestamp: Option<u64>,
+    /// The packets of the event in the order they were read out.
+    pub packets: Vec<MossPacket>,
+    /// Expected unit IDs that have no packet in the event.
+    pub missing_units: Vec<u8>,
+    /// Unit IDs that have more than one packet in the event.
+    pub duplicate_units: Vec<u8>,
+}
+
+impl MossEvent {
+    /// Iterate over the hits of all units as `(unit ID, hit)` pairs.
+    pub fn iter_hits(&self) -> impl Iterator<Item = (u8, MossHit)> + '_ {
+        self.packets
+            .iter()
+            .flat_map(|p| p.hits.iter().map(|hit| (p.unit_id, *hit)))
+    }
+}
+
+#[pymethods]
+impl MossEvent {
+    /// The hits of all units as `(unit ID, hit)` pairs.
+    pub fn hits(&self) -> Vec<(u8, MossHit)> {
+        self.iter_hits().collect()
+    }
+
+    /// The total number of hits of all units.
+    pub fn hit_count(&self) -> usize {
+        self.packets.iter().map(|p| p.hits.len()).sum()
+    }
+
+    /// The unit IDs of the packets in the event in the order they were read out.
+    pub fn unit_ids(&self) -> Vec<u8> {
+        self.packets.iter().map(|p| p.unit_id).collect()
+    }
+
+    /// Returns true if every expected unit has exactly one packet in the event.
+    pub fn is_complete(&self) -> bool {
+        self.missing_units.is_empty() && self.duplicate_units.is_empty()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}({:?} {:?} units: {:?} missing: {:?} duplicate: {:?})",
+            class_name,
+            slf.trigger_id,
+            slf.timestamp,
+            slf.unit_ids(),
+            slf.missing_units,
+            slf.duplicate_units
+        ))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for MossEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(trigger_id) = self.trigger_id {
+            write(f, format_args!("Trigger: {trigger_id} "))?;
+        }
+        write(
+            f,
+            format_args!(
+                "Units: {units:?} Hits: {hits}",
+                units = self.unit_ids(),
+                hits = self.hit_count()
+            ),
+        )?;
+        if !self.missing_units.is_empty() {
+            write(f, format_args!(" Missing: {:?}", self.missing_units))?;
+        }
+        if !self.duplicate_units.is_empty() {
+            write(f, format_args!(" Duplicate: {:?}", self.duplicate_units))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,717 @@
+//! Columnar storage of the hits of many MOSS events.
+//!
+//! From Python the arrays of a batch are [BatchColumn]s exposing the memory of the batch through the buffer protocol,
+//! and iterating a batch yields [PyEventView]s, neither copies the hits.
+use pyo3::exceptions::{PyBufferError, PyIndexError};
+use pyo3::prelude::*;
+use std::ffi::{c_int, CStr};
+use std::fmt::{write, Display};
+use std::ops::Range;
+
+use super::{MossHit, MossPacket};
+use crate::decode_hits_fsm::HitSink;
+
+#[pyclass(module = "moss_decoder", frozen)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "BatchColumns")
+)]
+/// The hits of many MOSS events stored as a structure of arrays.
+///
+/// All hits are stored contiguously in the `regions`, `rows` and `columns` arrays,
+/// the hits of event `i` are at the indexes `event_offsets[i]..event_offsets[i + 1]`.
+/// Decoding into a batch does not allocate per event.
+///
+/// The batch can't be modified from Python, so the arrays can be shared with Python without copying them.
+pub struct MossEventBatch {
+    unit_ids: Vec<u8>,
+    regions: Vec<u8>,
+    rows: Vec<u16>,
+    columns: Vec<u16>,
+    /// Always starts with 0 and has one more entry than there are events
+    event_offsets: Vec<usize>,
+}
+
+/// A view of a single event in a [MossEventBatch].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MossEventView<'a> {
+    /// The unit ID of the event.
+    pub unit_id: u8,
+    /// The region IDs of the hits in the event.
+    pub regions: &'a [u8],
+    /// The rows of the hits in the event.
+    pub rows: &'a [u16],
+    /// The columns of the hits in the event.
+    pub columns: &'a [u16],
+}
+
+impl MossEventView<'_> {
+    /// The number of hits in the event.
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Returns true if the event has no hits.
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Iterate over the hits of the event.
+    pub fn hits(&self) -> impl ExactSizeIterator<Item = MossHit> + '_ {
+        (0..self.len()).map(|i| MossHit {
+            region: self.regions[i],
+            row: self.rows[i],
+            column: self.columns[i],
+        })
+    }
+
+    /// Copy the event into a [MossPacket].
+    pub fn to_packet(&self) -> MossPacket {
+        MossPacket {
+            unit_id: self.unit_id,
+            hits: self.hits().collect(),
+            metadata: None,
+        }
+    }
+}
+
+impl Default for MossEventBatch {
+    fn default() -> Self {
+        Self::with_capacity(0, 0)
+    }
+}
+
+impl MossEventBatch {
+    /// Create an empty batch with room for `events` events and `hits` hits.
+    pub fn with_capacity(events: usize, hits: usize) -> Self {
+        let mut event_offsets = Vec::with_capacity(events + 1);
+        event_offsets.push(0);
+        Self {
+            unit_ids: Vec::with_capacity(events),
+            regions: Vec::with_capacity(hits),
+            rows: Vec::with_capacity(hits),
+            columns: Vec::with_capacity(hits),
+            event_offsets,
+        }
+    }
+
+    /// The number of events in the batch.
+    pub fn len(&self) -> usize {
+        self.unit_ids.len()
+    }
+
+    /// Returns true if there are no events in the batch.
+    pub fn is_empty(&self) -> bool {
+        self.unit_ids.is_empty()
+    }
+
+    /// The total number of hits in all events.
+    pub fn hit_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// The unit ID of each event.
+    pub fn unit_ids(&self) -> &[u8] {
+        &self.unit_ids
+    }
+
+    /// The region ID of every hit.
+    pub fn regions(&self) -> &[u8] {
+        &self.regions
+    }
+
+    /// The row of every hit.
+    pub fn rows(&self) -> &[u16] {
+        &self.rows
+    }
+
+    /// The column of every hit.
+    pub fn columns(&self) -> &[u16] {
+        &self.columns
+    }
+
+    /// The index of the first hit of each event, followed by the total number of hits.
+    pub fn event_offsets(&self) -> &[usize] {
+        &self.event_offsets
+    }
+
+    /// Create a batch from its arrays, checking that they are consistent, see [MossEventBatch].
+    pub fn from_columns(
+        unit_ids: Vec<u8>,
+        regions: Vec<u8>,
+        rows: Vec<u16>,
+        columns: Vec<u16>,
+        event_offsets: Vec<usize>,
+    ) -> Result<Self, String> {
+        if rows.len() != regions.len() || columns.len() != regions.len() {
+            return Err(format!(
+                "Hit arrays differ in length: {} regions, {} rows, {} columns",
+                regions.len(),
+                rows.len(),
+                columns.len()
+            ));
+        }
+        if event_offsets.len() != unit_ids.len() + 1
+            || event_offsets.first() != Some(&0)
+            || event_offsets.last() != Some(&regions.len())
+            || event_offsets.windows(2).any(|w| w[0] > w[1])
+        {
+            return Err(format!(
+                "Event offsets must increase from 0 to {} hits with one entry more than the {} events",
+                regions.len(),
+                unit_ids.len()
+            ));
+        }
+        Ok(Self {
+            unit_ids,
+            regions,
+            rows,
+            columns,
+            event_offsets,
+        })
+    }
+
+    /// Get a view of event `i`.
+    pub fn get(&self, i: usize) -> Option<MossEventView<'_>> {
+        let unit_id = *self.unit_ids.get(i)?;
+        let hits = self.event_offsets[i]..self.event_offsets[i + 1];
+        Some(MossEventView {
+            unit_id,
+            regions: &self.regions[hits.clone()],
+            rows: &self.rows[hits.clone()],
+            columns: &self.columns[hits],
+        })
+    }
+
+    /// Iterate over views of all events in the batch.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = MossEventView<'_>> {
+        (0..self.len()).map(|i| self.get(i).expect("index is within the batch"))
+    }
+
+    /// Copy all events into [MossPacket]s.
+    pub fn to_packets(&self) -> Vec<MossPacket> {
+        self.iter().map(|event| event.to_packet()).collect()
+    }
+
+    /// Add an event, the hits are added with [HitSink::push_hit] and the event is finished with [Self::end_event].
+    pub(crate) fn begin_event(&mut self, unit_id: u8) {
+        self.unit_ids.push(unit_id);
+    }
+
+    /// Finish the event started with [Self::begin_event].
+    pub(crate) fn end_event(&mut self) {
+        self.event_offsets.push(self.regions.len());
+    }
+
+    /// Remove the event started with [Self::begin_event] and any hits added to it.
+    pub(crate) fn discard_event(&mut self) {
+        _ = self.unit_ids.pop();
+        let hits_end = *self.event_offsets.last().expect("never empty");
+        self.regions.truncate(hits_end);
+        self.rows.truncate(hits_end);
+        self.columns.truncate(hits_end);
+    }
+}
+
+impl HitSink for MossEventBatch {
+    #[inline]
+    fn push_hit(&mut self, hit: MossHit) {
+        self.regions.push(hit.region);
+        self.rows.push(hit.row);
+        self.columns.push(hit.column);
+    }
+}
+
+/// The arrays of a deserialized [MossEventBatch] before they are checked to be consistent.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BatchColumns {
+    unit_ids: Vec<u8>,
+    regions: Vec<u8>,
+    rows: Vec<u16>,
+    columns: Vec<u16>,
+    event_offsets: Vec<usize>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<BatchColumns> for MossEventBatch {
+    type Error = String;
+
+    fn try_from(batch: BatchColumns) -> Result<Self, Self::Error> {
+        Self::from_columns(
+            batch.unit_ids,
+            batch.regions,
+            batch.rows,
+            batch.columns,
+            batch.event_offsets,
+        )
+    }
+}
+
+impl From<&[MossPacket]> for MossEventBatch {
+    fn from(packets: &[MossPacket]) -> Self {
+        let mut batch =
+            Self::with_capacity(packets.len(), packets.iter().map(|p| p.hits.len()).sum());
+        for packet in packets {
+            batch.begin_event(packet.unit_id);
+            packet.hits.iter().for_each(|hit| batch.push_hit(*hit));
+            batch.end_event();
+        }
+        batch
+    }
+}
+
+impl FromIterator<MossPacket> for MossEventBatch {
+    fn from_iter<T: IntoIterator<Item = MossPacket>>(packets: T) -> Self {
+        let mut batch = Self::default();
+        for packet in packets {
+            batch.begin_event(packet.unit_id);
+            packet.hits.into_iter().for_each(|hit| batch.push_hit(hit));
+            batch.end_event();
+        }
+        batch
+    }
+}
+
+impl From<MossEventBatch> for Vec<MossPacket> {
+    fn from(batch: MossEventBatch) -> Self {
+        batch.to_packets()
+    }
+}
+
+#[pymethods]
+impl MossEventBatch {
+    #[new]
+    fn py_new() -> Self {
+        Self::default()
+    }
+
+    fn __len__(&self) -> usize {
+        self.len()
+    }
+
+    /// Returns event `i` as a [MossPacket].
+    fn __getitem__(&self, i: isize) -> PyResult<MossPacket> {
+        let idx = py_index(i, self.len(), "event")?;
+        Ok(self
+            .get(idx)
+            .expect("index is within the batch")
+            .to_packet())
+    }
+
+    /// Iterates over views of the events, see [PyEventView].
+    fn __iter__(slf: PyRef<'_, Self>) -> PyEventViewIter {
+        PyEventViewIter {
+            batch: slf.into(),
+            next: 0,
+        }
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!(
+            "{}({} events {} hits)",
+            class_name,
+            slf.borrow().len(),
+            slf.borrow().hit_count()
+        ))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    /// Converts the batch to a list of [MossPacket]s.
+    #[pyo3(name = "to_packets")]
+    fn py_to_packets(&self) -> Vec<MossPacket> {
+        self.to_packets()
+    }
+
+    /// Creates a batch from a list of [MossPacket]s.
+    #[staticmethod]
+    fn from_packets(packets: Vec<MossPacket>) -> Self {
+        packets.into_iter().collect()
+    }
+
+    #[getter(unit_ids)]
+    fn py_unit_ids(slf: PyRef<'_, Self>) -> BatchColumn {
+        let len = slf.len();
+        BatchColumn::new(slf.into(), Column::UnitIds, 0..len)
+    }
+
+    #[getter(regions)]
+    fn py_regions(slf: PyRef<'_, Self>) -> BatchColumn {
+        let len = slf.hit_count();
+        BatchColumn::new(slf.into(), Column::Regions, 0..len)
+    }
+
+    #[getter(rows)]
+    fn py_rows(slf: PyRef<'_, Self>) -> BatchColumn {
+        let len = slf.hit_count();
+        BatchColumn::new(slf.into(), Column::Rows, 0..len)
+    }
+
+    #[getter(columns)]
+    fn py_columns(slf: PyRef<'_, Self>) -> BatchColumn {
+        let len = slf.hit_count();
+        BatchColumn::new(slf.into(), Column::Columns, 0..len)
+    }
+
+    #[getter(event_offsets)]
+    fn py_event_offsets(slf: PyRef<'_, Self>) -> BatchColumn {
+        let len = slf.event_offsets.len();
+        BatchColumn::new(slf.into(), Column::EventOffsets, 0..len)
+    }
+}
+
+/// The index `i` into a sequence of `len` items, counting from the end if negative.
+fn py_index(i: isize, len: usize, what: &str) -> PyResult<usize> {
+    let idx = if i < 0 { i + len as isize } else { i };
+    usize::try_from(idx)
+        .ok()
+        .filter(|&idx| idx < len)
+        .ok_or_else(|| PyIndexError::new_err(format!("{what} index out of range")))
+}
+
+/// An array of a [MossEventBatch].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    UnitIds,
+    Regions,
+    Rows,
+    Columns,
+    EventOffsets,
+}
+
+#[pyclass(module = "moss_decoder", frozen)]
+/// A read-only view of (part of) an array of a [MossEventBatch], e.g. `batch.rows` or the rows of an event.
+///
+/// Supports `len()`, indexing and the buffer protocol, so `memoryview(batch.rows)` or `numpy.asarray(batch.rows)`
+/// use the memory of the batch without copying it. The view keeps the batch alive.
+pub struct BatchColumn {
+    batch: Py<MossEventBatch>,
+    column: Column,
+    range: Range<usize>,
+    /// The shape and strides of the exported buffer, they must stay valid as long as the buffer is used.
+    shape: isize,
+    stride: isize,
+}
+
+impl BatchColumn {
+    fn new(batch: Py<MossEventBatch>, column: Column, range: Range<usize>) -> Self {
+        let (_, itemsize, _) = Self::layout(batch.get(), column);
+        Self {
+            batch,
+            column,
+            shape: range.len() as isize,
+            stride: itemsize as isize,
+            range,
+        }
+    }
+
+    /// The pointer to the first item, item size and buffer format of `column` of `batch`.
+    fn layout(batch: &MossEventBatch, column: Column) -> (*const u8, usize, &'static CStr) {
+        fn raw<T>(items: &[T], format: &'static CStr) -> (*const u8, usize, &'static CStr) {
+            (items.as_ptr().cast(), std::mem::size_of::<T>(), format)
+        }
+        match column {
+            Column::UnitIds => raw(&batch.unit_ids, c"B"),
+            Column::Regions => raw(&batch.regions, c"B"),
+            Column::Rows => raw(&batch.rows, c"H"),
+            Column::Columns => raw(&batch.columns, c"H"),
+            Column::EventOffsets => raw(&batch.event_offsets, c"N"),
+        }
+    }
+
+    /// The item at index `idx` of the whole array.
+    fn item(&self, idx: usize) -> usize {
+        let batch = self.batch.get();
+        match self.column {
+            Column::UnitIds => usize::from(batch.unit_ids[idx]),
+            Column::Regions => usize::from(batch.regions[idx]),
+            Column::Rows => usize::from(batch.rows[idx]),
+            Column::Columns => usize::from(batch.columns[idx]),
+            Column::EventOffsets => batch.event_offsets[idx],
+        }
+    }
+}
+
+#[pymethods]
+impl BatchColumn {
+    fn __len__(&self) -> usize {
+        self.range.len()
+    }
+
+    fn __getitem__(&self, i: isize) -> PyResult<usize> {
+        let idx = py_index(i, self.range.len(), "column")?;
+        Ok(self.item(self.range.start + idx))
+    }
+
+    /// Copies the items to a list.
+    fn to_list(&self) -> Vec<usize> {
+        self.range.clone().map(|idx| self.item(idx)).collect()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({:?})", class_name, slf.get().to_list()))
+    }
+
+    /// Exports the items as a read-only one dimensional buffer of the native unsigned integer type.
+    ///
+    /// # Safety
+    /// `view` must be NULL or valid for writing a `Py_buffer`, as passed by Python.
+    unsafe fn __getbuffer__(
+        slf: &PyCell<Self>,
+        view: *mut pyo3::ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        use pyo3::{ffi, AsPyPointer};
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Batch columns are read-only"));
+        }
+        let column = slf.get();
+        let (ptr, itemsize, format) = Self::layout(column.batch.get(), column.column);
+        // The batch is frozen and kept alive by `obj`, so the memory stays valid as long as the buffer
+        (*view).obj = ffi::_Py_NewRef(slf.as_ptr());
+        (*view).buf = ptr.add(column.range.start * itemsize).cast_mut().cast();
+        (*view).len = column.shape * column.stride;
+        (*view).readonly = 1;
+        (*view).itemsize = column.stride;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            format.as_ptr().cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            std::ptr::addr_of!(column.shape).cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            std::ptr::addr_of!(column.stride).cast_mut()
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).suboffsets = std::ptr::null_mut();
+        (*view).internal = std::ptr::null_mut();
+        Ok(())
+    }
+}
+
+#[pyclass(name = "MossEventView", module = "moss_decoder", frozen)]
+/// A view of event `index` of a [MossEventBatch] as yielded by iterating the batch, the Python counterpart of [MossEventView].
+///
+/// Has the `unit_id` and `hits` of a [MossPacket], the hit arrays are [BatchColumn]s of the batch.
+pub struct PyEventView {
+    batch: Py<MossEventBatch>,
+    index: usize,
+}
+
+impl PyEventView {
+    fn event(&self) -> MossEventView<'_> {
+        self.batch
+            .get()
+            .get(self.index)
+            .expect("index is within the batch")
+    }
+
+    fn column(&self, py: Python<'_>, column: Column) -> BatchColumn {
+        let offsets = &self.batch.get().event_offsets;
+        BatchColumn::new(
+            self.batch.clone_ref(py),
+            column,
+            offsets[self.index]..offsets[self.index + 1],
+        )
+    }
+}
+
+#[pymethods]
+impl PyEventView {
+    /// The index of the event in the batch.
+    #[getter]
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The unit ID of the event.
+    #[getter]
+    fn unit_id(&self) -> u8 {
+        self.event().unit_id
+    }
+
+    /// The hits of the event, copied to [MossHit]s.
+    #[getter]
+    fn hits(&self) -> Vec<MossHit> {
+        self.event().hits().collect()
+    }
+
+    /// The region IDs of the hits of the event.
+    #[getter]
+    fn regions(&self, py: Python<'_>) -> BatchColumn {
+        self.column(py, Column::Regions)
+    }
+
+    /// The rows of the hits of the event.
+    #[getter]
+    fn rows(&self, py: Python<'_>) -> BatchColumn {
+        self.column(py, Column::Rows)
+    }
+
+    /// The columns of the hits of the event.
+    #[getter]
+    fn columns(&self, py: Python<'_>) -> BatchColumn {
+        self.column(py, Column::Columns)
+    }
+
+    fn __len__(&self) -> usize {
+        self.event().len()
+    }
+
+    /// Copies the event into a [MossPacket].
+    fn to_packet(&self) -> MossPacket {
+        self.event().to_packet()
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let view = slf.get();
+        Ok(format!(
+            "{}(index: {} unit ID: {} hits: {})",
+            class_name,
+            view.index,
+            view.event().unit_id,
+            view.event().len()
+        ))
+    }
+}
+
+#[pyclass(module = "moss_decoder")]
+/// Iterator over the events of a [MossEventBatch], yielding a [PyEventView] for each event.
+pub struct PyEventViewIter {
+    batch: Py<MossEventBatch>,
+    next: usize,
+}
+
+#[pymethods]
+impl PyEventViewIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> Option<PyEventView> {
+        let index = slf.next;
+        (index < slf.batch.get().len()).then(|| {
+            slf.next += 1;
+            PyEventView {
+                batch: slf.batch.clone_ref(py),
+                index,
+            }
+        })
+    }
+}
+
+impl Display for MossEventBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Events: {events} Hits: {hits}",
+                events = self.len(),
+                hits = self.hit_count()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_batch_round_trip() {
+        let packets = vec![
+            MossPacket {
+                unit_id: 1,
+                hits: vec![
+                    MossHit {
+                        region: 0,
+                        row: 2,
+                        column: 8,
+                    },
+                    MossHit {
+                        region: 3,
+                        row: 301,
+                        column: 433,
+                    },
+                ],
+                metadata: None,
+            },
+            MossPacket::new(2),
+            MossPacket {
+                unit_id: 3,
+                hits: vec![MossHit {
+                    region: 1,
+                    row: 5,
+                    column: 7,
+                }],
+                metadata: None,
+            },
+        ];
+
+        let batch = MossEventBatch::from(packets.as_slice());
+
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch.hit_count(), 3);
+        assert_eq!(batch.event_offsets(), &[0, 2, 2, 3]);
+        assert!(batch.get(1).unwrap().is_empty());
+        assert!(batch.get(3).is_none());
+        assert_eq!(batch.to_packets(), packets);
+    }
+
+    #[test]
+    fn test_discard_event() {
+        let mut batch = MossEventBatch::default();
+        batch.begin_event(1);
+        batch.push_hit(MossHit::default());
+        batch.end_event();
+        batch.begin_event(2);
+        batch.push_hit(MossHit::default());
+        batch.discard_event();
+
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch.hit_count(), 1);
+        assert_eq!(batch.event_offsets(), &[0, 1]);
+    }
+
+    #[test]
+    fn test_from_columns_checks_consistency() {
+        let batch = MossEventBatch::from_columns(
+            vec![1, 2],
+            vec![0, 3],
+            vec![2, 5],
+            vec![8, 7],
+            vec![0, 1, 2],
+        )
+        .unwrap();
+        assert_eq!(batch.get(1).unwrap().to_packet().hits[0].region, 3);
+
+        assert!(
+            MossEventBatch::from_columns(vec![1], vec![0], vec![], vec![8], vec![0, 1]).is_err()
+        );
+        assert!(
+            MossEventBatch::from_columns(vec![1], vec![0], vec![2], vec![8], vec![0, 2]).is_err()
+        );
+        assert!(
+            MossEventBatch::from_columns(vec![1, 2], vec![0], vec![2], vec![8], vec![0, 1])
+                .is_err()
+        );
+    }
+}
@@ -1,11 +1,15 @@
 //! struct representation of a single hit from a MOSS region.
 use pyo3::prelude::*;
 use pyo3::pyclass::CompareOp;
+use pyo3::types::PyType;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::write;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
-#[pyclass(get_all)]
-#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single hit from a MOSS region.
 pub struct MossHit {
     /// The region ID of the hit.
@@ -43,10 +47,28 @@ impl MossHit {
         self.to_string()
     }
 
+    /// Hits are ordered by region, then row, then column.
     fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
-        op.matches(self.region.cmp(&other.region))
-            && op.matches(self.row.cmp(&other.row))
-            && op.matches(self.column.cmp(&other.column))
+        op.matches(self.cmp(other))
+    }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn __copy__(&self) -> Self {
+        *self
+    }
+
+    fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        *self
+    }
+
+    fn __reduce__(slf: &PyCell<Self>) -> (&PyType, (u8, u16, u16)) {
+        let hit = *slf.borrow();
+        (slf.get_type(), (hit.region, hit.row, hit.column))
     }
 }
 
@@ -75,4 +97,25 @@ mod tests {
         println!("{moss_hit}");
         println!("{str}", str = moss_hit.__str__());
     }
+
+    #[test]
+    fn test_moss_hit_ordering() {
+        let hit = MossHit::new(1, 5, 5);
+        let smaller_row = MossHit::new(1, 4, 300);
+        let larger_region = MossHit::new(2, 0, 0);
+
+        assert!(smaller_row.__richcmp__(&hit, CompareOp::Lt));
+        assert!(!smaller_row.__richcmp__(&hit, CompareOp::Gt));
+        assert!(larger_region.__richcmp__(&hit, CompareOp::Gt));
+        assert!(hit.__richcmp__(&hit, CompareOp::Le));
+        assert!(hit.__richcmp__(&smaller_row, CompareOp::Ne));
+    }
+
+    #[test]
+    fn test_moss_hit_hash_consistent_with_eq() {
+        let hit = MossHit::new(3, 100, 200);
+
+        assert_eq!(hit.__hash__(), MossHit::new(3, 100, 200).__hash__());
+        assert_ne!(hit.__hash__(), MossHit::new(3, 200, 100).__hash__());
+    }
 }
@@ -1,17 +1,34 @@
 //! MOSS packet structure implementation.
-use pyo3::{prelude::*, pyclass::CompareOp};
+use pyo3::{prelude::*, pyclass::CompareOp, types::PyType};
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::{write, Display};
+use std::hash::{Hash, Hasher};
 
-use super::MossHit;
+use super::{MossHit, PacketMetadata};
 
-#[pyclass(get_all)]
-#[derive(Debug, Default, Clone, PartialEq)]
+#[pyclass(module = "moss_decoder")]
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// A single MOSS packet with the associated [MossHit]s.
+///
+/// Packets are equal if their unit IDs and hits are equal, the metadata is not compared.
 pub struct MossPacket {
     /// The unit ID of the packet.
+    #[pyo3(get)]
     pub unit_id: u8,
     /// The hits in the packet.
+    #[pyo3(get)]
     pub hits: Vec<MossHit>,
+    /// Where the packet was found in the decoded stream, if requested when decoding.
+    ///
+    /// Boxed to keep packets without metadata small.
+    pub metadata: Option<Box<PacketMetadata>>,
+}
+
+impl PartialEq for MossPacket {
+    fn eq(&self, other: &Self) -> bool {
+        self.unit_id == other.unit_id && self.hits == other.hits
+    }
 }
 
 #[pymethods]
@@ -21,9 +38,15 @@ impl MossPacket {
         Self {
             unit_id,
             hits: Vec::new(),
+            metadata: None,
         }
     }
 
+    #[getter]
+    fn get_metadata(&self) -> Option<PacketMetadata> {
+        self.metadata.as_deref().cloned()
+    }
+
     fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
         let class_name: &str = slf.get_type().name()?;
         Ok(format!(
@@ -38,8 +61,44 @@ impl MossPacket {
         self.to_string()
     }
 
+    /// Packets are ordered by unit ID, then by their hits. The metadata is not compared.
     fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
-        op.matches(self.unit_id.cmp(&other.unit_id)) && op.matches(self.hits.cmp(&other.hits))
+        op.matches(
+            self.unit_id
+                .cmp(&other.unit_id)
+                .then_with(|| self.hits.cmp(&other.hits)),
+        )
+    }
+
+    /// Hashes the same fields that are compared, the metadata is not included.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.unit_id.hash(&mut hasher);
+        self.hits.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn __copy__(&self) -> Self {
+        self.clone()
+    }
+
+    fn __deepcopy__(&self, _memo: &PyAny) -> Self {
+        self.clone()
+    }
+
+    fn __reduce__(slf: &PyCell<Self>) -> (&PyType, (u8,), (Vec<MossHit>, Option<PacketMetadata>)) {
+        let packet = slf.borrow();
+        (slf.get_type(), (packet.unit_id,), packet.__getstate__())
+    }
+
+    fn __getstate__(&self) -> (Vec<MossHit>, Option<PacketMetadata>) {
+        (self.hits.clone(), self.get_metadata())
+    }
+
+    fn __setstate__(&mut self, state: (Vec<MossHit>, Option<PacketMetadata>)) {
+        let (hits, metadata) = state;
+        self.hits = hits;
+        self.metadata = metadata.map(Box::new);
     }
 }
 
@@ -76,4 +135,48 @@ mod tests {
             assert_eq!(p.unit_id, i as u8);
         });
     }
+
+    #[test]
+    fn test_moss_packet_ordering() {
+        let mut packet = MossPacket::new(1);
+        packet.hits.push(MossHit {
+            region: 0,
+            row: 10,
+            column: 10,
+        });
+        let fewer_hits_larger_unit = MossPacket::new(2);
+
+        // The unit ID decides before the hits are compared
+        assert!(packet.__richcmp__(&fewer_hits_larger_unit, CompareOp::Lt));
+        assert!(!packet.__richcmp__(&fewer_hits_larger_unit, CompareOp::Gt));
+        assert!(MossPacket::new(1).__richcmp__(&packet, CompareOp::Lt));
+        assert!(packet.__richcmp__(&packet.clone(), CompareOp::Ge));
+    }
+
+    #[test]
+    fn test_moss_packet_hash_ignores_metadata() {
+        let packet = MossPacket::new(4);
+        let mut with_metadata = packet.clone();
+        with_metadata.metadata = Some(Box::new(PacketMetadata::new(0, 5, 0)));
+
+        assert!(packet.__richcmp__(&with_metadata, CompareOp::Eq));
+        assert_eq!(packet, with_metadata);
+        assert_eq!(packet.__hash__(), with_metadata.__hash__());
+    }
+
+    #[test]
+    fn test_moss_packet_state_round_trip() {
+        let mut packet = MossPacket::new(7);
+        packet.hits.push(MossHit {
+            region: 2,
+            row: 1,
+            column: 3,
+        });
+        packet.metadata = Some(Box::new(PacketMetadata::new(10, 20, 1)));
+
+        let mut restored = MossPacket::new(packet.unit_id);
+        restored.__setstate__(packet.__getstate__());
+        assert_eq!(restored, packet);
+        assert_eq!(restored.metadata, packet.metadata);
+    }
 }
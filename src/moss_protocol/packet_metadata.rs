@@ -0,0 +1,130 @@
+//! Positional and provenance information about a decoded [MossPacket](super::MossPacket).
+use pyo3::prelude::*;
+use pyo3::types::PyType;
+use std::fmt::{write, Display};
+use std::path::PathBuf;
+
+/// The arguments of the Python constructor, in order.
+type PacketMetadataArgs = (
+    usize,
+    usize,
+    usize,
+    Option<PathBuf>,
+    Option<u32>,
+    Option<u64>,
+);
+
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Where a [MossPacket](super::MossPacket) was found in the decoded stream.
+///
+/// Only attached to packets by the decoding functions that explicitly opt in to it.
+pub struct PacketMetadata {
+    /// Byte offset of the Unit Frame Header.
+    pub header_offset: usize,
+    /// Byte offset of the Unit Frame Trailer.
+    pub trailer_offset: usize,
+    /// Sequence number of the packet in the stream, starting from 0.
+    pub sequence: usize,
+    /// The file the packet was decoded from, if it was decoded from a file.
+    pub source_file: Option<PathBuf>,
+    /// Trigger ID from the readout wrapper record the packet was in, if the stream is wrapped.
+    pub trigger_id: Option<u32>,
+    /// Timestamp from the readout wrapper record the packet was in, if the stream is wrapped.
+    pub timestamp: Option<u64>,
+}
+
+impl PacketMetadata {
+    pub(crate) fn new(header_offset: usize, trailer_offset: usize, sequence: usize) -> Self {
+        Self {
+            header_offset,
+            trailer_offset,
+            sequence,
+            source_file: None,
+            trigger_id: None,
+            timestamp: None,
+        }
+    }
+}
+
+#[pymethods]
+impl PacketMetadata {
+    #[new]
+    #[pyo3(signature = (header_offset, trailer_offset, sequence, source_file = None, trigger_id = None, timestamp = None))]
+    fn py_new(
+        header_offset: usize,
+        trailer_offset: usize,
+        sequence: usize,
+        source_file: Option<PathBuf>,
+        trigger_id: Option<u32>,
+        timestamp: Option<u64>,
+    ) -> Self {
+        Self {
+            header_offset,
+            trailer_offset,
+            sequence,
+            source_file,
+            trigger_id,
+            timestamp,
+        }
+    }
+
+    fn __reduce__(slf: &PyCell<Self>) -> (&PyType, PacketMetadataArgs) {
+        let metadata = slf.borrow().clone();
+        (
+            slf.get_type(),
+            (
+                metadata.header_offset,
+                metadata.trailer_offset,
+                metadata.sequence,
+                metadata.source_file,
+                metadata.trigger_id,
+                metadata.timestamp,
+            ),
+        )
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}({} {} {} {:?} {:?} {:?})",
+            class_name,
+            slf.header_offset,
+            slf.trailer_offset,
+            slf.sequence,
+            slf.source_file,
+            slf.trigger_id,
+            slf.timestamp
+        ))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for PacketMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "#{seq} header: {header} trailer: {trailer}",
+                seq = self.sequence,
+                header = self.header_offset,
+                trailer = self.trailer_offset,
+            ),
+        )?;
+        if let Some(source_file) = &self.source_file {
+            write(f, format_args!(" file: {}", source_file.display()))?;
+        }
+        if let Some(trigger_id) = self.trigger_id {
+            write(f, format_args!(" trigger: {trigger_id}"))?;
+        }
+        if let Some(timestamp) = self.timestamp {
+            write(f, format_args!(" timestamp: {timestamp}"))?;
+        }
+        Ok(())
+    }
+}
@@ -183,3 +183,21 @@ pub fn fake_event_protocol_error_fb_in_idle() -> Vec<u8> {
         UNIT_FRAME_TRAILER, // index 34
     ]
 }
+
+/// Encodes a [MossPacket](super::MossPacket) as a MOSS event with a region header for each region.
+///
+/// The hits of each region are encoded in the order they appear in the packet,
+/// so decoding the event returns the hits sorted by region.
+pub fn encode_event(packet: &super::MossPacket) -> Vec<u8> {
+    let mut bytes = vec![0xD0 | packet.unit_id];
+    for region in 0..4 {
+        bytes.push(REGION_HEADER_0 | region);
+        for hit in packet.hits.iter().filter(|hit| hit.region == region) {
+            bytes.push((hit.row >> 3) as u8 & 0x3F); // Data 0: row position [8:3]
+            bytes.push(0x40 | ((hit.row & 0x7) as u8) << 3 | (hit.column >> 6) as u8 & 0x7); // Data 1: row [2:0], col [8:6]
+            bytes.push(0x80 | (hit.column & 0x3F) as u8); // Data 2: col [5:0]
+        }
+    }
+    bytes.push(UNIT_FRAME_TRAILER);
+    bytes
+}
@@ -0,0 +1,369 @@
+//! Outer framing a readout system can wrap around MOSS frames.
+//!
+//! A wrapped stream is a sequence of records, each carrying a trigger ID, a timestamp and a payload of raw MOSS data.
+//! The record format is pluggable through the [OuterFraming] trait. [ReadoutWrapper] implements an example record format,
+//! it is not the format of any particular readout firmware, other formats are supported by implementing [OuterFraming].
+use pyo3::exceptions::{PyAssertionError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::fmt::{write, Display};
+
+use crate::{decode_options, DecodeOptions, FramingConfig, MossPacket};
+
+/// A record parsed from a wrapped stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrapperRecord<'a> {
+    /// The trigger ID of the record.
+    pub trigger_id: u32,
+    /// The timestamp of the record.
+    pub timestamp: u64,
+    /// Byte offset of the payload from the start of the record.
+    pub payload_offset: usize,
+    /// The raw MOSS data in the record.
+    pub payload: &'a [u8],
+    /// Length of the whole record in bytes.
+    pub len: usize,
+}
+
+/// An invalid record in a wrapped stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    /// The record does not start with the expected magic number.
+    InvalidMagic {
+        /// The magic number found.
+        got: u16,
+        /// The magic number expected.
+        expected: u16,
+    },
+    /// The checksum stored in the record does not match the calculated checksum.
+    ChecksumMismatch {
+        /// The trigger ID of the record.
+        trigger_id: u32,
+        /// The checksum stored in the record.
+        got: u16,
+        /// The checksum calculated from the record.
+        expected: u16,
+    },
+    /// Any other error of a record format, described by the message.
+    Invalid(String),
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic { got, expected } => write(
+                f,
+                format_args!("Invalid record magic, got: 0x{got:04X}, expected: 0x{expected:04X}"),
+            ),
+            Self::ChecksumMismatch {
+                trigger_id,
+                got,
+                expected,
+            } => write(
+                f,
+                format_args!(
+                    "Checksum mismatch in record with trigger ID {trigger_id}, got: 0x{got:04X}, expected: 0x{expected:04X}"
+                ),
+            ),
+            Self::Invalid(message) => write(f, format_args!("{message}")),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// Parses the records of a wrapped stream.
+pub trait OuterFraming {
+    /// Parses the record at the start of `bytes`.
+    ///
+    /// Returns `Ok(None)` if `bytes` ends before the record does,
+    /// and an error if the record is invalid, e.g. if its checksum does not match.
+    fn parse_record<'a>(&self, bytes: &'a [u8]) -> Result<Option<WrapperRecord<'a>>, RecordError>;
+}
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An example record format, all fields are little-endian.
+///
+/// Useful for testing and as a template, the format of a real readout system is supported by implementing [OuterFraming].
+///
+/// | Offset      | Size | Field                                                         |
+/// |-------------|------|---------------------------------------------------------------|
+/// | 0           | 2    | Magic, `0x5AA5` by default                                    |
+/// | 2           | 2    | Payload length `N`                                            |
+/// | 4           | 4    | Trigger ID                                                    |
+/// | 8           | 8    | Timestamp                                                     |
+/// | 16          | N    | Payload, raw MOSS data                                        |
+/// | 16 + N      | 2    | Checksum, wrapping 16-bit sum of the bytes from offset 2 to 16 + N |
+pub struct ReadoutWrapper {
+    magic: u16,
+    verify_checksum: bool,
+}
+
+impl ReadoutWrapper {
+    /// The magic number that starts each record by default.
+    pub const DEFAULT_MAGIC: u16 = 0x5AA5;
+    /// Number of bytes before the payload.
+    pub const HEADER_LEN: usize = 16;
+    /// Number of bytes after the payload.
+    pub const CHECKSUM_LEN: usize = 2;
+
+    /// Create a new record format with the given magic number, optionally skipping checksum verification.
+    pub fn new(magic: u16, verify_checksum: bool) -> Self {
+        Self {
+            magic,
+            verify_checksum,
+        }
+    }
+
+    /// The magic number that starts each record.
+    pub fn magic(&self) -> u16 {
+        self.magic
+    }
+
+    /// Whether the checksum of each record is verified.
+    pub fn verify_checksum(&self) -> bool {
+        self.verify_checksum
+    }
+
+    /// Calculates the checksum of the record bytes following the magic number and preceding the checksum.
+    pub fn checksum(bytes: &[u8]) -> u16 {
+        bytes
+            .iter()
+            .fold(0u16, |sum, &b| sum.wrapping_add(u16::from(b)))
+    }
+
+    /// Wraps `payload` in a record with the given trigger ID and timestamp.
+    pub fn wrap(&self, trigger_id: u32, timestamp: u64, payload: &[u8]) -> PyResult<Vec<u8>> {
+        let payload_len = u16::try_from(payload.len()).map_err(|_| {
+            PyValueError::new_err(format!(
+                "Payload of {} bytes does not fit in a record",
+                payload.len()
+            ))
+        })?;
+        let mut record = Vec::with_capacity(Self::HEADER_LEN + payload.len() + Self::CHECKSUM_LEN);
+        record.extend_from_slice(&self.magic.to_le_bytes());
+        record.extend_from_slice(&payload_len.to_le_bytes());
+        record.extend_from_slice(&trigger_id.to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.extend_from_slice(payload);
+        let checksum = Self::checksum(&record[2..]);
+        record.extend_from_slice(&checksum.to_le_bytes());
+        Ok(record)
+    }
+}
+
+impl Default for ReadoutWrapper {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAGIC, true)
+    }
+}
+
+impl OuterFraming for ReadoutWrapper {
+    fn parse_record<'a>(&self, bytes: &'a [u8]) -> Result<Option<WrapperRecord<'a>>, RecordError> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Ok(None);
+        }
+        let magic = u16::from_le_bytes([bytes[0], bytes[1]]);
+        if magic != self.magic {
+            return Err(RecordError::InvalidMagic {
+                got: magic,
+                expected: self.magic,
+            });
+        }
+        let payload_len = usize::from(u16::from_le_bytes([bytes[2], bytes[3]]));
+        let len = Self::HEADER_LEN + payload_len + Self::CHECKSUM_LEN;
+        if bytes.len() < len {
+            return Ok(None);
+        }
+        let trigger_id = u32::from_le_bytes(bytes[4..8].try_into().expect("4 bytes"));
+        if self.verify_checksum {
+            let checksum = u16::from_le_bytes([bytes[len - 2], bytes[len - 1]]);
+            let expected = Self::checksum(&bytes[2..len - Self::CHECKSUM_LEN]);
+            if checksum != expected {
+                return Err(RecordError::ChecksumMismatch {
+                    trigger_id,
+                    got: checksum,
+                    expected,
+                });
+            }
+        }
+        Ok(Some(WrapperRecord {
+            trigger_id,
+            timestamp: u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes")),
+            payload_offset: Self::HEADER_LEN,
+            payload: &bytes[Self::HEADER_LEN..Self::HEADER_LEN + payload_len],
+            len,
+        }))
+    }
+}
+
+#[pymethods]
+impl ReadoutWrapper {
+    #[new]
+    #[pyo3(signature = (magic = ReadoutWrapper::DEFAULT_MAGIC, verify_checksum = true))]
+    fn py_new(magic: u16, verify_checksum: bool) -> Self {
+        Self::new(magic, verify_checksum)
+    }
+
+    #[getter(magic)]
+    fn py_magic(&self) -> u16 {
+        self.magic
+    }
+
+    #[getter(verify_checksum)]
+    fn py_verify_checksum(&self) -> bool {
+        self.verify_checksum
+    }
+
+    /// Wraps `payload` in a record, useful for generating test data.
+    #[pyo3(name = "wrap")]
+    fn py_wrap<'py>(
+        &self,
+        py: Python<'py>,
+        trigger_id: u32,
+        timestamp: u64,
+        payload: &[u8],
+    ) -> PyResult<&'py PyBytes> {
+        Ok(PyBytes::new(
+            py,
+            &self.wrap(trigger_id, timestamp, payload)?,
+        ))
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for ReadoutWrapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "magic: 0x{magic:04X}, verify_checksum: {verify}",
+                magic = self.magic,
+                verify = self.verify_checksum
+            ),
+        )
+    }
+}
+
+/// Decodes the MOSS events in all complete records at the start of `bytes`.
+///
+/// Each [MossPacket] gets [PacketMetadata](crate::PacketMetadata) with the trigger ID and timestamp of its record.
+/// `stream_offset` is the offset of `bytes` in the decoded stream and `first_sequence` is the sequence number given to the first packet.
+///
+/// Returns the packets and the number of bytes in the complete records.
+pub fn decode_wrapped_records(
+    bytes: &[u8],
+    wrapper: &impl OuterFraming,
+    framing: &FramingConfig,
+    stream_offset: usize,
+    first_sequence: usize,
+) -> PyResult<(Vec<MossPacket>, usize)> {
+    let options = DecodeOptions {
+        metadata: true,
+        ..Default::default()
+    };
+    let mut moss_packets = Vec::new();
+    let mut record_start = 0;
+    let mut record_cnt = 0;
+    while let Some(record) = wrapper.parse_record(&bytes[record_start..]).map_err(|e| {
+        PyAssertionError::new_err(format!(
+            "Failed parsing record #{record_num} at byte {offset}: {e}",
+            record_num = record_cnt + 1,
+            offset = stream_offset + record_start
+        ))
+    })? {
+        let payload_offset = stream_offset + record_start + record.payload_offset;
+        let sequence = first_sequence + moss_packets.len();
+        let mut packets = Vec::new();
+        let result =
+            decode_options::decode_bytes_into(record.payload, &options, framing, &mut packets)
+                .map_err(|e| {
+                    PyAssertionError::new_err(format!(
+                        "Failed decoding record #{record_num} with trigger ID {trigger_id}: {e}",
+                        record_num = record_cnt + 1,
+                        trigger_id = record.trigger_id
+                    ))
+                })?;
+        if !result.remainder.is_empty() {
+            return Err(PyAssertionError::new_err(format!(
+                "Partial MOSS event at the end of record #{record_num} with trigger ID {trigger_id}",
+                record_num = record_cnt + 1,
+                trigger_id = record.trigger_id
+            )));
+        }
+        moss_packets.extend(packets.into_iter().map(|mut p| {
+            if let Some(metadata) = p.metadata.as_mut() {
+                metadata.header_offset += payload_offset;
+                metadata.trailer_offset += payload_offset;
+                metadata.sequence += sequence;
+                metadata.trigger_id = Some(record.trigger_id);
+                metadata.timestamp = Some(record.timestamp);
+            }
+            p
+        }));
+        record_start += record.len;
+        record_cnt += 1;
+    }
+    Ok((moss_packets, record_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+
+    #[test]
+    fn test_wrap_and_parse_record() {
+        let wrapper = ReadoutWrapper::default();
+        let record = wrapper.wrap(7, 123_456, &fake_event_simple()).unwrap();
+
+        assert!(wrapper
+            .parse_record(&record[..record.len() - 1])
+            .unwrap()
+            .is_none());
+        let parsed = wrapper.parse_record(&record).unwrap().unwrap();
+        assert_eq!(parsed.trigger_id, 7);
+        assert_eq!(parsed.timestamp, 123_456);
+        assert_eq!(parsed.payload, fake_event_simple().as_slice());
+        assert_eq!(parsed.len, record.len());
+    }
+
+    #[test]
+    fn test_invalid_magic() {
+        let record = ReadoutWrapper::default().wrap(7, 0, &[]).unwrap();
+
+        assert_eq!(
+            ReadoutWrapper::new(0x1234, true).parse_record(&record),
+            Err(RecordError::InvalidMagic {
+                got: ReadoutWrapper::DEFAULT_MAGIC,
+                expected: 0x1234
+            })
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let wrapper = ReadoutWrapper::default();
+        let mut record = wrapper.wrap(7, 123_456, &fake_event_simple()).unwrap();
+        record[20] ^= 0x01;
+
+        let err = wrapper.parse_record(&record).unwrap_err();
+        assert!(matches!(
+            err,
+            RecordError::ChecksumMismatch { trigger_id: 7, .. }
+        ));
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert!(ReadoutWrapper::new(ReadoutWrapper::DEFAULT_MAGIC, false)
+            .parse_record(&record)
+            .is_ok());
+    }
+}
@@ -1,21 +1,102 @@
 use pyo3::{exceptions::PyAssertionError, PyResult};
 
 use crate::moss_protocol::MossWord;
+use crate::FramingConfig;
+
+/// Number of bytes checked at a time when scanning for a Unit Frame Header.
+const SCAN_BLOCK_SIZE: usize = 64;
+
+/// Get the index of the first Unit Frame Trailer in the given bytes.
+#[inline]
+pub(crate) fn find_trailer(bytes: &[u8]) -> Option<usize> {
+    memchr::memchr(MossWord::UNIT_FRAME_TRAILER, bytes)
+}
+
+/// Get the index of the first Unit Frame Header in the given bytes.
+///
+/// The bytes are checked a block at a time without branching on each byte, which lets the compiler vectorise the range check.
+/// Only the block containing the header is searched byte by byte.
+#[inline]
+pub(crate) fn find_header(bytes: &[u8]) -> Option<usize> {
+    find_first(bytes, |b| {
+        // Same as `UNIT_FRAME_HEADER_RANGE.contains(&b)` but without a branch
+        b.wrapping_sub(*MossWord::UNIT_FRAME_HEADER_RANGE.start())
+            <= MossWord::UNIT_FRAME_HEADER_RANGE.end() - MossWord::UNIT_FRAME_HEADER_RANGE.start()
+    })
+}
+
+/// Get the index of the first byte that is not a filler byte of the given framing.
+///
+/// Scans a block at a time like [find_header]. Filler usually is a run of the same byte,
+/// so the run at the start is skipped with a plain comparison, which vectorises better than the filler lookup.
+#[inline]
+pub(crate) fn find_non_filler(bytes: &[u8], framing: &FramingConfig) -> Option<usize> {
+    let &first = bytes.first()?;
+    if !framing.is_filler(first) {
+        return Some(0);
+    }
+    let run_len = find_first(bytes, |b| b != first).unwrap_or(bytes.len());
+    find_first(&bytes[run_len..], |b| !framing.is_filler(b)).map(|idx| run_len + idx)
+}
+
+/// Get the index of the first byte matching `pred`, checking [SCAN_BLOCK_SIZE] bytes at a time.
+#[inline(always)]
+fn find_first(bytes: &[u8], pred: impl Fn(u8) -> bool) -> Option<usize> {
+    let mut blocks = bytes.chunks_exact(SCAN_BLOCK_SIZE);
+    for (block_idx, block) in blocks.by_ref().enumerate() {
+        if block.iter().fold(false, |found, &b| found | pred(b)) {
+            let idx_in_block = block.iter().position(|&b| pred(b)).unwrap_or_default();
+            return Some(block_idx * SCAN_BLOCK_SIZE + idx_in_block);
+        }
+    }
+    let remainder_start = bytes.len() - blocks.remainder().len();
+    blocks
+        .remainder()
+        .iter()
+        .position(|&b| pred(b))
+        .map(|idx| remainder_start + idx)
+}
+
+/// Get the index to resume decoding at after a protocol error at `err_idx` in an event starting at index 0.
+///
+/// That is the error byte itself if it is an unexpected Unit Frame Header, otherwise the next header after it.
+/// Returns the length of `bytes` if there is no header to resume at.
+#[inline]
+pub(crate) fn find_resume_idx(bytes: &[u8], err_idx: usize) -> usize {
+    if err_idx > 0
+        && bytes
+            .get(err_idx)
+            .is_some_and(|b| MossWord::UNIT_FRAME_HEADER_RANGE.contains(b))
+    {
+        err_idx
+    } else {
+        let resume_idx = bytes.len().min(err_idx + 1);
+        find_header(&bytes[resume_idx..]).map_or(bytes.len(), |idx| resume_idx + idx)
+    }
+}
 
 /// Get trailer N's byte index in the given bytes.
+///
+/// If the events are padded the index of the last padding byte following trailer N is returned,
+/// an event is incomplete until all of its padding is in `bytes`.
 #[inline]
-pub(super) fn find_trailer_n_idx(bytes: &[u8], n: usize) -> PyResult<usize> {
+pub(crate) fn find_trailer_n_idx(
+    bytes: &[u8],
+    n: usize,
+    framing: &FramingConfig,
+) -> PyResult<usize> {
     let mut last_trailer_idx = 0;
     for i in 0..n {
-        if let Some(header_idx) = bytes[last_trailer_idx..]
-            .iter()
-            .position(|b| MossWord::UNIT_FRAME_HEADER_RANGE.contains(b))
-        {
-            if let Some(trailer_idx) = &bytes[last_trailer_idx + header_idx..]
-                .iter()
-                .position(|b| *b == MossWord::UNIT_FRAME_TRAILER)
-            {
-                last_trailer_idx += header_idx + trailer_idx + 1;
+        if let Some(header_idx) = find_header(&bytes[last_trailer_idx..]) {
+            if let Some(trailer_idx) = find_trailer(&bytes[last_trailer_idx + header_idx..]) {
+                let padding = framing.padding_after(trailer_idx + 1);
+                last_trailer_idx += header_idx + trailer_idx + padding + 1;
+                if last_trailer_idx > bytes.len() {
+                    return Err(PyAssertionError::new_err(format!(
+                        "Reached end of buffer before the end of the padding of packet {packet_cnt}",
+                        packet_cnt = i + 1
+                    )));
+                }
             } else {
                 return Err(PyAssertionError::new_err(format!(
                     "No Unit Frame Trailer found for packet {packet_cnt}",
@@ -36,12 +117,57 @@ pub(super) fn find_trailer_n_idx(bytes: &[u8], n: usize) -> PyResult<usize> {
 mod tests {
     use crate::moss_protocol::MossWord;
 
+    #[test]
+    fn test_find_header() {
+        // Place a header at every position around the block boundaries
+        for header_idx in 0..3 * super::SCAN_BLOCK_SIZE {
+            let mut bytes = vec![MossWord::DELIMITER; 3 * super::SCAN_BLOCK_SIZE + 5];
+            bytes[header_idx] = 0xD5;
+            bytes[header_idx + 1] = 0xD1; // A second header that should not be found first
+            assert_eq!(super::find_header(&bytes), Some(header_idx));
+        }
+        assert_eq!(super::find_header(&[MossWord::DELIMITER; 200]), None);
+        assert_eq!(super::find_header(&[0xD0, 0xDB, 0xE0, 0xFF]), None);
+        assert_eq!(super::find_header(&[0xD0, 0xDA]), Some(1));
+        assert_eq!(super::find_header(&[]), None);
+    }
+
+    #[test]
+    fn test_find_non_filler() {
+        let framing = crate::FramingConfig::default();
+        for idx in 0..3 * super::SCAN_BLOCK_SIZE {
+            let mut bytes = vec![MossWord::DELIMITER; 3 * super::SCAN_BLOCK_SIZE + 5];
+            bytes[idx] = 0x01;
+            assert_eq!(super::find_non_filler(&bytes, &framing), Some(idx));
+        }
+        assert_eq!(
+            super::find_non_filler(&[MossWord::DELIMITER; 200], &framing),
+            None
+        );
+        assert_eq!(super::find_non_filler(&[], &framing), None);
+        // Mixed filler bytes after the first run
+        let framing = crate::FramingConfig::try_new([0x00, 0xFA], 1, false).unwrap();
+        assert_eq!(
+            super::find_non_filler(&[0xFA, 0xFA, 0x00, 0xFA, 0xD1], &framing),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_find_trailer() {
+        let packets = crate::moss_protocol::test_util::fake_event_simple();
+        assert_eq!(super::find_trailer(&packets), Some(18));
+        assert_eq!(super::find_trailer(&packets[..18]), None);
+    }
+
     #[test]
     fn test_find_trailer_n_idx_1() {
         let trailer_n = 1;
         let packets = crate::moss_protocol::test_util::fake_multiple_events();
 
-        let trailer_idx = super::find_trailer_n_idx(&packets, trailer_n).unwrap();
+        let trailer_idx =
+            super::find_trailer_n_idx(&packets, trailer_n, &crate::FramingConfig::default())
+                .unwrap();
 
         println!("trailer {trailer_n}: {}", trailer_idx);
         assert_eq!(
@@ -57,7 +183,9 @@ mod tests {
         let trailer_n = 2;
         let packets = crate::moss_protocol::test_util::fake_multiple_events();
 
-        let trailer_idx = super::find_trailer_n_idx(&packets, trailer_n).unwrap();
+        let trailer_idx =
+            super::find_trailer_n_idx(&packets, trailer_n, &crate::FramingConfig::default())
+                .unwrap();
 
         println!("trailer {trailer_n}: {}", trailer_idx);
         assert_eq!(
@@ -73,7 +201,9 @@ mod tests {
         let trailer_n = 3;
         let packets = crate::moss_protocol::test_util::fake_multiple_events();
 
-        let trailer_idx = super::find_trailer_n_idx(&packets, trailer_n).unwrap();
+        let trailer_idx =
+            super::find_trailer_n_idx(&packets, trailer_n, &crate::FramingConfig::default())
+                .unwrap();
 
         println!("trailer {trailer_n}: {}", trailer_idx);
         assert_eq!(
@@ -88,7 +218,9 @@ mod tests {
         let trailer_n = 4;
         let packets = crate::moss_protocol::test_util::fake_multiple_events();
 
-        let trailer_idx = super::find_trailer_n_idx(&packets, trailer_n).unwrap();
+        let trailer_idx =
+            super::find_trailer_n_idx(&packets, trailer_n, &crate::FramingConfig::default())
+                .unwrap();
 
         println!("trailer {trailer_n}: {}", trailer_idx);
         assert_eq!(
@@ -106,7 +238,9 @@ mod tests {
         let trailer_n = 5;
         let packets = crate::moss_protocol::test_util::fake_multiple_events();
 
-        let trailer_idx = super::find_trailer_n_idx(&packets, trailer_n).unwrap();
+        let trailer_idx =
+            super::find_trailer_n_idx(&packets, trailer_n, &crate::FramingConfig::default())
+                .unwrap();
 
         println!("trailer {trailer_n}: {}", trailer_idx);
         assert_eq!(
@@ -0,0 +1,185 @@
+//! Reading raw data from Python file-like objects.
+//!
+//! Lets the chunked decoding loops used for files also pull data from an open Python stream,
+//! e.g. an `io.BufferedReader`, a `gzip.GzipFile`, a socket file or a tar member.
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::intern;
+use pyo3::prelude::*;
+use pyo3::types::PyByteArray;
+use std::io::{self, Read};
+
+use crate::buffer_input::bytes_from_buffer;
+
+/// Adapts a Python object with a `readinto` or `read` method to [Read].
+///
+/// Exceptions raised by the Python object are wrapped in the returned [io::Error], use [into_py_err] to get them back.
+pub(crate) struct PyReader {
+    stream: PyObject,
+    has_readinto: bool,
+    /// Reused for `readinto` calls.
+    chunk: Option<Py<PyByteArray>>,
+}
+
+impl PyReader {
+    /// Wraps `stream`, fails with a `TypeError` if it has neither a `readinto` nor a `read` method.
+    pub(crate) fn new(stream: &PyAny) -> PyResult<Self> {
+        let py = stream.py();
+        let has_readinto = stream.hasattr(intern!(py, "readinto"))?;
+        if !has_readinto && !stream.hasattr(intern!(py, "read"))? {
+            return Err(PyTypeError::new_err(format!(
+                "Expected a file-like object with a `readinto` or `read` method, got {}",
+                stream.get_type().name()?
+            )));
+        }
+        Ok(Self {
+            stream: stream.into(),
+            has_readinto,
+            chunk: None,
+        })
+    }
+
+    fn read_with_gil(&mut self, py: Python<'_>, buf: &mut [u8]) -> PyResult<usize> {
+        let stream = self.stream.as_ref(py);
+        if self.has_readinto {
+            let chunk = match &self.chunk {
+                Some(chunk) if chunk.as_ref(py).len() == buf.len() => chunk.as_ref(py),
+                _ => self
+                    .chunk
+                    .insert(PyByteArray::new(py, &vec![0; buf.len()]).into())
+                    .as_ref(py),
+            };
+            // `None` is returned by non-blocking streams with no data available
+            let Some(bytes_read) = stream
+                .call_method1(intern!(py, "readinto"), (chunk,))?
+                .extract::<Option<usize>>()?
+            else {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+            };
+            if bytes_read > buf.len() {
+                return Err(PyValueError::new_err(format!(
+                    "`readinto` returned {bytes_read} for a buffer of {} bytes",
+                    buf.len()
+                )));
+            }
+            // SAFETY: No Python code runs while the contents are borrowed
+            buf[..bytes_read].copy_from_slice(unsafe { &chunk.as_bytes()[..bytes_read] });
+            Ok(bytes_read)
+        } else {
+            let data = stream.call_method1(intern!(py, "read"), (buf.len(),))?;
+            if data.is_none() {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+            }
+            let bytes = bytes_from_buffer(data)?;
+            if bytes.len() > buf.len() {
+                return Err(PyValueError::new_err(format!(
+                    "`read({})` returned {} bytes",
+                    buf.len(),
+                    bytes.len()
+                )));
+            }
+            buf[..bytes.len()].copy_from_slice(bytes);
+            Ok(bytes.len())
+        }
+    }
+}
+
+impl Read for PyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            // The caller usually holds the GIL already, so `with_gil` doesn't create a pool and the objects
+            // returned by each read would only be released when the whole stream is decoded.
+            // SAFETY: No references owned by the pool escape the closure, errors are owned `PyErr`s.
+            let pool = unsafe { py.new_pool() };
+            self.read_with_gil(pool.python(), buf)
+        })
+        .map_err(io::Error::other)
+    }
+}
+
+/// Converts an error from reading back to the Python exception that caused it, or an `OSError`.
+pub(crate) fn into_py_err(err: io::Error) -> PyErr {
+    let kind = err.kind();
+    match err.into_inner().map(|inner| inner.downcast::<PyErr>()) {
+        Some(Ok(py_err)) => *py_err,
+        Some(Err(inner)) => io::Error::new(kind, inner).into(),
+        None => io::Error::from(kind).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_from_python_stream() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let io = py.import("io").unwrap();
+            for stream in [
+                io.call_method1("BytesIO", (&b"\xD1\xC0\xE0"[..],)).unwrap(),
+                io.call_method1("StringIO", ("no bytes",)).unwrap(),
+            ] {
+                let mut reader = PyReader::new(stream).unwrap();
+                let mut buf = vec![0; 2];
+                if stream.hasattr("readinto").unwrap() {
+                    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+                    assert_eq!(buf, [0xD1, 0xC0]);
+                    assert_eq!(reader.read(&mut buf).unwrap(), 1);
+                    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+                } else {
+                    let err = into_py_err(reader.read(&mut buf).unwrap_err());
+                    assert!(err.is_instance_of::<PyTypeError>(py));
+                }
+            }
+
+            assert!(PyReader::new(py.None().as_ref(py)).is_err());
+        });
+    }
+
+    #[test]
+    fn test_read_releases_chunks() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = pyo3::types::PyDict::new(py);
+            py.run(
+                r#"
+import weakref
+
+class Chunk(bytearray):
+    pass
+
+class Stream:
+    def __init__(self):
+        self.chunks = []
+
+    def read(self, size):
+        if len(self.chunks) == 3:
+            return b""
+        chunk = Chunk(b"\xfa")
+        self.chunks.append(weakref.ref(chunk))
+        return chunk
+
+stream = Stream()
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            let stream = globals.get_item("stream").unwrap();
+
+            let mut reader = PyReader::new(stream).unwrap();
+            let mut buf = vec![0; 4];
+            while reader.read(&mut buf).unwrap() > 0 {}
+
+            // Each chunk is released after the read that returned it, not when the GIL is released
+            let released = py
+                .eval(
+                    "all(chunk() is None for chunk in stream.chunks)",
+                    Some(globals),
+                    None,
+                )
+                .unwrap();
+            assert!(released.is_true().unwrap());
+        });
+    }
+}
@@ -0,0 +1,407 @@
+//! Filtering and splitting of raw MOSS data files while keeping the raw format, e.g. for replaying a part of a run.
+//!
+//! The file is streamed in chunks and split into events at the Unit Frame Headers and Trailers.
+//! Each event is decoded to evaluate the [EventFilter] and the raw bytes of the selected events are written verbatim,
+//! including any padding following the trailer but not the filler bytes between events.
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::parse_error::ParseErrorKind;
+use crate::parse_util::{find_header, find_resume_idx};
+use crate::{FramingConfig, MossPacket};
+
+/// A single event in a raw data stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawEvent<'a> {
+    /// Index of the first byte of the event in the split bytes.
+    pub offset: usize,
+    /// The raw bytes of the event, from the Unit Frame Header to the trailer and its padding.
+    ///
+    /// For an invalid event, up to the next Unit Frame Header.
+    pub bytes: &'a [u8],
+    /// The decoded event, or the error message if the event is invalid.
+    pub decoded: Result<MossPacket, String>,
+}
+
+impl RawEvent<'_> {
+    /// The unit ID from the Unit Frame Header.
+    pub fn unit_id(&self) -> u8 {
+        self.bytes[0] & 0xF
+    }
+}
+
+/// Splits `bytes` into [RawEvent]s and returns them with the number of bytes consumed.
+///
+/// The bytes of a partial event at the end are not consumed unless `at_eof` is true,
+/// in which case the partial event is returned as an invalid event.
+pub fn split_raw_events<'a>(
+    bytes: &'a [u8],
+    at_eof: bool,
+    framing: &FramingConfig,
+) -> (Vec<RawEvent<'a>>, usize) {
+    let mut events = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let buf = &bytes[pos..];
+        match crate::rust_only::extract_packet_with_header_idx(buf, framing) {
+            Ok((packet, header_idx, _trailer_idx, event_end)) => {
+                events.push(RawEvent {
+                    offset: pos + header_idx,
+                    bytes: &buf[header_idx..=event_end],
+                    decoded: Ok(packet),
+                });
+                pos += event_end + 1;
+            }
+            Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => pos = bytes.len(),
+            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer && !at_eof => break,
+            Err(e) => {
+                let resume_idx = if e.kind() == ParseErrorKind::EndOfBufferNoTrailer {
+                    buf.len()
+                } else {
+                    find_resume_idx(buf, e.err_index())
+                };
+                // Invalid bytes before the first header are not part of an event
+                if let Some(header_idx) = find_header(buf).filter(|&idx| idx < resume_idx) {
+                    events.push(RawEvent {
+                        offset: pos + header_idx,
+                        bytes: &buf[header_idx..resume_idx],
+                        decoded: Err(e.to_string()),
+                    });
+                }
+                pos += resume_idx;
+            }
+        }
+    }
+    (events, pos)
+}
+
+#[pyclass]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+/// Selects events by their properties, an event is selected if it matches all the criteria that are set.
+pub struct EventFilter {
+    /// Range of event indexes to select, counting all events including invalid events from 0.
+    pub event_range: Option<Range<usize>>,
+    /// Unit IDs to select.
+    pub unit_ids: Option<Vec<u8>>,
+    /// Minimum number of hits, invalid events are never selected if set.
+    pub min_hits: Option<usize>,
+    /// Maximum number of hits, invalid events are never selected if set.
+    pub max_hits: Option<usize>,
+    /// Select only invalid events if true, only valid events if false.
+    pub has_error: Option<bool>,
+}
+
+impl EventFilter {
+    /// Returns true if the event with index `index` is selected.
+    pub fn matches(&self, index: usize, event: &RawEvent) -> bool {
+        let hits = event.decoded.as_ref().map(|packet| packet.hits.len());
+        self.event_range
+            .as_ref()
+            .is_none_or(|range| range.contains(&index))
+            && self
+                .unit_ids
+                .as_ref()
+                .is_none_or(|units| units.contains(&event.unit_id()))
+            && self
+                .min_hits
+                .is_none_or(|min| hits.as_ref().is_ok_and(|&hits| hits >= min))
+            && self
+                .max_hits
+                .is_none_or(|max| hits.as_ref().is_ok_and(|&hits| hits <= max))
+            && self
+                .has_error
+                .is_none_or(|has_error| event.decoded.is_err() == has_error)
+    }
+
+    /// Returns true if no event at `index` or later can be selected.
+    fn is_past_end(&self, index: usize) -> bool {
+        self.event_range
+            .as_ref()
+            .is_some_and(|range| index >= range.end)
+    }
+}
+
+#[pymethods]
+impl EventFilter {
+    #[new]
+    fn py_new(
+        first_event: Option<usize>,
+        event_count: Option<usize>,
+        unit_ids: Option<Vec<u8>>,
+        min_hits: Option<usize>,
+        max_hits: Option<usize>,
+        has_error: Option<bool>,
+    ) -> Self {
+        Self {
+            event_range: event_range(first_event, event_count),
+            unit_ids,
+            min_hits,
+            max_hits,
+            has_error,
+        }
+    }
+
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}(events: {:?} units: {:?} min hits: {:?} max hits: {:?} has error: {:?})",
+            class_name, slf.event_range, slf.unit_ids, slf.min_hits, slf.max_hits, slf.has_error
+        ))
+    }
+}
+
+/// Returns the range of `event_count` events starting at `first_event`, or `None` if neither is given.
+///
+/// Without `first_event` the range starts at the first event, without `event_count` it includes all following events.
+pub fn event_range(first_event: Option<usize>, event_count: Option<usize>) -> Option<Range<usize>> {
+    (first_event.is_some() || event_count.is_some()).then(|| {
+        let first = first_event.unwrap_or(0);
+        first..event_count.map_or(usize::MAX, |count| first.saturating_add(count))
+    })
+}
+
+/// When to start a new output file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    /// Write all events to a single file.
+    #[default]
+    Never,
+    /// Start a new file after the given number of events.
+    Events(usize),
+    /// Start a new file before exceeding the given number of bytes, unless the file is empty.
+    Bytes(usize),
+}
+
+/// Writes raw events to one or more files.
+///
+/// When splitting, the files are named after the output path with a file number appended to the file stem,
+/// e.g. `run_0000.raw`, `run_0001.raw`...
+#[derive(Debug)]
+pub struct RawEventWriter {
+    path: PathBuf,
+    split: SplitBy,
+    current: Option<BufWriter<File>>,
+    events_in_file: usize,
+    bytes_in_file: usize,
+    files: Vec<PathBuf>,
+}
+
+impl RawEventWriter {
+    /// Create a writer to `path`, the first output file is created immediately.
+    pub fn new(path: impl Into<PathBuf>, split: SplitBy) -> std::io::Result<Self> {
+        let mut writer = Self {
+            path: path.into(),
+            split,
+            current: None,
+            events_in_file: 0,
+            bytes_in_file: 0,
+            files: Vec::new(),
+        };
+        writer.start_file()?;
+        Ok(writer)
+    }
+
+    /// Write the raw bytes of an event, starting a new file first if the current one is full.
+    pub fn write_event(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        let file_full = match self.split {
+            SplitBy::Never => false,
+            SplitBy::Events(max_events) => self.events_in_file >= max_events,
+            SplitBy::Bytes(max_bytes) => {
+                self.events_in_file > 0 && self.bytes_in_file + bytes.len() > max_bytes
+            }
+        };
+        if file_full {
+            self.start_file()?;
+        }
+        self.current
+            .as_mut()
+            .expect("a file is always open")
+            .write_all(bytes)?;
+        self.events_in_file += 1;
+        self.bytes_in_file += bytes.len();
+        Ok(())
+    }
+
+    /// Flush the last file and return the paths of all files written.
+    pub fn finish(mut self) -> std::io::Result<Vec<PathBuf>> {
+        if let Some(mut file) = self.current.take() {
+            file.flush()?;
+        }
+        Ok(self.files)
+    }
+
+    fn start_file(&mut self) -> std::io::Result<()> {
+        if let Some(mut file) = self.current.take() {
+            file.flush()?;
+        }
+        let path = match self.split {
+            SplitBy::Never => self.path.clone(),
+            SplitBy::Events(_) | SplitBy::Bytes(_) => {
+                let mut file_name = self.path.file_stem().unwrap_or_default().to_owned();
+                file_name.push(format!("_{:04}", self.files.len()));
+                if let Some(extension) = self.path.extension() {
+                    file_name.push(".");
+                    file_name.push(extension);
+                }
+                self.path.with_file_name(file_name)
+            }
+        };
+        self.current = Some(BufWriter::new(File::create(&path)?));
+        self.files.push(path);
+        self.events_in_file = 0;
+        self.bytes_in_file = 0;
+        Ok(())
+    }
+}
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Summary of a raw file filtering.
+pub struct RawFilterStats {
+    /// Number of events read from the input up to the end of the event range of the filter, including invalid events.
+    pub events_read: usize,
+    /// Number of events written to the output.
+    pub events_written: usize,
+    /// Number of bytes written to the output.
+    pub bytes_written: usize,
+    /// The output files in the order they were written.
+    pub output_files: Vec<PathBuf>,
+}
+
+#[pymethods]
+impl RawFilterStats {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}(events read: {} written: {} bytes written: {} files: {})",
+            class_name,
+            slf.events_read,
+            slf.events_written,
+            slf.bytes_written,
+            slf.output_files.len()
+        ))
+    }
+}
+
+/// Streams through the raw file at `input` and writes the raw bytes of the events selected by `filter` to `output`.
+///
+/// Reading stops early once the end of the event range of the filter is reached.
+pub fn filter_raw_file(
+    input: impl AsRef<Path>,
+    filter: &EventFilter,
+    mut output: RawEventWriter,
+    framing: &FramingConfig,
+) -> std::io::Result<RawFilterStats> {
+    let mut reader = File::open(input)?;
+    let mut stats = RawFilterStats::default();
+
+    let mut buf = vec![0; crate::READER_BUFFER_CAPACITY];
+    let mut bytes_to_split = Vec::with_capacity(crate::READER_BUFFER_CAPACITY);
+    let mut at_eof = false;
+    while !at_eof && !filter.is_past_end(stats.events_read) {
+        let bytes_read = reader.read(&mut buf)?;
+        at_eof = bytes_read == 0;
+        bytes_to_split.extend_from_slice(&buf[..bytes_read]);
+
+        let (events, consumed) = split_raw_events(&bytes_to_split, at_eof, framing);
+        for event in events {
+            if filter.is_past_end(stats.events_read) {
+                break;
+            }
+            if filter.matches(stats.events_read, &event) {
+                output.write_event(event.bytes)?;
+                stats.events_written += 1;
+                stats.bytes_written += event.bytes.len();
+            }
+            stats.events_read += 1;
+        }
+        _ = bytes_to_split.drain(..consumed);
+    }
+    stats.output_files = output.finish()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_split_raw_events() {
+        let event = fake_event_simple();
+        let mut bytes = vec![0xFA];
+        bytes.extend(&event);
+        // An event with a protocol error followed by a valid event with another unit ID
+        let mut invalid_event = event.clone();
+        invalid_event[3] = 0xFB;
+        bytes.extend(&invalid_event);
+        bytes.extend([0xD2, 0xE0, 0xFA]);
+        bytes.extend(&event[..5]);
+
+        let (events, consumed) = split_raw_events(&bytes, false, &FramingConfig::default());
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].bytes, &event[..]);
+        assert!(events[1].decoded.is_err());
+        assert_eq!(events[1].bytes, &invalid_event[..]);
+        assert_eq!(events[2].bytes, &[0xD2, 0xE0]);
+        assert_eq!(events[2].unit_id(), 2);
+        assert_eq!(events[2].offset, 1 + 2 * event.len());
+        // The filler byte before the partial event is not consumed either
+        assert_eq!(consumed, bytes.len() - 6);
+
+        let (events, consumed) =
+            split_raw_events(&bytes[consumed..], true, &FramingConfig::default());
+        assert_eq!(events.len(), 1);
+        assert!(events[0].decoded.is_err());
+        assert_eq!(events[0].bytes, &event[..5]);
+        assert_eq!(consumed, 6);
+    }
+
+    #[test]
+    fn test_event_filter() {
+        let event = fake_event_simple();
+        let bytes = [&event[..], &[0xD2, 0xE0], &event[..4]].concat();
+        let (events, _) = split_raw_events(&bytes, true, &FramingConfig::default());
+        let selected = |filter: EventFilter| {
+            events
+                .iter()
+                .enumerate()
+                .filter(|(i, e)| filter.matches(*i, e))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(selected(EventFilter::default()), vec![0, 1, 2]);
+        assert_eq!(
+            selected(EventFilter {
+                min_hits: Some(1),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            selected(EventFilter {
+                unit_ids: Some(vec![1]),
+                has_error: Some(false),
+                ..Default::default()
+            }),
+            vec![0]
+        );
+        assert_eq!(
+            selected(EventFilter {
+                event_range: Some(1..3),
+                has_error: Some(true),
+                ..Default::default()
+            }),
+            vec![2]
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Replay of recorded raw files at a controlled rate, as if the data was coming from the readout FPGA.
+//!
+//! The file is written verbatim, including the filler bytes between events, one event at a time
+//! so the rate can be limited per event or per byte. Useful for testing online monitoring and the streaming decoders locally.
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::raw_filter::split_raw_events;
+use crate::FramingConfig;
+
+/// The rate to replay at.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ReplayRate {
+    /// As fast as the output accepts the data.
+    #[default]
+    Unlimited,
+    /// Bytes per second.
+    BytesPerSec(f64),
+    /// Events per second.
+    EventsPerSec(f64),
+}
+
+/// Options for [replay].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayOptions {
+    /// The rate to replay at.
+    pub rate: ReplayRate,
+    /// Number of times to replay the file, or `None` to loop until the output is closed.
+    pub loops: Option<usize>,
+    /// Framing used to find the event boundaries.
+    pub framing: FramingConfig,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            rate: ReplayRate::Unlimited,
+            loops: Some(1),
+            framing: FramingConfig::default(),
+        }
+    }
+}
+
+/// Summary of a replay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplayStats {
+    /// Number of bytes written.
+    pub bytes: usize,
+    /// Number of events written, including invalid events.
+    pub events: usize,
+    /// Number of times the file was replayed completely.
+    pub loops: usize,
+    /// Time spent replaying.
+    pub elapsed: Duration,
+}
+
+/// Where to replay to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayTarget {
+    /// Standard output, given as `-`.
+    Stdout,
+    /// A file or FIFO.
+    File(PathBuf),
+    /// Connect to a TCP server, given as `tcp://<host>:<port>`.
+    TcpConnect(String),
+    /// Listen for a single TCP client, given as `tcp-listen://<host>:<port>`.
+    TcpListen(String),
+}
+
+impl FromStr for ReplayTarget {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if s == "-" {
+            Self::Stdout
+        } else if let Some(addr) = s.strip_prefix("tcp://") {
+            Self::TcpConnect(addr.to_string())
+        } else if let Some(addr) = s.strip_prefix("tcp-listen://") {
+            Self::TcpListen(addr.to_string())
+        } else {
+            Self::File(s.into())
+        })
+    }
+}
+
+impl ReplayTarget {
+    /// Opens the target for writing, waiting for a client to connect if listening.
+    pub fn open(&self) -> std::io::Result<Box<dyn Write>> {
+        Ok(match self {
+            Self::Stdout => Box::new(std::io::stdout().lock()),
+            // Opening a FIFO blocks until a reader opens it
+            Self::File(path) => Box::new(
+                File::options()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)?,
+            ),
+            Self::TcpConnect(addr) => Box::new(TcpStream::connect(addr)?),
+            Self::TcpListen(addr) => Box::new(TcpListener::bind(addr)?.accept()?.0),
+        })
+    }
+}
+
+/// Keeps the replay at the configured rate by sleeping when it is ahead.
+struct Pacer {
+    rate: ReplayRate,
+    start: Instant,
+}
+
+impl Pacer {
+    /// Waits until `bytes` and `events` are due to have been written, flushing `output` before sleeping.
+    fn wait(&self, bytes: usize, events: usize, output: &mut impl Write) -> std::io::Result<()> {
+        let due_secs = match self.rate {
+            ReplayRate::Unlimited => return Ok(()),
+            ReplayRate::BytesPerSec(rate) => bytes as f64 / rate,
+            ReplayRate::EventsPerSec(rate) => events as f64 / rate,
+        };
+        if let Some(ahead) = Duration::from_secs_f64(due_secs).checked_sub(self.start.elapsed()) {
+            output.flush()?;
+            std::thread::sleep(ahead);
+        }
+        Ok(())
+    }
+}
+
+/// Replays the raw file at `input` to `output` with the given options.
+///
+/// The rate is kept per event, each event is written when it is due at the configured rate.
+/// Filler bytes that do not precede an event, e.g. at the end of the file, are written when they are due at a byte rate.
+pub fn replay(
+    input: impl AsRef<Path>,
+    output: &mut impl Write,
+    options: &ReplayOptions,
+) -> std::io::Result<ReplayStats> {
+    let pacer = Pacer {
+        rate: options.rate,
+        start: Instant::now(),
+    };
+    let mut stats = ReplayStats::default();
+
+    let mut buf = vec![0; crate::READER_BUFFER_CAPACITY];
+    let mut bytes_to_split = Vec::with_capacity(crate::READER_BUFFER_CAPACITY);
+    while options.loops.is_none_or(|loops| stats.loops < loops) {
+        let mut reader = File::open(input.as_ref())?;
+        let mut at_eof = false;
+        while !at_eof {
+            let bytes_read = reader.read(&mut buf)?;
+            at_eof = bytes_read == 0;
+            bytes_to_split.extend_from_slice(&buf[..bytes_read]);
+
+            let (events, consumed) = split_raw_events(&bytes_to_split, at_eof, &options.framing);
+            // Each event is written with the filler bytes preceding it
+            let mut written = 0;
+            for event in events {
+                let event_end = event.offset + event.bytes.len();
+                pacer.wait(stats.bytes + event_end - written, stats.events + 1, output)?;
+                output.write_all(&bytes_to_split[written..event_end])?;
+                stats.bytes += event_end - written;
+                stats.events += 1;
+                written = event_end;
+            }
+            // Filler after the last event, only paced by a byte rate
+            if consumed > written {
+                pacer.wait(stats.bytes + consumed - written, stats.events, output)?;
+                output.write_all(&bytes_to_split[written..consumed])?;
+                stats.bytes += consumed - written;
+            }
+            _ = bytes_to_split.drain(..consumed);
+        }
+        stats.loops += 1;
+        // Nothing to loop over
+        if stats.bytes == 0 {
+            break;
+        }
+    }
+    output.flush()?;
+    stats.elapsed = pacer.start.elapsed();
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    fn write_test_file(name: &str, bytes: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{name}_{}.raw", std::process::id()));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_replay_loops_verbatim() {
+        let mut bytes = fake_event_simple();
+        bytes.extend([0xFA, 0xFA]);
+        bytes.extend(fake_event_simple());
+        bytes.push(0xFA);
+        let path = write_test_file("moss_decoder_test_replay_loops", &bytes);
+
+        let mut output = Vec::new();
+        let stats = replay(
+            &path,
+            &mut output,
+            &ReplayOptions {
+                loops: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(output, [&bytes[..], &bytes[..]].concat());
+        assert_eq!(stats.bytes, 2 * bytes.len());
+        assert_eq!(stats.events, 4);
+        assert_eq!(stats.loops, 2);
+    }
+
+    #[test]
+    fn test_replay_event_rate() {
+        let bytes = fake_event_simple().repeat(10);
+        let path = write_test_file("moss_decoder_test_replay_event_rate", &bytes);
+
+        let mut output = Vec::new();
+        let stats = replay(
+            &path,
+            &mut output,
+            &ReplayOptions {
+                rate: ReplayRate::EventsPerSec(500.0),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(output, bytes);
+        // The last of the 10 events is due after 20 ms
+        assert!(stats.elapsed >= Duration::from_millis(20), "{stats:?}");
+    }
+
+    #[test]
+    fn test_replay_byte_rate_paces_trailing_filler() {
+        let mut bytes = fake_event_simple();
+        bytes.extend([0xFA; 1000]);
+        let path = write_test_file("moss_decoder_test_replay_byte_rate", &bytes);
+
+        let mut output = Vec::new();
+        let stats = replay(
+            &path,
+            &mut output,
+            &ReplayOptions {
+                rate: ReplayRate::BytesPerSec(20_000.0),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(output, bytes);
+        assert_eq!(stats.events, 1);
+        // The last of the 1019 bytes is due after about 51 ms
+        assert!(stats.elapsed >= Duration::from_millis(50), "{stats:?}");
+    }
+
+    #[test]
+    fn test_parse_replay_target() {
+        assert_eq!("-".parse(), Ok(ReplayTarget::Stdout));
+        assert_eq!(
+            "tcp://localhost:5000".parse(),
+            Ok(ReplayTarget::TcpConnect("localhost:5000".to_string()))
+        );
+        assert_eq!(
+            "tcp-listen://0.0.0.0:5000".parse(),
+            Ok(ReplayTarget::TcpListen("0.0.0.0:5000".to_string()))
+        );
+        assert_eq!(
+            "/tmp/moss_fifo".parse(),
+            Ok(ReplayTarget::File("/tmp/moss_fifo".into()))
+        );
+    }
+}
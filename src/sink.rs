@@ -0,0 +1,212 @@
+//! Consumers of decoded packets for online processing.
+//!
+//! The decoding loop of [decode_options](crate::decode_options) hands each decoded [MossPacket] to a [PacketSink]
+//! instead of collecting a list, so monitoring, histogramming and writing can happen while the data is decoded.
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+use crate::decode_hits_fsm::HitSink;
+use crate::decode_options::DecodeResult;
+use crate::{HitMap, MossEventBatch, MossPacket};
+
+/// Whether decoding should continue after a packet was consumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkControl {
+    /// Continue decoding.
+    Continue,
+    /// Stop decoding, no more packets are consumed.
+    Stop,
+}
+
+/// Destination of the packets decoded by the decoding loop.
+pub trait PacketSink {
+    /// Consume a decoded packet, return [SinkControl::Stop] to stop decoding.
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl>;
+
+    /// Called for each error skipped with the `"skip"` error policy, `offset` is the stream offset of the offending byte.
+    fn error(&mut self, _message: &str, _offset: usize) -> PyResult<()> {
+        Ok(())
+    }
+
+    /// Called once after the last packet was consumed, unless decoding failed.
+    fn finish(&mut self) -> PyResult<()> {
+        Ok(())
+    }
+
+    /// The batch the hits can be decoded into directly instead of allocating a [MossPacket] for each event.
+    ///
+    /// Only used if no hits are masked and no metadata is requested.
+    fn as_batch(&mut self) -> Option<&mut MossEventBatch> {
+        None
+    }
+
+    /// Whether [PacketMetadata](crate::PacketMetadata) must be attached to the packets, even if [DecodeOptions::metadata](crate::decode_options::DecodeOptions::metadata) is not set.
+    fn needs_metadata(&self) -> bool {
+        false
+    }
+}
+
+impl PacketSink for Vec<MossPacket> {
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl> {
+        self.push(packet);
+        Ok(SinkControl::Continue)
+    }
+}
+
+impl PacketSink for MossEventBatch {
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl> {
+        self.begin_event(packet.unit_id);
+        packet.hits.into_iter().for_each(|hit| self.push_hit(hit));
+        self.end_event();
+        Ok(SinkControl::Continue)
+    }
+
+    fn as_batch(&mut self) -> Option<&mut MossEventBatch> {
+        Some(self)
+    }
+}
+
+#[pyclass(module = "moss_decoder", frozen)]
+#[derive(Debug, Clone, Copy)]
+/// Sentinel returned by a callback to stop decoding, available as `moss_decoder.STOP`.
+pub struct StopDecoding;
+
+#[pymethods]
+impl StopDecoding {
+    fn __repr__(&self) -> &'static str {
+        "STOP"
+    }
+}
+
+/// Calls a Python callable with each packet, or with lists of up to `batch_size` packets.
+pub(crate) struct CallbackSink<'py> {
+    callback: &'py PyAny,
+    batch_size: Option<usize>,
+    pending: Vec<MossPacket>,
+}
+
+impl<'py> CallbackSink<'py> {
+    /// Fails with a `TypeError` if `callback` is not callable or a `ValueError` if `batch_size` is 0.
+    pub(crate) fn new(callback: &'py PyAny, batch_size: Option<usize>) -> PyResult<Self> {
+        if !callback.is_callable() {
+            return Err(PyTypeError::new_err(format!(
+                "Expected a callable or a HitMap as sink, got {}",
+                callback.get_type().name()?
+            )));
+        }
+        if batch_size == Some(0) {
+            return Err(PyValueError::new_err("batch_size must be at least 1"));
+        }
+        Ok(Self {
+            callback,
+            batch_size,
+            pending: Vec::with_capacity(batch_size.unwrap_or_default()),
+        })
+    }
+
+    fn call(&self, arg: PyObject) -> PyResult<SinkControl> {
+        if self
+            .callback
+            .call1((arg,))?
+            .is_instance_of::<StopDecoding>()
+        {
+            Ok(SinkControl::Stop)
+        } else {
+            Ok(SinkControl::Continue)
+        }
+    }
+
+    fn call_with_pending(&mut self) -> PyResult<SinkControl> {
+        let py = self.callback.py();
+        let packets = PyList::new(
+            py,
+            std::mem::take(&mut self.pending)
+                .into_iter()
+                .map(|p| p.into_py(py)),
+        );
+        self.call(packets.into())
+    }
+}
+
+impl PacketSink for CallbackSink<'_> {
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl> {
+        let Some(batch_size) = self.batch_size else {
+            return self.call(packet.into_py(self.callback.py()));
+        };
+        self.pending.push(packet);
+        if self.pending.len() < batch_size {
+            return Ok(SinkControl::Continue);
+        }
+        self.call_with_pending()
+    }
+
+    fn finish(&mut self) -> PyResult<()> {
+        if !self.pending.is_empty() {
+            _ = self.call_with_pending()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `decode` with a Python `sink`, either a [HitMap] that is filled without calling into Python, or a callable.
+pub(crate) fn with_py_sink(
+    sink: &PyAny,
+    batch_size: Option<usize>,
+    decode: impl FnOnce(&mut dyn PacketSink) -> PyResult<DecodeResult>,
+) -> PyResult<DecodeResult> {
+    if let Ok(hit_map) = sink.downcast::<PyCell<HitMap>>() {
+        return decode(&mut *hit_map.try_borrow_mut()?);
+    }
+    decode(&mut CallbackSink::new(sink, batch_size)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use crate::{DecodeOptions, FramingConfig};
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_callback_sink_batches_and_stops() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals
+                .set_item("STOP", Py::new(py, StopDecoding).unwrap())
+                .unwrap();
+            py.run(
+                r#"
+calls = []
+def callback(packets):
+    calls.append(len(packets))
+    if len(calls) == 2:
+        return STOP
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            let callback = globals.get_item("callback").unwrap();
+            let bytes = fake_event_simple().repeat(10);
+
+            let result = with_py_sink(callback, Some(3), |sink| {
+                crate::decode_options::decode_bytes_into(
+                    &bytes,
+                    &DecodeOptions::default(),
+                    &FramingConfig::default(),
+                    sink,
+                )
+            })
+            .unwrap();
+            let calls: Vec<usize> = globals.get_item("calls").unwrap().extract().unwrap();
+            assert_eq!(calls, [3, 3]);
+            assert_eq!(result.bytes_consumed, 6 * fake_event_simple().len());
+            assert!(result.remainder.is_empty());
+
+            assert!(CallbackSink::new(py.None().as_ref(py), None).is_err());
+            assert!(CallbackSink::new(callback, Some(0)).is_err());
+        });
+    }
+}
@@ -0,0 +1,178 @@
+//! Incremental decoding of a MOSS stream that arrives in chunks.
+use pyo3::prelude::*;
+use std::fmt::{write, Display};
+
+use crate::parse_error::ParseErrorKind;
+use crate::parse_util::find_resume_idx;
+use crate::{FramingConfig, MossPacket};
+
+#[pyclass(get_all)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Statistics of a decoded stream.
+pub struct StreamStats {
+    /// Number of bytes fed to the decoder.
+    pub bytes: usize,
+    /// Number of decoded packets.
+    pub packets: usize,
+    /// Number of decoded hits.
+    pub hits: usize,
+    /// Number of protocol errors the decoder recovered from.
+    pub protocol_errors: usize,
+    /// Description of the last protocol error.
+    pub last_error: Option<String>,
+}
+
+#[pymethods]
+impl StreamStats {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        Ok(format!("{}({})", class_name, *slf.borrow()))
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for StreamStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write(
+            f,
+            format_args!(
+                "Bytes: {bytes} Packets: {packets} Hits: {hits} Protocol errors: {errors}",
+                bytes = self.bytes,
+                packets = self.packets,
+                hits = self.hits,
+                errors = self.protocol_errors
+            ),
+        )
+    }
+}
+
+/// The state of a stream between chunks: the bytes of a partial event and the statistics so far.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct StreamState {
+    remainder: Vec<u8>,
+    stats: StreamStats,
+}
+
+impl StreamState {
+    /// Decodes all complete events in the remainder of the previous chunks followed by `bytes`.
+    ///
+    /// The bytes of a partial event at the end are kept for the next chunk.
+    /// On a protocol error the error is recorded in the statistics and decoding resumes at the next Unit Frame Header.
+    pub(crate) fn feed(&mut self, bytes: &[u8], framing: &FramingConfig) -> Vec<MossPacket> {
+        let mut moss_packets = Vec::new();
+        self.feed_into(bytes, framing, &mut moss_packets);
+        moss_packets
+    }
+
+    /// Same as [Self::feed] but appends the decoded packets to `moss_packets`, to reuse its allocation.
+    pub(crate) fn feed_into(
+        &mut self,
+        bytes: &[u8],
+        framing: &FramingConfig,
+        moss_packets: &mut Vec<MossPacket>,
+    ) {
+        self.stats.bytes += bytes.len();
+        if self.remainder.is_empty() {
+            // Nothing to prepend, only the bytes of a partial event at the end are copied
+            let pos = decode_chunk(bytes, framing, &mut self.stats, moss_packets);
+            self.remainder.extend_from_slice(&bytes[pos..]);
+        } else {
+            self.remainder.extend_from_slice(bytes);
+            let pos = decode_chunk(&self.remainder, framing, &mut self.stats, moss_packets);
+            _ = self.remainder.drain(..pos);
+        }
+    }
+
+    /// Discards the partial event and resets the statistics, keeping the allocated buffer.
+    pub(crate) fn reset(&mut self) {
+        self.remainder.clear();
+        self.stats = StreamStats::default();
+    }
+
+    /// The bytes of the partial event at the end of the stream so far.
+    pub(crate) fn remainder(&self) -> &[u8] {
+        &self.remainder
+    }
+
+    pub(crate) fn stats(&self) -> &StreamStats {
+        &self.stats
+    }
+}
+
+/// Decodes the complete events in `buf`, returns the number of bytes consumed.
+fn decode_chunk(
+    buf: &[u8],
+    framing: &FramingConfig,
+    stats: &mut StreamStats,
+    moss_packets: &mut Vec<MossPacket>,
+) -> usize {
+    let mut pos = 0;
+    while pos < buf.len() {
+        match crate::rust_only::extract_packet_from_buf(&buf[pos..], None, framing) {
+            Ok((moss_packet, trailer_idx)) => {
+                stats.packets += 1;
+                stats.hits += moss_packet.hits.len();
+                moss_packets.push(moss_packet);
+                pos += trailer_idx + 1;
+            }
+            // Only filler bytes left
+            Err(e) if e.kind() == ParseErrorKind::NoHeaderFound => pos = buf.len(),
+            // Wait for the rest of the event
+            Err(e) if e.kind() == ParseErrorKind::EndOfBufferNoTrailer => break,
+            Err(e) => {
+                stats.protocol_errors += 1;
+                stats.last_error = Some(format!(
+                    "Failed decoding packet #{packet_cnt}: {e}",
+                    packet_cnt = stats.packets + 1
+                ));
+                pos += find_resume_idx(&buf[pos..], e.err_index());
+            }
+        }
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_feed_split_event() {
+        let event = fake_event_simple();
+        let mut state = StreamState::default();
+        let framing = FramingConfig::default();
+
+        assert!(state.feed(&event[..7], &framing).is_empty());
+        assert_eq!(state.remainder(), &event[..7]);
+        let packets = state.feed(&event[7..], &framing);
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].hits.len(), 4);
+        assert!(state.remainder().is_empty());
+        assert_eq!(state.stats().bytes, event.len());
+        assert_eq!(state.stats().packets, 1);
+        assert_eq!(state.stats().hits, 4);
+    }
+
+    #[test]
+    fn test_feed_recovers_from_protocol_error() {
+        let mut bytes = fake_event_simple();
+        bytes.insert(3, 0xD5); // Unexpected header in the first event
+        bytes.extend(fake_event_simple());
+        let mut state = StreamState::default();
+
+        let packets = state.feed(&bytes, &FramingConfig::default());
+
+        assert!(state.stats().protocol_errors > 0);
+        assert!(state.stats().last_error.is_some());
+        // The unexpected header starts a new (invalid) event, the second event is decoded
+        assert_eq!(packets.last().unwrap().hits.len(), 4);
+        assert!(state.remainder().is_empty());
+    }
+}
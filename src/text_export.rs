@@ -0,0 +1,422 @@
+//! Streaming export of decoded packets to CSV and JSON Lines files, optionally gzip compressed.
+//!
+//! The files are written by a [TextWriter] sink while the input is decoded, so runs larger than memory can be exported.
+//!
+//! The CSV file has a header line followed by a line `event,unit,region,row,column` per hit.
+//!
+//! The JSON Lines file has an object per packet, with the hits as `[region, row, column]` arrays:
+//! `{"event":0,"unit":1,"header_offset":0,"trailer_offset":11,"hits":[[0,2,3]]}`.
+//! Errors skipped with the `"skip"` error policy are written in stream order as `{"error":"<message>","offset":<offset>}`.
+//!
+//! Events are numbered by their sequence number in the stream, including skipped events.
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use pyo3::exceptions::{PyFileNotFoundError, PyValueError};
+use pyo3::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::decode_options::{self, DecodeOptions};
+use crate::sink::{PacketSink, SinkControl};
+use crate::{FramingConfig, MossPacket};
+
+/// Format of the exported file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// A line per hit, given as `"csv"`.
+    #[default]
+    Csv,
+    /// A JSON object per packet or skipped error, given as `"jsonl"`.
+    JsonLines,
+}
+
+impl<'source> FromPyObject<'source> for TextFormat {
+    fn extract(ob: &'source PyAny) -> PyResult<Self> {
+        match ob.extract::<&str>()? {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::JsonLines),
+            other => Err(PyValueError::new_err(format!(
+                "Invalid text format {other:?}, expected \"csv\" or \"jsonl\""
+            ))),
+        }
+    }
+}
+
+/// An output file, gzip compressed or not.
+#[derive(Debug)]
+pub enum OutputFile {
+    /// An uncompressed file.
+    Plain(BufWriter<File>),
+    /// A gzip compressed file.
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl OutputFile {
+    /// Creates the file at `path`, gzip compressed if `gzip` is true or, if it is `None`, if the file name ends in `.gz`.
+    pub fn create(path: impl AsRef<Path>, gzip: Option<bool>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let gzip = gzip.unwrap_or_else(|| path.extension().is_some_and(|ext| ext == "gz"));
+        let file = BufWriter::new(File::create(path)?);
+        Ok(if gzip {
+            Self::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Self::Plain(file)
+        })
+    }
+
+    /// Whether the file is gzip compressed.
+    pub fn is_gzip(&self) -> bool {
+        matches!(self, Self::Gzip(_))
+    }
+
+    /// Writes the buffered data and the gzip trailer, a compressed file is incomplete without it.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(mut file) => file.flush(),
+            Self::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// A [PacketSink] writing each packet to `out` as soon as it is decoded, in the given [TextFormat].
+#[derive(Debug)]
+pub struct TextWriter<W: Write> {
+    out: W,
+    format: TextFormat,
+    packets: usize,
+    hits: usize,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// Creates a writer, writing the CSV header if the format is [TextFormat::Csv].
+    pub fn new(mut out: W, format: TextFormat) -> std::io::Result<Self> {
+        if format == TextFormat::Csv {
+            writeln!(out, "event,unit,region,row,column")?;
+        }
+        Ok(Self {
+            out,
+            format,
+            packets: 0,
+            hits: 0,
+        })
+    }
+
+    /// Number of packets written so far.
+    pub fn packets(&self) -> usize {
+        self.packets
+    }
+
+    /// Number of hits written so far.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Returns the underlying writer, e.g. to [finish](OutputFile::finish) a compressed file.
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Writes `packet`, numbered by the sequence number of its metadata.
+    ///
+    /// The decoding loop always attaches metadata for this sink, packets without metadata are numbered by the packets written before.
+    fn write_packet(&mut self, packet: &MossPacket) -> std::io::Result<()> {
+        let event = packet
+            .metadata
+            .as_ref()
+            .map_or(self.packets, |metadata| metadata.sequence);
+        let unit = packet.unit_id;
+        match self.format {
+            TextFormat::Csv => {
+                for hit in &packet.hits {
+                    writeln!(
+                        self.out,
+                        "{event},{unit},{region},{row},{column}",
+                        region = hit.region,
+                        row = hit.row,
+                        column = hit.column
+                    )?;
+                }
+            }
+            TextFormat::JsonLines => {
+                write!(self.out, "{{\"event\":{event},\"unit\":{unit},")?;
+                match &packet.metadata {
+                    Some(metadata) => write!(
+                        self.out,
+                        "\"header_offset\":{},\"trailer_offset\":{},",
+                        metadata.header_offset, metadata.trailer_offset
+                    )?,
+                    None => write!(self.out, "\"header_offset\":null,\"trailer_offset\":null,")?,
+                }
+                write!(self.out, "\"hits\":[")?;
+                for (idx, hit) in packet.hits.iter().enumerate() {
+                    let separator = if idx == 0 { "" } else { "," };
+                    write!(
+                        self.out,
+                        "{separator}[{},{},{}]",
+                        hit.region, hit.row, hit.column
+                    )?;
+                }
+                writeln!(self.out, "]}}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> PacketSink for TextWriter<W> {
+    fn consume(&mut self, packet: MossPacket) -> PyResult<SinkControl> {
+        self.write_packet(&packet)?;
+        self.packets += 1;
+        self.hits += packet.hits.len();
+        Ok(SinkControl::Continue)
+    }
+
+    fn error(&mut self, message: &str, offset: usize) -> PyResult<()> {
+        if self.format == TextFormat::JsonLines {
+            write!(self.out, "{{\"error\":")?;
+            write_json_string(&mut self.out, message)?;
+            writeln!(self.out, ",\"offset\":{offset}}}")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> PyResult<()> {
+        Ok(self.out.flush()?)
+    }
+
+    fn needs_metadata(&self) -> bool {
+        true
+    }
+}
+
+/// Writes `s` as a quoted JSON string.
+fn write_json_string(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    write!(out, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{c}")?,
+        }
+    }
+    write!(out, "\"")
+}
+
+#[pyclass(get_all, module = "moss_decoder")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Summary of an export to a CSV or JSON Lines file.
+pub struct TextExportStats {
+    /// Number of packets written.
+    pub packets: usize,
+    /// Number of hits written.
+    pub hits: usize,
+    /// The errors skipped with the `"skip"` error policy.
+    pub errors: Vec<String>,
+    /// Number of bytes decoded.
+    pub bytes_consumed: usize,
+    /// The written file.
+    pub output_file: PathBuf,
+    /// Whether the written file is gzip compressed.
+    pub compressed: bool,
+}
+
+#[pymethods]
+impl TextExportStats {
+    fn __repr__(slf: &PyCell<Self>) -> PyResult<String> {
+        let class_name: &str = slf.get_type().name()?;
+        let slf = slf.borrow();
+        Ok(format!(
+            "{}(packets: {} hits: {} errors: {} file: {})",
+            class_name,
+            slf.packets,
+            slf.hits,
+            slf.errors.len(),
+            slf.output_file.display()
+        ))
+    }
+}
+
+/// Decodes the data from `reader` according to the [DecodeOptions] and writes the packets to `output` while decoding.
+///
+/// [PacketMetadata](crate::PacketMetadata) is always attached to the packets for the event numbers and byte offsets,
+/// [DecodeOptions::output] is ignored. `output` is gzip compressed if `gzip` is true or, if it is `None`, if its name ends in `.gz`.
+pub fn export_reader(
+    reader: impl Read,
+    output: impl AsRef<Path>,
+    format: TextFormat,
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    source_file: Option<&Path>,
+    gzip: Option<bool>,
+) -> PyResult<TextExportStats> {
+    let output = output.as_ref();
+    let file = OutputFile::create(output, gzip)?;
+    let compressed = file.is_gzip();
+    let mut writer = TextWriter::new(file, format)?;
+    let result =
+        decode_options::decode_reader_into(reader, options, framing, source_file, &mut writer)?;
+    let stats = TextExportStats {
+        packets: writer.packets(),
+        hits: writer.hits(),
+        errors: result.errors,
+        bytes_consumed: result.bytes_consumed,
+        output_file: output.to_path_buf(),
+        compressed,
+    };
+    writer.into_inner().finish()?;
+    Ok(stats)
+}
+
+/// Decodes the file at `input` and writes the packets to `output` while decoding, see [export_reader].
+pub fn export_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    format: TextFormat,
+    options: &DecodeOptions,
+    framing: &FramingConfig,
+    gzip: Option<bool>,
+) -> PyResult<TextExportStats> {
+    let input = input.as_ref();
+    let file = match File::open(input) {
+        Ok(file) => file,
+        Err(e) => return Err(PyFileNotFoundError::new_err(e.to_string())),
+    };
+    let reader = std::io::BufReader::with_capacity(crate::READER_BUFFER_CAPACITY, file);
+    export_reader(reader, output, format, options, framing, Some(input), gzip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode_options::ErrorPolicy;
+    use crate::moss_protocol::test_util::fake_event_simple;
+    use pretty_assertions::assert_eq;
+
+    fn write_to_string(bytes: &[u8], format: TextFormat, options: &DecodeOptions) -> String {
+        let mut writer = TextWriter::new(Vec::new(), format).unwrap();
+        _ = decode_options::decode_bytes_into(
+            bytes,
+            options,
+            &FramingConfig::default(),
+            &mut writer,
+        )
+        .unwrap();
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_csv_and_jsonl_lines() {
+        let bytes = fake_event_simple().repeat(2);
+        let (packets, _) = crate::decode_all_events(&bytes, None).unwrap();
+        let hits: usize = packets.iter().map(|p| p.hits.len()).sum();
+
+        let csv = write_to_string(&bytes, TextFormat::Csv, &DecodeOptions::default());
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("event,unit,region,row,column"));
+        let first_hit = &packets[0].hits[0];
+        assert_eq!(
+            lines.next().unwrap(),
+            format!(
+                "0,{},{},{},{}",
+                packets[0].unit_id, first_hit.region, first_hit.row, first_hit.column
+            )
+        );
+        assert_eq!(csv.lines().count(), 1 + hits);
+        assert!(csv.lines().last().unwrap().starts_with("1,"));
+
+        // Metadata is attached for the offsets without being requested
+        let jsonl = write_to_string(&bytes, TextFormat::JsonLines, &DecodeOptions::default());
+        assert_eq!(jsonl.lines().count(), 2);
+        let second = jsonl.lines().nth(1).unwrap();
+        assert!(second.starts_with(&format!(
+            "{{\"event\":1,\"unit\":{},\"header_offset\":{},",
+            packets[1].unit_id,
+            fake_event_simple().len()
+        )));
+        assert!(second.ends_with("]]}"));
+    }
+
+    #[test]
+    fn test_jsonl_skipped_errors() {
+        let mut bytes = fake_event_simple();
+        bytes.insert(3, 0xD5); // Unexpected header in the first event
+        bytes.extend(fake_event_simple());
+        let options = DecodeOptions {
+            errors: ErrorPolicy::Skip,
+            ..Default::default()
+        };
+        let jsonl = write_to_string(&bytes, TextFormat::JsonLines, &options);
+        let first = jsonl.lines().next().unwrap();
+        assert!(first.starts_with("{\"error\":\"Failed decoding packet #1"));
+        assert!(first.ends_with(",\"offset\":3}"));
+        assert!(jsonl.lines().last().unwrap().starts_with("{\"event\":"));
+    }
+
+    #[test]
+    fn test_events_numbered_by_sequence() {
+        let options = DecodeOptions {
+            skip: 2,
+            ..Default::default()
+        };
+        let csv = write_to_string(&fake_event_simple().repeat(4), TextFormat::Csv, &options);
+        assert!(csv.lines().nth(1).unwrap().starts_with("2,"));
+        assert!(csv.lines().last().unwrap().starts_with("3,"));
+    }
+
+    #[test]
+    fn test_json_string_escaping() {
+        let mut out = Vec::new();
+        write_json_string(&mut out, "a \"b\"\\\n\u{1}").unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#""a \"b\"\\\n\u0001""#);
+    }
+
+    #[test]
+    fn test_export_gzip_file() {
+        let dir = std::env::temp_dir().join(format!("moss_text_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.raw");
+        std::fs::write(&input, fake_event_simple().repeat(3)).unwrap();
+        let output = dir.join("hits.csv.gz");
+
+        let stats = export_file(
+            &input,
+            &output,
+            TextFormat::Csv,
+            &DecodeOptions::default(),
+            &FramingConfig::default(),
+            None,
+        )
+        .unwrap();
+        assert!(stats.compressed);
+        assert_eq!(stats.packets, 3);
+
+        let mut csv = String::new();
+        _ = flate2::read::GzDecoder::new(File::open(&output).unwrap())
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv.lines().count(), 1 + stats.hits);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+"""Checks that the moss_decoder.pyi stub matches the module, run by `test_stub_matches_module` in src/lib.rs.
+
+Expects the globals `module`, `stub_path` and `source_path`, the path of src/lib.rs. Appends the mismatches to `problems`.
+
+- Every function, class and constant of the module is in the stub and the other way around.
+- Functions, `__init__` and the other methods have the parameters and default values of their text signature.
+- The parameter and return annotations of the functions are those of their Rust signature, see `RUST_TYPES`.
+"""
+
+import ast
+import re
+
+# Python annotations of the Rust types of the functions. Vec<u8> arguments accept bytes and lists of ints,
+# &PyAny arguments are annotated by their name
+RUST_TYPES = {
+    "usize": "int",
+    "u8": "int",
+    "u16": "int",
+    "u32": "int",
+    "u64": "int",
+    "bool": "bool",
+    "String": "str",
+    "&str": "str",
+    "&[u8]": "ReadableBuffer",
+    "std::path::PathBuf": "str | Path",
+    "RunPaths": "str | list[str | Path]",
+    "corry_export::ExportInput": "list[MossEvent] | list[MossPacket]",
+    "corry_export::HitFileFormat": 'Literal["text", "binary"]',
+    "event_file::EventFileInput": "MossEventBatch | list[MossPacket]",
+    "event_file::ColumnCompression": 'Literal["none", "deflate"]',
+}
+PY_ANY_ARGUMENTS = {
+    "stream": "BinaryIO",
+    "sink": "PacketCallback | HitMap",
+}
+
+
+def normalize(annotation):
+    return ast.unparse(ast.parse(annotation, mode="eval"))
+
+
+def parse_rust_type(tokens):
+    """Parses a type from the tokens into a (name, arguments) tree, tuples are named `tuple`"""
+    token = tokens.pop(0)
+    if token == "(":
+        elements = []
+        while tokens[0] != ")":
+            elements.append(parse_rust_type(tokens))
+            if tokens[0] == ",":
+                tokens.pop(0)
+        tokens.pop(0)
+        return ("tuple", elements)
+    arguments = []
+    if tokens and tokens[0] == "<":
+        tokens.pop(0)
+        while tokens[0] != ">":
+            arguments.append(parse_rust_type(tokens))
+            if tokens[0] == ",":
+                tokens.pop(0)
+        tokens.pop(0)
+    return (token, arguments)
+
+
+def rust_type(text):
+    return parse_rust_type(re.findall(r"&\[u8\]|&?[\w:]+|[<>(),]", text))
+
+
+def python_types(node, aliases, is_argument):
+    """The annotations a Rust type can be given as in the stub"""
+    name, arguments = node
+    if name in aliases:
+        return python_types(aliases[name], aliases, is_argument)
+    inner = [python_types(argument, aliases, is_argument) for argument in arguments]
+
+    def combine(template):
+        results = {""}
+        for options in inner:
+            results = {f"{done}, {option}" if done else option for done in results for option in options}
+        return {template.format(result) for result in results}
+
+    if name == "Option":
+        return combine("Optional[{}]")
+    if name == "PyResult":
+        return inner[0]
+    if name == "tuple":
+        return combine("tuple[{}]")
+    if name == "Vec":
+        if is_argument and arguments == [("u8", [])]:
+            return {"list[int]", "bytes"}
+        return combine("list[{}]")
+    if name.split("::")[-1] in ("BTreeMap", "HashMap"):
+        return combine("dict[{}]")
+    if name in RUST_TYPES:
+        return {RUST_TYPES[name]}
+    if isinstance(getattr(module, name.split("::")[-1], None), type):
+        return {name.split("::")[-1]}
+    problems.append(f"Rust type {name} has no Python annotation in check_stub.py")
+    return set()
+
+
+def rust_functions(source):
+    """The parameters and return type of each `#[pyfunction]` in the source"""
+    functions = {}
+    for match in re.finditer(
+        r"#\[pyfunction\].*?fn (\w+)\((.*?)\)(?: -> (.*?))?\s*\{", source, re.DOTALL
+    ):
+        name, params, returns = match.groups()
+        params = re.sub(r"#\[pyo3\(from_py_with = [^\]]*\)\]", "", params)
+        parsed = []
+        for param in re.split(r",(?![^<(]*[>)])", params):
+            if param.strip():
+                param_name, param_type = param.split(":", 1)
+                parsed.append((param_name.strip(), param_type.strip()))
+        functions[name] = (parsed, returns)
+    return functions
+
+
+def stub_parameters(node):
+    """The parameter names and default values of a stub function, `self` excluded"""
+    args = node.args.posonlyargs + node.args.args
+    defaults = [None] * (len(args) - len(node.args.defaults)) + node.args.defaults
+    params = list(zip(args, defaults)) + list(zip(node.args.kwonlyargs, node.args.kw_defaults))
+    return [(arg, default) for arg, default in params if arg.arg != "self"]
+
+
+def module_parameters(obj):
+    """The parameter names and default values of the text signature of a function or method, `self` excluded"""
+    signature = getattr(obj, "__text_signature__", None)
+    if signature is None:
+        return None
+    signature = re.sub(r"\$\w+(, )?", "", signature).replace("(/, ", "(").replace("(/)", "()")
+    node = ast.parse(f"def f{signature}: pass").body[0]
+    return [(arg.arg, default) for arg, default in stub_parameters(node)]
+
+
+def same_default(module_default, stub_default):
+    if module_default is None or stub_default is None:
+        return module_default is None and stub_default is None
+    # Defaults that can't be written as a literal are given as `...`
+    if isinstance(module_default, ast.Constant) and module_default.value is Ellipsis:
+        return True
+    try:
+        return ast.literal_eval(module_default) == ast.literal_eval(stub_default)
+    except ValueError:
+        return ast.unparse(module_default) == ast.unparse(stub_default)
+
+
+def check_parameters(name, obj, node):
+    params = module_parameters(obj)
+    if params is None:
+        return
+    stub_params = stub_parameters(node)
+    if [param for param, _ in params] != [arg.arg for arg, _ in stub_params]:
+        problems.append(
+            f"{name}: module has {[param for param, _ in params]}, stub has {[arg.arg for arg, _ in stub_params]}"
+        )
+        return
+    for (param, default), (_, stub_default) in zip(params, stub_params):
+        if not same_default(default, stub_default):
+            show = lambda d: "no default" if d is None else ast.unparse(d)
+            problems.append(f"{name}({param}): module has {show(default)}, stub has {show(stub_default)}")
+
+
+with open(stub_path, encoding="utf-8") as stub_file:
+    stub = ast.parse(stub_file.read())
+with open(source_path, encoding="utf-8") as source_file:
+    source = source_file.read()
+aliases = {
+    name: rust_type(definition)
+    for name, definition in re.findall(r"^type (\w+) = (.*);$", source, re.MULTILINE)
+}
+stub_functions = {node.name: node for node in stub.body if isinstance(node, ast.FunctionDef)}
+stub_classes = {node.name: node for node in stub.body if isinstance(node, ast.ClassDef)}
+stub_constants = {node.target.id for node in stub.body if isinstance(node, ast.AnnAssign)}
+
+for name in dir(module):
+    obj = getattr(module, name)
+    if name.startswith("_"):
+        continue
+    if isinstance(obj, type):
+        if name not in stub_classes:
+            problems.append(f"class {name} is missing from the stub")
+            continue
+        for node in stub_classes[name].body:
+            # The parameters of the other special methods are positional-only, their names don't matter
+            if isinstance(node, ast.FunctionDef) and (node.name == "__init__" or not node.name.startswith("__")):
+                target = obj if node.name == "__init__" else getattr(obj, node.name, None)
+                if target is None:
+                    problems.append(f"{name}.{node.name} is in the stub but not in the module")
+                else:
+                    check_parameters(f"{name}.{node.name}", target, node)
+    elif not callable(obj):
+        if name not in stub_constants:
+            problems.append(f"constant {name} is missing from the stub")
+    elif name not in stub_functions:
+        problems.append(f"function {name} is missing from the stub")
+    else:
+        check_parameters(name, obj, stub_functions[name])
+for name in stub_functions.keys() | stub_classes.keys() | stub_constants:
+    if not hasattr(module, name):
+        problems.append(f"{name} is in the stub but not in the module")
+
+for name, (params, returns) in rust_functions(source).items():
+    node = stub_functions.get(name)
+    if node is None:
+        continue
+    annotations = {arg.arg: arg.annotation for arg, _ in stub_parameters(node)}
+    for param, param_type in params:
+        if param_type == "&PyAny":
+            expected = {PY_ANY_ARGUMENTS.get(param, "Any")}
+        else:
+            expected = python_types(rust_type(param_type), aliases, True)
+        annotation = annotations.get(param)
+        got = "nothing" if annotation is None else ast.unparse(annotation)
+        if expected and got not in {normalize(e) for e in expected}:
+            problems.append(f"{name}({param}): Rust type {param_type} is {' or '.join(sorted(expected))}, stub has {got}")
+    expected = python_types(rust_type(returns), aliases, False) if returns else {"None"}
+    got = "nothing" if node.returns is None else ast.unparse(node.returns)
+    if expected and got not in {normalize(e) for e in expected}:
+        problems.append(f"{name}: returns {' or '.join(sorted(expected))}, stub has {got}")
@@ -0,0 +1,93 @@
+/*
+ * Example of decoding a raw MOSS file through the C interface, as a DAQ producer would decode its readout buffers.
+ *
+ * Usage: ffi_example <raw file> <chunk size>
+ * Prints the number of decoded packets and hits.
+ * Protocol errors in the data are logged and decoding continues, the valid events of a chunk are decoded anyway.
+ */
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "moss_decoder.h"
+
+#define MAX_PACKETS 16
+#define MAX_HITS 4096
+
+static int check(moss_status status, const char *what) {
+    if (status != MOSS_STATUS_OK) {
+        fprintf(stderr, "%s failed (%d): %s\n", what, status, moss_last_error());
+        return 1;
+    }
+    return 0;
+}
+
+/* Poll until all decoded packets are retrieved, adding up the packets and hits */
+static int drain(moss_decoder *decoder, size_t *total_packets, size_t *total_hits) {
+    moss_packet packets[MAX_PACKETS];
+    moss_hit hits[MAX_HITS];
+    while (moss_decoder_pending(decoder) > 0) {
+        size_t packets_written = 0;
+        size_t hits_written = 0;
+        if (check(moss_decoder_poll(decoder, packets, MAX_PACKETS, hits, MAX_HITS, &packets_written, &hits_written),
+                  "moss_decoder_poll")) {
+            return 1;
+        }
+        for (size_t i = 0; i < packets_written; i++) {
+            *total_hits += packets[i].hit_count;
+        }
+        *total_packets += packets_written;
+    }
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "Usage: %s <raw file> <chunk size>\n", argv[0]);
+        return 2;
+    }
+    size_t chunk_size = (size_t)strtoul(argv[2], NULL, 10);
+    FILE *file = fopen(argv[1], "rb");
+    uint8_t *chunk = malloc(chunk_size);
+    if (file == NULL || chunk == NULL || chunk_size == 0) {
+        fprintf(stderr, "Cannot read %s in chunks of %s bytes\n", argv[1], argv[2]);
+        return 2;
+    }
+
+    moss_decoder *decoder = NULL;
+    if (check(moss_decoder_new(&decoder), "moss_decoder_new")) {
+        return 1;
+    }
+    size_t total_packets = 0;
+    size_t total_hits = 0;
+    size_t len;
+    size_t protocol_errors = 0;
+    while ((len = fread(chunk, 1, chunk_size, file)) > 0) {
+        moss_status status = moss_decoder_feed(decoder, chunk, len);
+        if (status == MOSS_STATUS_PROTOCOL) {
+            /* The valid events of the chunk are queued, log the error and keep polling */
+            fprintf(stderr, "Protocol error: %s\n", moss_last_error());
+            protocol_errors++;
+        } else if (check(status, "moss_decoder_feed")) {
+            return 1;
+        }
+        if (drain(decoder, &total_packets, &total_hits)) {
+            return 1;
+        }
+    }
+
+    moss_stats stats;
+    if (check(moss_decoder_stats(decoder, &stats), "moss_decoder_stats")) {
+        return 1;
+    }
+    if (stats.packets != total_packets || stats.hits != total_hits || stats.protocol_errors < protocol_errors) {
+        fprintf(stderr, "Statistics don't match the polled packets\n");
+        return 1;
+    }
+    printf("moss_decoder %s packets=%zu hits=%zu protocol_errors=%zu\n", moss_decoder_version(), total_packets, total_hits,
+           stats.protocol_errors);
+
+    moss_decoder_free(decoder);
+    free(chunk);
+    fclose(file);
+    return 0;
+}
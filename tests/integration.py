@@ -338,6 +338,295 @@ def test_fundamental_class_comparisons():
     print("\n==> MossPacket is OK\n\n")
 
 
+def test_buffer_protocol_input():
+    """Test decoding from objects implementing the buffer protocol"""
+    import mmap
+    import tempfile
+
+    print("=== Decoding from buffer protocol objects ===\n")
+    event = make_simple_moss_event_packet()
+    expected, _ = moss_decoder.decode_all_events(event * 3)
+
+    buffer = bytearray(event * 3)
+    packets, _ = moss_decoder.decode_all_events(buffer)
+    assert packets == expected, f"bytearray: {packets} != {expected}"
+    buffer.extend(b"\xfa")  # The buffer is released after the call
+
+    packets, _ = moss_decoder.decode_all_events(memoryview(buffer)[len(event) :])
+    assert packets == expected[1:], f"memoryview slice: {packets} != {expected[1:]}"
+
+    packet, _ = decode_event(memoryview(buffer)[: len(event)])
+    assert packet == expected[0], f"memoryview slice: {packet} != {expected[0]}"
+
+    with tempfile.TemporaryFile() as tmp_file:
+        tmp_file.write(buffer)
+        tmp_file.flush()
+        with mmap.mmap(tmp_file.fileno(), 0) as mapped:
+            packets, _ = moss_decoder.decode_all_events(mapped)
+            assert packets == expected, f"mmap: {packets} != {expected}"
+    print("==> Test OK\n\n")
+
+
+def test_decode_options():
+    """Test the unified decoding entry points with DecodeOptions"""
+    import io
+
+    print("=== Decoding with DecodeOptions ===\n")
+    event = make_simple_moss_event_packet()
+    expected, _ = moss_decoder.decode_all_events(event * 4)
+
+    result = moss_decoder.decode(event * 4, moss_decoder.DecodeOptions(skip=1, take=2))
+    assert result.packets == expected[1:3], f"{result.packets} != {expected[1:3]}"
+    assert result.skipped == 1 and len(result) == 2
+
+    # A partial event is returned as the remainder and completed by the next call
+    split = len(event) * 2 + 3
+    first = moss_decoder.decode((event * 4)[:split])
+    assert len(first) == 2 and first.remainder == (event * 4)[first.bytes_consumed : split]
+    options = moss_decoder.DecodeOptions(prepend=first.remainder)
+    second = moss_decoder.decode((event * 4)[split:], options)
+    assert first.packets + second.packets == expected
+
+    options = moss_decoder.DecodeOptions(output="batch", mask=[expected[0].hits[0]])
+    result = moss_decoder.decode_stream(io.BytesIO(event * 4), options)
+    assert result.batch is not None and len(result.batch) == 4
+    assert all(expected[0].hits[0] not in packet.hits for packet in result.batch.to_packets())
+
+    try:
+        moss_decoder.DecodeOptions(output="batch", metadata=True)
+        assert False, "metadata with batch output should raise"
+    except ValueError:
+        pass
+    print("==> Test OK\n\n")
+
+
+def test_decode_to_sink():
+    """Test decoding into a callback and a HitMap"""
+    import io
+
+    print("=== Decoding into sinks ===\n")
+    event = make_simple_moss_event_packet()
+    expected, _ = moss_decoder.decode_all_events(event * 10)
+
+    received = []
+    moss_decoder.decode_to_sink(event * 10, received.append)
+    assert received == expected, f"{received} != {expected}"
+
+    batches = []
+
+    def stop_after_two_batches(packets):
+        batches.append(packets)
+        if len(batches) == 2:
+            return moss_decoder.STOP
+
+    result = moss_decoder.decode_stream_to_sink(
+        io.BytesIO(event * 10), stop_after_two_batches, batch_size=4
+    )
+    assert [len(b) for b in batches] == [4, 4], f"{[len(b) for b in batches]}"
+    assert 7 * len(event) < result.bytes_consumed <= 8 * len(event)
+
+    # A callback overwriting a writable input does not change the data being decoded
+    data = bytearray(event * 10)
+
+    def overwrite_input(packet):
+        data[:] = b"\xfa" * len(data)
+        received.append(packet)
+
+    received = []
+    moss_decoder.decode_to_sink(data, overwrite_input)
+    assert received == expected, f"{received} != {expected}"
+
+    hit_map = moss_decoder.HitMap()
+    moss_decoder.decode_to_sink(event * 10, hit_map)
+    assert hit_map.events == 10 and hit_map.hits == 10 * len(expected[0].hits)
+    unit_id, hit, count = hit_map.hottest(1)[0]
+    assert unit_id == expected[0].unit_id and count == 10
+    assert hit_map.count(unit_id, hit.region, hit.row, hit.column) == 10
+    assert hit_map.region_map(unit_id, hit.region)[hit.row][hit.column] == 10
+    print("==> Test OK\n\n")
+
+
+def test_moss_decoder_feed():
+    """Test decoding a stream in small chunks with a reusable MossDecoder"""
+    print("=== Decoding chunks with MossDecoder ===\n")
+    event = make_simple_moss_event_packet()
+    stream = event * 20
+    expected, _ = moss_decoder.decode_all_events(stream)
+
+    decoder = moss_decoder.MossDecoder()
+    packets = []
+    for i in range(0, len(stream), 7):
+        packets.extend(decoder.feed(stream[i : i + 7]))
+    assert packets == expected, f"{packets} != {expected}"
+    assert decoder.stats.packets == 20 and decoder.stats.bytes == len(stream)
+
+    assert decoder.feed(event[:5]) == []
+    assert decoder.remainder == event[:5]
+    decoder.reset()
+    assert decoder.remainder == b"" and decoder.stats.packets == 0
+    print("==> Test OK\n\n")
+
+
+def test_export_corryvreckan():
+    """Test exporting decoded events as Corryvreckan pixel hit files"""
+    import tempfile
+
+    print("=== Exporting to Corryvreckan ===\n")
+    packets = moss_decoder.decode_from_file_with_metadata(FILE_PATTERN_ALL_REGIONS)
+    hits = sum(len(packet.hits) for packet in packets)
+    layout = moss_decoder.DetectorLayout.stitched()
+    assert layout.position(2, MossHit(1, 3, 4)) == ("MOSS", 1024 + 256 + 4, 3)
+
+    with tempfile.TemporaryDirectory() as output_dir:
+        stats = moss_decoder.export_corryvreckan(packets, output_dir, layout)
+        assert stats.hits_written + stats.hits_skipped == hits, f"{stats}"
+        assert len(stats.output_files) == 3
+        with open(Path(output_dir) / "MOSS.txt") as hit_file:
+            lines = [line for line in hit_file if not line.startswith("#")]
+        assert len(lines) == stats.hits_written
+        with open(Path(output_dir) / "events.txt") as index_file:
+            events = [int(line) for line in index_file if not line.startswith("#")]
+        # Numbered by the sequence number of the first packet of each event
+        built = moss_decoder.build_events(packets)
+        assert events == [event.packets[0].metadata.sequence for event in built], f"{events[:10]}"
+        with open(Path(output_dir) / "geometry.conf") as geometry:
+            # The top half-units by default
+            assert "number_of_pixels = 5120, 256" in geometry.read()
+    print("==> Test OK\n\n")
+
+
+def test_export_csv_and_jsonl():
+    """Test exporting decoded packets to CSV and gzip compressed JSON Lines files"""
+    import csv
+    import gzip
+    import json
+    import tempfile
+
+    print("=== Exporting to CSV and JSON Lines ===\n")
+    packets = moss_decoder.decode_from_file_with_metadata(FILE_PATTERN_ALL_REGIONS)
+    hits = sum(len(packet.hits) for packet in packets)
+
+    with tempfile.TemporaryDirectory() as output_dir:
+        csv_path = Path(output_dir) / "hits.csv"
+        stats = moss_decoder.export_csv(FILE_PATTERN_ALL_REGIONS, csv_path)
+        assert not stats.compressed
+        assert stats.packets == len(packets) and stats.hits == hits, f"{stats}"
+        with open(csv_path, newline="") as csv_file:
+            rows = list(csv.DictReader(csv_file))
+        assert len(rows) == hits
+        first_hit = packets[0].hits[0]
+        assert int(rows[0]["region"]) == first_hit.region
+        assert int(rows[0]["row"]) == first_hit.row
+        assert int(rows[0]["column"]) == first_hit.column
+
+        jsonl_path = Path(output_dir) / "packets.jsonl.gz"
+        stats = moss_decoder.export_jsonl(
+            FILE_PATTERN_ALL_REGIONS, jsonl_path, moss_decoder.DecodeOptions(take=3)
+        )
+        assert stats.compressed and stats.packets == 3
+        with gzip.open(jsonl_path, "rt") as jsonl_file:
+            objects = [json.loads(line) for line in jsonl_file]
+        assert [obj["event"] for obj in objects] == [0, 1, 2]
+        assert objects[0]["unit"] == packets[0].unit_id
+        assert objects[0]["hits"][0] == [first_hit.region, first_hit.row, first_hit.column]
+        assert objects[1]["header_offset"] > objects[0]["trailer_offset"]
+    print("==> Test OK\n\n")
+
+
+def test_event_file():
+    """Test writing decoded events to an event file and reading them back"""
+    import array
+    import mmap
+    import tempfile
+
+    print("=== Event files ===\n")
+    batch = moss_decoder.decode_all_events_batch(
+        FILE_PATTERN_ALL_REGIONS.read_bytes()
+    )[0]
+    with tempfile.TemporaryDirectory() as output_dir:
+        path = Path(output_dir) / "events.mev"
+        info = moss_decoder.write_event_file(
+            path, batch, {"run": "42"}, compression="deflate"
+        )
+        assert info.events == len(batch) and info.metadata["run"] == "42", f"{info}"
+        assert info.column_offsets == {}  # compressed
+        assert moss_decoder.read_event_file(path).to_packets() == batch.to_packets()
+
+        # Uncompressed by default
+        packets = batch.to_packets()[:10]
+        moss_decoder.write_event_file(path, packets)
+        info = moss_decoder.read_event_file_info(path)
+        assert info.version[0] == 1 and info.hits == sum(len(p.hits) for p in packets)
+        assert moss_decoder.read_event_file(path).to_packets() == packets
+        # Uncompressed columns can be mapped without copying, e.g. with numpy.memmap
+        with open(path, "rb") as file:
+            with mmap.mmap(file.fileno(), 0, access=mmap.ACCESS_READ) as mapped:
+                offset = info.column_offsets["rows"]
+                rows = array.array("H", mapped[offset : offset + 2 * info.hits])
+        assert rows.tolist() == [hit.row for p in packets for hit in p.hits]
+    print("==> Test OK\n\n")
+
+
+def test_event_batch_views():
+    """Test iterating a MossEventBatch and reading its arrays without copying them"""
+    print("=== Event batch views ===\n")
+    packets = moss_decoder.decode_from_file(FILE_NOISE_RANDOM_REGION)
+    batch = moss_decoder.MossEventBatch.from_packets(packets)
+
+    views = list(batch)
+    assert len(views) == len(packets)
+    for view, packet in zip(views, packets):
+        assert view.unit_id == packet.unit_id and view.hits == packet.hits
+        assert view.to_packet() == packet and len(view) == len(packet.hits)
+        assert view.rows.to_list() == [hit.row for hit in packet.hits]
+    assert views[-1].index == len(batch) - 1
+
+    # The arrays share the memory of the batch through the buffer protocol
+    rows = memoryview(batch.rows)
+    assert rows.readonly and rows.format == "H" and len(rows) == len(batch.rows)
+    assert rows.tolist() == [hit.row for packet in packets for hit in packet.hits]
+    offsets = memoryview(batch.event_offsets)
+    assert offsets.tolist()[-1] == len(batch.regions) and batch.event_offsets[-1] == len(rows)
+    event = views[1]
+    assert memoryview(event.columns).tolist() == [hit.column for hit in packets[1].hits]
+    try:
+        memoryview(batch.unit_ids).cast("B")[0] = 1
+        assert False, "batch arrays should be read-only"
+    except TypeError:
+        pass
+    # A view keeps the batch alive
+    del batch, views
+    assert rows.tolist()[: len(packets[0].hits)] == [hit.row for hit in packets[0].hits]
+    print("==> Test OK\n\n")
+
+
+def test_pickle_copy_and_hash():
+    """Test pickling, copying, hashing and ordering of MossHit and MossPacket"""
+    import copy
+    import pickle
+
+    print("=== Pickling, copying and hashing ===\n")
+    hit = MossHit(1, 2, 3)
+    assert pickle.loads(pickle.dumps(hit)) == hit
+    assert copy.copy(hit) == hit and copy.deepcopy(hit) == hit
+    assert len({hit, MossHit(1, 2, 3), MossHit(1, 3, 2)}) == 2
+    assert MossHit(0, 300, 300) < MossHit(1, 0, 0)
+    assert not MossHit(0, 300, 300) > MossHit(1, 0, 0)
+    print("\tMossHit is OK")
+
+    packets, _ = moss_decoder.decode_all_events_with_metadata(make_simple_moss_event_packet())
+    packet = packets[0]
+    unpickled = pickle.loads(pickle.dumps(packet))
+    assert unpickled == packet and hash(unpickled) == hash(packet)
+    assert unpickled.hits == packet.hits
+    assert unpickled.metadata.trailer_offset == packet.metadata.trailer_offset
+    assert copy.deepcopy(packet) == packet
+    assert {packet: 1}[unpickled] == 1
+    assert MossPacket(1) < MossPacket(2)
+    print("\tMossPacket is OK")
+    print("==> Test OK\n\n")
+
+
 def test_debug_decode_events(
     test_file: Path,
     expect_trailer_idx: int,
@@ -416,6 +705,15 @@ if __name__ == "__main__":
             sys.exit(0)
 
     test_fundamental_class_comparisons()
+    test_pickle_copy_and_hash()
+    test_buffer_protocol_input()
+    test_decode_options()
+    test_decode_to_sink()
+    test_moss_decoder_feed()
+    test_export_corryvreckan()
+    test_export_csv_and_jsonl()
+    test_event_file()
+    test_event_batch_views()
     test_decode_partial_events_from_two_files()
 
     start = time.time()
@@ -35,20 +35,12 @@ const THREE_EVENTS_PARTIAL_START_LAST_TRAILER_IDX: usize = 379;
 
 // Utility to compare all packets in two vectors (for comparing result of different decoding methods)
 fn compare_all_packets(a_packets: &[MossPacket], b_packets: &[MossPacket]) {
-    assert_eq!(a_packets.len(), b_packets.len());
-
-    for (i, a_packet) in a_packets.iter().enumerate() {
-        let b_packet = &b_packets[i];
-        assert_eq!(a_packet.unit_id, b_packet.unit_id);
-        assert_eq!(a_packet.hits.len(), b_packet.hits.len());
-        for (j, a_hit) in a_packet.hits.iter().enumerate() {
-            let b_hit = &b_packet.hits[j];
-
-            assert_eq!(a_hit.region, b_hit.region);
-            assert_eq!(a_hit.row, b_hit.row);
-            assert_eq!(a_hit.column, b_hit.column);
-        }
-    }
+    let diff = moss_decoder::diff::diff_packets(a_packets, b_packets);
+    assert!(
+        diff.is_identical(),
+        "{diff}\nFirst difference: {first}",
+        first = diff.differences[0]
+    );
 }
 
 // Compare the result of all decoding methods that can decode all packets from a file/byte array
@@ -62,7 +54,7 @@ fn compare_all_decoding_methods(
 
     // Do an initial comparison with the simple naive decoder and the expected values
     let (debug_packets, debug_last_trailer_idx, invalid_words) =
-        moss_decoder::debug_decode_all_events(&bytes).unwrap();
+        moss_decoder::debug_decode_all_events(&bytes, None).unwrap();
     assert_eq!(debug_last_trailer_idx, expect_trailer_idx, "Unexpected last trailer index, got trailer index: {debug_last_trailer_idx}, expected: {expect_trailer_idx}. From trailer index to end of bytes: {remainder:#X?}", remainder = bytes.get(debug_last_trailer_idx..).unwrap());
     assert_eq!(
         debug_packets.len(),
@@ -80,7 +72,7 @@ fn compare_all_decoding_methods(
 
     // Check moss_decoder::debug_decode_all_events_from_file
     let (debug_packets_from_file, debug_last_trailer_idx_from_file, invalid_words_from_file) =
-        moss_decoder::debug_decode_all_events_from_file(test_file.into()).unwrap();
+        moss_decoder::debug_decode_all_events_from_file(test_file.into(), None).unwrap();
     assert_eq!(
         debug_last_trailer_idx_from_file, debug_last_trailer_idx,
         "Unexpected last trailer index, got trailer index: {debug_last_trailer_idx_from_file}, expected: {debug_last_trailer_idx}. From trailer index to end of bytes: {remainder:#X?}",
@@ -91,23 +83,30 @@ fn compare_all_decoding_methods(
 
     // Check moss_decoder::decode_all_events
     let (decode_all_events_packets, decode_all_events_last_trailer_idx) =
-        moss_decoder::decode_all_events(&bytes).unwrap();
+        moss_decoder::decode_all_events(&bytes, None).unwrap();
     assert_eq!(debug_last_trailer_idx, decode_all_events_last_trailer_idx);
     compare_all_packets(&debug_packets, &decode_all_events_packets);
 
+    // Check moss_decoder::decode_all_events_batch
+    let (batch, batch_last_trailer_idx) =
+        moss_decoder::decode_all_events_batch(&bytes, None).unwrap();
+    assert_eq!(debug_last_trailer_idx, batch_last_trailer_idx);
+    assert_eq!(batch.hit_count(), expect_hits);
+    compare_all_packets(&batch.to_packets(), &decode_all_events_packets);
+
     // Check moss_decoder::decode_from_file
-    let packets = moss_decoder::decode_from_file(test_file.into()).unwrap();
+    let packets = moss_decoder::decode_from_file(test_file.into(), None).unwrap();
     compare_all_packets(&packets, &decode_all_events_packets);
 
     // Check moss_decoder::skip_n_take_all
-    let (packets, remainder) = moss_decoder::skip_n_take_all(&bytes, 0).unwrap();
+    let (packets, remainder) = moss_decoder::skip_n_take_all(&bytes, 0, None).unwrap();
     let packets = packets.unwrap();
     assert!(remainder.is_none());
     compare_all_packets(&packets, &decode_all_events_packets);
 
     // Check moss_decoder::decode_n_events
     let (packets, last_trailer_idx) =
-        moss_decoder::decode_n_events(&bytes, expect_packets, None, None).unwrap();
+        moss_decoder::decode_n_events(&bytes, expect_packets, None, None, None).unwrap();
     assert_eq!(last_trailer_idx, debug_last_trailer_idx);
     compare_all_packets(&packets, &decode_all_events_packets);
 }
@@ -116,7 +115,7 @@ fn compare_all_decoding_methods(
 fn test_decoding_single_event() {
     let event = fake_event_simple();
 
-    let (packet, last_trailer_idx) = decode_event(&event).unwrap();
+    let (packet, last_trailer_idx) = decode_event(&event, None).unwrap();
 
     assert!(
         last_trailer_idx == event.len() - 1,
@@ -148,7 +147,8 @@ fn test_decoding_single_event() {
                     row: 2,
                     column: 8
                 },
-            ]
+            ],
+            metadata: None,
         },
         "unexpected decoding result"
     );
@@ -159,7 +159,7 @@ fn test_decoding_single_event_fsm() {
     //
     let event = fake_event_simple();
 
-    let (packet, last_trailer_idx) = decode_event(&event).unwrap();
+    let (packet, last_trailer_idx) = decode_event(&event, None).unwrap();
 
     assert!(
         last_trailer_idx == event.len() - 1,
@@ -191,7 +191,8 @@ fn test_decoding_single_event_fsm() {
                     row: 2,
                     column: 8
                 },
-            ]
+            ],
+            metadata: None,
         },
         "unexpected decoding result"
     );
@@ -204,7 +205,7 @@ fn test_decoding_multiple_events_one_call() {
     let mut moss_packets: Vec<MossPacket> = Vec::new();
 
     // There's multiple events in the data but we only call decode_event once so we should only get one packet
-    if let Ok((packet, _unprocessed_data)) = decode_event(&events) {
+    if let Ok((packet, _unprocessed_data)) = decode_event(&events, None) {
         moss_packets.push(packet);
     }
 
@@ -230,7 +231,7 @@ fn test_read_file_decode() {
     );
 
     println!("Decoding content...");
-    let (p, last_trailer_idx) = decode_all_events(&f).unwrap();
+    let (p, last_trailer_idx) = decode_all_events(&f, None).unwrap();
     println!("Decoded in: {t:?}\n", t = time.elapsed());
 
     println!("Got: {packets} packets", packets = p.len());
@@ -252,7 +253,7 @@ fn test_decode_from_file() {
     let expect_packets = 100000;
     let expect_hits = 2716940;
 
-    let packets = moss_decoder::decode_from_file(FILE_MOSS_NOISE.to_string().into()).unwrap();
+    let packets = moss_decoder::decode_from_file(FILE_MOSS_NOISE.to_string().into(), None).unwrap();
     println!("Decoded in: {t:?}\n", t = time.elapsed());
 
     println!("Got: {packets}", packets = packets.len());
@@ -275,7 +276,8 @@ fn test_decode_from_file() {
 #[test]
 fn test_decode_from_file_noise_all_region() {
     let packets =
-        moss_decoder::decode_from_file(FILE_MOSS_NOISE_ALL_REGION.to_string().into()).unwrap();
+        moss_decoder::decode_from_file(FILE_MOSS_NOISE_ALL_REGION.to_string().into(), None)
+            .unwrap();
     assert_eq!(
         packets.len(),
         NOISE_ALL_REGION_PACKETS,
@@ -296,7 +298,7 @@ fn test_decode_from_file_noise_random_region() {
     let expect_hits = 5380;
 
     let packets =
-        moss_decoder::decode_from_file(FILE_NOISE_RANDOM_REGION.to_string().into()).unwrap();
+        moss_decoder::decode_from_file(FILE_NOISE_RANDOM_REGION.to_string().into(), None).unwrap();
     assert_eq!(
         packets.len(),
         expect_packets,
@@ -317,7 +319,7 @@ fn test_decode_from_file_pattern_all_region() {
     let expect_hits = 4000;
 
     let packets =
-        moss_decoder::decode_from_file(FILE_PATTERN_ALL_REGIONS.to_string().into()).unwrap();
+        moss_decoder::decode_from_file(FILE_PATTERN_ALL_REGIONS.to_string().into(), None).unwrap();
     assert_eq!(
         packets.len(),
         expect_packets,
@@ -338,7 +340,7 @@ fn test_decode_protocol_error() {
 
     let event = fake_event_protocol_error();
 
-    match decode_event(&event) {
+    match decode_event(&event, None) {
         Ok(_) => {
             panic!("This packet has a protocol error, but it was not detected!")
         }
@@ -367,7 +369,7 @@ fn test_decode_multiple_events_fsm() {
     );
 
     println!("Decoding content...");
-    let (p, last_trailer_idx) = decode_all_events(&f).unwrap();
+    let (p, last_trailer_idx) = decode_all_events(&f, None).unwrap();
     println!("Decoded in: {t:?}\n", t = time.elapsed());
 
     println!("Got: {packets} packets", packets = p.len());
@@ -400,7 +402,7 @@ fn test_decode_from_file_fsm() {
     let expect_packets = 100000;
     let expect_hits = 2716940;
 
-    let packets = moss_decoder::decode_from_file(FILE_MOSS_NOISE.to_string().into()).unwrap();
+    let packets = moss_decoder::decode_from_file(FILE_MOSS_NOISE.to_string().into(), None).unwrap();
     println!("Decoded in: {t:?}\n", t = time.elapsed());
 
     println!("Got: {packets}", packets = packets.len());
@@ -426,7 +428,7 @@ fn test_decode_protocol_error_fsm() {
 
     let event = fake_event_protocol_error();
 
-    match decode_event(&event) {
+    match decode_event(&event, None) {
         Ok(_) => {
             panic!("This packet has a protocol error, but it was not detected!")
         }
@@ -443,7 +445,7 @@ fn test_decode_protocol_error_fsm() {
 fn test_decode_events_skip_0_take_10() {
     let take = 10;
     let f = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE)).unwrap();
-    let (p, last_trailer_idx) = decode_n_events(&f, take, None, None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, None, None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -456,7 +458,7 @@ fn test_decode_events_skip_10_take_1() {
     let take = 1;
     let f = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -469,7 +471,7 @@ fn test_decode_events_skip_500_take_100() {
     let take = 100;
     let f = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -482,7 +484,7 @@ fn test_decode_events_skip_99000_take_1000() {
     let take = 1000;
     let f = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None, None).unwrap();
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
     assert_eq!(p.len(), take, "Expected {take} packets, got {}", p.len());
@@ -495,7 +497,7 @@ fn test_decode_split_events_skip_0_take_5() {
     let take = 5;
     let f = std::fs::read(std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, None, None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, None, None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -509,7 +511,7 @@ fn test_decode_split_events_skip_1_take_2() {
     let take = 2;
     let f = std::fs::read(std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -523,7 +525,7 @@ fn test_decode_split_events_from_partial_event_skip_1_take_2() {
     let take = 2;
     let f = std::fs::read(std::path::PathBuf::from(FILE_3_EVENTS_PARTIAL_START)).unwrap();
 
-    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None).unwrap();
+    let (p, last_trailer_idx) = decode_n_events(&f, take, Some(skip), None, None).unwrap();
 
     println!("Got: {packets} packets", packets = p.len());
     println!("Last trailer at index: {last_trailer_idx}");
@@ -536,9 +538,9 @@ fn test_decode_split_events_with_remainder() {
     let take = 100;
     let f = std::fs::read(std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END)).unwrap();
 
-    assert!(decode_n_events(&f, take, None, None).is_err());
+    assert!(decode_n_events(&f, take, None, None, None).is_err());
 
-    let (packets, remainder) = skip_n_take_all(&f, 0).unwrap();
+    let (packets, remainder) = skip_n_take_all(&f, 0, None).unwrap();
 
     let remainder = remainder.unwrap();
     let packets = packets.unwrap();
@@ -557,16 +559,16 @@ fn test_decode_split_events_from_both_files() {
     let f2 = std::fs::read(std::path::PathBuf::from(FILE_3_EVENTS_PARTIAL_START)).unwrap();
 
     // First attempt to decode 6 events from the first file, that should fail
-    assert!(decode_n_events(&f, take, None, None).is_err());
+    assert!(decode_n_events(&f, take, None, None, None).is_err());
 
     // Then fall back to decoding as many as possible and returning the remainder
-    let (packets, remainder) = skip_n_take_all(&f, 0).unwrap();
+    let (packets, remainder) = skip_n_take_all(&f, 0, None).unwrap();
     let packets = packets.unwrap();
     let decoded_packets = packets.len();
 
     // Now take the rest from the remainder and the next file
     let (packets2, last_trailer_idx) =
-        decode_n_events(&f2, take - decoded_packets, None, remainder).unwrap();
+        decode_n_events(&f2, take - decoded_packets, None, remainder, None).unwrap();
 
     println!("Got: {packets} packets", packets = packets.len());
     println!("Got: {packets2} packets", packets2 = packets2.len());
@@ -579,7 +581,7 @@ fn test_decode_2_events_from_path() {
     pyo3::prepare_freethreaded_python();
     let take = 2;
     let p = std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END);
-    let res = decode_n_events_from_file(p, take, None, None);
+    let res = decode_n_events_from_file(p, take, None, None, None);
     let packets = res.unwrap();
     println!("Got: {packets} packets", packets = packets.len());
     assert_eq!(packets.len(), take);
@@ -590,19 +592,25 @@ fn test_decode_split_events_from_path_repeated_until_err() {
     pyo3::prepare_freethreaded_python();
     let take_first = 2;
     let p = std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END);
-    let res = decode_n_events_from_file(p.clone(), take_first, None, None);
+    let res = decode_n_events_from_file(p.clone(), take_first, None, None, None);
     let mut running_packets = res.unwrap();
     println!("Got: {packets} packets", packets = running_packets.len());
     assert_eq!(running_packets.len(), take_first);
 
     let take_second = 2;
-    let res = decode_n_events_from_file(p.clone(), take_second, Some(running_packets.len()), None);
+    let res = decode_n_events_from_file(
+        p.clone(),
+        take_second,
+        Some(running_packets.len()),
+        None,
+        None,
+    );
     running_packets.extend(res.unwrap());
     println!("Got: {packets} packets", packets = running_packets.len());
     assert_eq!(running_packets.len(), take_first + take_second);
 
     let take_third = 2;
-    let res = decode_n_events_from_file(p, take_third, Some(running_packets.len()), None);
+    let res = decode_n_events_from_file(p, take_third, Some(running_packets.len()), None, None);
     println!("Got : {:?}", res);
     assert!(res.is_err());
     assert!(res
@@ -616,7 +624,7 @@ fn test_decode_split_events_from_path_take_too_many() {
     pyo3::prepare_freethreaded_python();
     let take_first = 10;
     let p = std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END);
-    let res = decode_n_events_from_file(p.clone(), take_first, None, None);
+    let res = decode_n_events_from_file(p.clone(), take_first, None, None, None);
     println!("Got : {:?}", res);
     assert!(res.is_err());
     assert!(res.unwrap_err().to_string().contains("BytesWarning"));
@@ -626,23 +634,29 @@ fn test_decode_split_events_from_path_take_too_many() {
 fn test_skip_n_take_all_from_file() {
     pyo3::prepare_freethreaded_python();
     let p = std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END);
-    let res = skip_n_take_all_from_file(p.clone(), 0);
+    let res = skip_n_take_all_from_file(p.clone(), 0, None);
     assert!(res.is_ok());
     let (packets, remainder) = res.unwrap();
     assert!(packets.is_some());
     assert!(remainder.is_some());
     assert_eq!(packets.unwrap().len(), 4);
     let remainder = remainder.unwrap();
+    // Same as the remainder of the bytes
+    let bytes = std::fs::read(&p).unwrap();
+    assert_eq!(
+        Some(remainder.clone()),
+        skip_n_take_all(&bytes, 0, None).unwrap().1
+    );
     println!("Got {} remainder bytes", remainder.len());
     println!("Got remainder: {:02X?}", remainder);
 
-    let (packets, _) = skip_n_take_all_from_file(p.clone(), 1).unwrap();
+    let (packets, _) = skip_n_take_all_from_file(p.clone(), 1, None).unwrap();
     assert_eq!(packets.unwrap().len(), 3);
-    let (packets, _) = skip_n_take_all_from_file(p.clone(), 2).unwrap();
+    let (packets, _) = skip_n_take_all_from_file(p.clone(), 2, None).unwrap();
     assert_eq!(packets.unwrap().len(), 2);
-    let (packets, _) = skip_n_take_all_from_file(p.clone(), 3).unwrap();
+    let (packets, _) = skip_n_take_all_from_file(p.clone(), 3, None).unwrap();
     assert_eq!(packets.unwrap().len(), 1);
-    let (packets, _) = skip_n_take_all_from_file(p.clone(), 4).unwrap();
+    let (packets, _) = skip_n_take_all_from_file(p.clone(), 4, None).unwrap();
     assert!(packets.is_none());
 }
 
@@ -658,7 +672,7 @@ fn test_decode_split_events_from_file_spillover() {
         } else {
             Some(running_packets.len())
         };
-        let res = decode_n_events_from_file(p.clone(), take, skip, None);
+        let res = decode_n_events_from_file(p.clone(), take, skip, None, None);
         if res.is_err() {
             println!("Got error: {:?}", res);
             break;
@@ -666,16 +680,505 @@ fn test_decode_split_events_from_file_spillover() {
         running_packets.extend(res.unwrap());
     }
     let skip = running_packets.len();
-    let (packets, remainder) = skip_n_take_all_from_file(p.clone(), skip).unwrap();
+    let (packets, remainder) = skip_n_take_all_from_file(p.clone(), skip, None).unwrap();
     assert!(
         packets.is_none(),
         "take is two ({take}) but there's still packets in the file"
     );
     let p2 = std::path::PathBuf::from(FILE_3_EVENTS_PARTIAL_START);
-    let res = decode_n_events_from_file(p2.clone(), take, None, remainder);
+    let res = decode_n_events_from_file(p2.clone(), take, None, remainder, None);
     assert_eq!(res.unwrap().len(), 2);
 }
 
+#[test]
+fn test_decode_run_split_events_from_both_files() {
+    pyo3::prepare_freethreaded_python();
+    let f = std::path::PathBuf::from(FILE_4_EVENTS_PARTIAL_END);
+    let f2 = std::path::PathBuf::from(FILE_3_EVENTS_PARTIAL_START);
+
+    let packets = decode_run(RunPaths::Files(vec![f.clone(), f2.clone()]), None).unwrap();
+
+    println!("Got: {packets} packets", packets = packets.len());
+    // The 5th event starts in the first file and ends in the second
+    assert_eq!(
+        packets.len(),
+        FOUR_EVENTS_PARTIAL_END_PACKETS + 1 + THREE_EVENTS_PARTIAL_START_PACKETS
+    );
+    let source = |idx: usize| packets[idx].metadata.as_ref().unwrap().source_file.clone();
+    assert_eq!(source(FOUR_EVENTS_PARTIAL_END_PACKETS), Some(f.clone()));
+    assert_eq!(
+        source(FOUR_EVENTS_PARTIAL_END_PACKETS + 1),
+        Some(f2.clone())
+    );
+
+    // Header offsets are relative to the file they were found in
+    let bytes = std::fs::read(&f).unwrap();
+    let bytes2 = std::fs::read(&f2).unwrap();
+    for (i, packet) in packets.iter().enumerate() {
+        let metadata = packet.metadata.as_ref().unwrap();
+        assert_eq!(metadata.sequence, i);
+        let header = if metadata.source_file.as_ref() == Some(&f) {
+            bytes[metadata.header_offset]
+        } else {
+            bytes2[metadata.header_offset]
+        };
+        assert!(
+            (0xD1..=0xDA).contains(&header),
+            "Expected header, got {header:#X}"
+        );
+    }
+    // The trailer of the stitched event is past the end of its source file
+    let stitched = packets[FOUR_EVENTS_PARTIAL_END_PACKETS]
+        .metadata
+        .as_ref()
+        .unwrap();
+    assert_eq!(
+        stitched.trailer_offset - bytes.len(),
+        bytes2
+            .iter()
+            .position(|&b| b == UNIT_FRAME_TRAILER)
+            .unwrap()
+    );
+
+    // Decoding the same run from a glob gives the same result
+    let glob_packets = decode_run(
+        RunPaths::Glob("tests/test-data/moss_noise_*b.raw".to_string()),
+        None,
+    )
+    .unwrap();
+    assert_eq!(glob_packets, packets);
+    assert!(glob_packets
+        .iter()
+        .zip(&packets)
+        .all(|(a, b)| a.metadata == b.metadata));
+}
+
+#[test]
+fn test_decode_run_missing_file() {
+    pyo3::prepare_freethreaded_python();
+    let missing = std::path::PathBuf::from("tests/test-data/does_not_exist.raw");
+    let err = decode_run(
+        RunPaths::Files(vec![FILE_4_EVENTS_PARTIAL_END.into(), missing]),
+        None,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("FileNotFoundError"), "{err}");
+    assert!(err.to_string().contains("does_not_exist.raw"), "{err}");
+
+    // Errors while reading are raised too, reading a directory fails after opening it
+    let directory = std::path::PathBuf::from("tests/test-data");
+    let err = decode_run(
+        RunPaths::Files(vec![FILE_4_EVENTS_PARTIAL_END.into(), directory]),
+        None,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("tests/test-data"), "{err}");
+}
+
+#[test]
+fn test_decode_run_no_files() {
+    pyo3::prepare_freethreaded_python();
+    let res = decode_run(
+        RunPaths::Glob("tests/test-data/does_not_exist_*.raw".to_string()),
+        None,
+    );
+    assert!(res.is_err());
+    assert!(res.unwrap_err().to_string().contains("No files in run"));
+}
+
+#[test]
+fn test_decode_all_events_with_metadata() {
+    pyo3::prepare_freethreaded_python();
+    let mut bytes = fake_event_simple();
+    bytes.extend([0xFA, 0xFA]); // Add padding delimiter bytes
+    bytes.extend(
+        fake_event_protocol_error_fb_in_idle()
+            .into_iter()
+            .filter(|b| *b != 0xFB),
+    );
+    bytes.extend([0xFA, 0xFA]);
+    bytes.extend(fake_event_simple());
+
+    let (packets, last_trailer_idx) = decode_all_events_with_metadata(&bytes, None).unwrap();
+    let (expect_packets, expect_last_trailer_idx) = decode_all_events(&bytes, None).unwrap();
+
+    assert_eq!(last_trailer_idx, expect_last_trailer_idx);
+    assert_eq!(packets.len(), expect_packets.len());
+    for (i, (packet, expect_packet)) in packets.iter().zip(expect_packets.iter()).enumerate() {
+        assert_eq!(packet.unit_id, expect_packet.unit_id);
+        assert_eq!(packet.hits, expect_packet.hits);
+
+        let metadata = packet.metadata.as_ref().unwrap();
+        assert_eq!(metadata.sequence, i);
+        assert_eq!(metadata.source_file, None);
+        assert_eq!(bytes[metadata.header_offset] & 0xF, packet.unit_id);
+        assert_eq!(bytes[metadata.trailer_offset], UNIT_FRAME_TRAILER);
+    }
+    assert!(expect_packets.iter().all(|p| p.metadata.is_none()));
+}
+
+#[test]
+fn test_decode_from_file_with_metadata() {
+    pyo3::prepare_freethreaded_python();
+    let path = std::path::PathBuf::from(FILE_MOSS_NOISE_ALL_REGION);
+    let bytes = std::fs::read(&path).unwrap();
+
+    let packets = decode_from_file_with_metadata(path.clone(), None).unwrap();
+    assert_eq!(packets.len(), NOISE_ALL_REGION_PACKETS);
+
+    let last_metadata = packets.last().unwrap().metadata.as_ref().unwrap();
+    assert_eq!(
+        last_metadata.trailer_offset,
+        NOISE_ALL_REGION_LAST_TRAILER_IDX
+    );
+    assert_eq!(last_metadata.sequence, NOISE_ALL_REGION_PACKETS - 1);
+
+    for packet in packets.iter() {
+        let metadata = packet.metadata.as_ref().unwrap();
+        assert_eq!(metadata.source_file.as_ref(), Some(&path));
+        assert!((0xD1..=0xDA).contains(&bytes[metadata.header_offset]));
+        assert_eq!(bytes[metadata.trailer_offset], UNIT_FRAME_TRAILER);
+    }
+}
+
+#[test]
+fn test_decode_from_stream() {
+    pyo3::prepare_freethreaded_python();
+    let path = std::path::PathBuf::from(FILE_NOISE_RANDOM_REGION);
+    let expect_packets = decode_from_file_with_metadata(path.clone(), None).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+
+    pyo3::Python::with_gil(|py| {
+        let globals = pyo3::types::PyDict::new(py);
+        globals
+            .set_item("data", pyo3::types::PyBytes::new(py, &bytes))
+            .unwrap();
+        py.run(
+            r#"
+import gzip, io
+
+class ShortReads:
+    """Only has `read` and returns at most 7 bytes at a time, like a socket"""
+    def __init__(self, data):
+        self._stream = io.BytesIO(data)
+    def read(self, size):
+        return self._stream.read(min(size, 7))
+
+streams = [
+    io.BufferedReader(io.BytesIO(data)),
+    gzip.GzipFile(fileobj=io.BytesIO(gzip.compress(data))),
+    ShortReads(data),
+]
+"#,
+            Some(globals),
+            None,
+        )
+        .unwrap();
+        let streams: Vec<&pyo3::PyAny> = globals.get_item("streams").unwrap().extract().unwrap();
+
+        for stream in streams {
+            let packets = decode_from_stream_with_metadata(stream, None).unwrap();
+            assert_eq!(packets.len(), NOISE_RANDOM_REGION_PACKETS);
+            compare_all_packets(&packets, &expect_packets);
+            for (packet, expect) in packets.iter().zip(&expect_packets) {
+                let (metadata, expect) = (
+                    packet.metadata.as_ref().unwrap(),
+                    expect.metadata.as_ref().unwrap(),
+                );
+                assert_eq!(metadata.header_offset, expect.header_offset);
+                assert_eq!(metadata.trailer_offset, expect.trailer_offset);
+                assert_eq!(metadata.source_file, None);
+            }
+        }
+
+        let not_a_stream = decode_from_stream(pyo3::types::PyBytes::new(py, &bytes), None);
+        assert!(not_a_stream
+            .unwrap_err()
+            .to_string()
+            .contains("file-like object"));
+    });
+}
+
+#[test]
+fn test_ffi_example_program() {
+    use std::process::Command;
+    // The test binary is next to the built library in `target/<profile>/deps`
+    let lib_dir = std::env::current_exe().unwrap();
+    let lib_dir = lib_dir.parent().unwrap();
+    let example = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi_example");
+    // Link libpython too, in case the library was last built as an extension module
+    let python_ldflags = Command::new("python3-config")
+        .args(["--ldflags", "--embed"])
+        .output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).into_owned())
+        .unwrap_or_default();
+    let Ok(compiled) = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-Iinclude"])
+        .arg("tests/ffi_example.c")
+        .arg("-o")
+        .arg(&example)
+        .arg(format!("-L{}", lib_dir.display()))
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lmoss_decoder")
+        .arg("-Wl,--no-as-needed")
+        .args(python_ldflags.split_whitespace())
+        .status()
+    else {
+        eprintln!("No C compiler found, skipping");
+        return;
+    };
+    assert!(compiled.success());
+
+    let expect_packets = decode_from_file(FILE_NOISE_RANDOM_REGION.into(), None).unwrap();
+    let expect_hits: usize = expect_packets.iter().map(|p| p.hits.len()).sum();
+    for chunk_size in ["7", "4096"] {
+        // Load the library from the rpath rather than the search path set by cargo
+        let output = Command::new(&example)
+            .env_remove("LD_LIBRARY_PATH")
+            .args([FILE_NOISE_RANDOM_REGION, chunk_size])
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            stdout.ends_with(&format!(
+                "packets={} hits={expect_hits} protocol_errors=0\n",
+                expect_packets.len()
+            )),
+            "{stdout}"
+        );
+    }
+
+    // An unexpected header in the first event is logged, the following events are still decoded
+    let mut corrupt = std::fs::read(FILE_NOISE_RANDOM_REGION).unwrap();
+    corrupt.insert(3, 0xD5);
+    let corrupt_path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "moss_decoder_test_ffi_example_{}.raw",
+        std::process::id()
+    ));
+    std::fs::write(&corrupt_path, corrupt).unwrap();
+    let output = Command::new(&example)
+        .env_remove("LD_LIBRARY_PATH")
+        .arg(&corrupt_path)
+        .arg("4096")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&corrupt_path).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stderr}");
+    assert!(stderr.starts_with("Protocol error: "), "{stderr}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.ends_with("protocol_errors=0\n"), "{stdout}");
+    assert!(
+        stdout.contains(&format!("packets={} ", expect_packets.len() - 1)),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_ffi_header_layout() {
+    use moss_decoder::ffi::{MossCHit, MossCPacket, MossCStats, MossStatus};
+    use std::mem::{offset_of, size_of};
+    use std::process::Command;
+    // Prints the size and field offsets of each type as the C compiler lays them out from the header
+    const LAYOUT_PROGRAM: &str = r#"
+#include <stdio.h>
+#include <stddef.h>
+#include "moss_decoder.h"
+
+int main(void) {
+    printf("moss_status %zu\n", sizeof(moss_status));
+    printf("moss_hit %zu %zu %zu %zu\n", sizeof(moss_hit), offsetof(moss_hit, region),
+           offsetof(moss_hit, row), offsetof(moss_hit, column));
+    printf("moss_packet %zu %zu %zu %zu\n", sizeof(moss_packet), offsetof(moss_packet, unit_id),
+           offsetof(moss_packet, first_hit), offsetof(moss_packet, hit_count));
+    printf("moss_stats %zu %zu %zu %zu %zu\n", sizeof(moss_stats), offsetof(moss_stats, bytes),
+           offsetof(moss_stats, packets), offsetof(moss_stats, hits), offsetof(moss_stats, protocol_errors));
+    return 0;
+}
+"#;
+    let tmp_dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let source = tmp_dir.join("ffi_layout.c");
+    let program = tmp_dir.join("ffi_layout");
+    std::fs::write(&source, LAYOUT_PROGRAM).unwrap();
+    let Ok(compiled) = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-Iinclude"])
+        .arg(&source)
+        .arg("-o")
+        .arg(&program)
+        .status()
+    else {
+        eprintln!("No C compiler found, skipping");
+        return;
+    };
+    assert!(compiled.success());
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(output.status.success());
+    let expected = format!(
+        "moss_status {}\n\
+         moss_hit {} {} {} {}\n\
+         moss_packet {} {} {} {}\n\
+         moss_stats {} {} {} {} {}\n",
+        size_of::<MossStatus>(),
+        size_of::<MossCHit>(),
+        offset_of!(MossCHit, region),
+        offset_of!(MossCHit, row),
+        offset_of!(MossCHit, column),
+        size_of::<MossCPacket>(),
+        offset_of!(MossCPacket, unit_id),
+        offset_of!(MossCPacket, first_hit),
+        offset_of!(MossCPacket, hit_count),
+        size_of::<MossCStats>(),
+        offset_of!(MossCStats, bytes),
+        offset_of!(MossCStats, packets),
+        offset_of!(MossCStats, hits),
+        offset_of!(MossCStats, protocol_errors),
+    );
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+/// The C header is generated from `src/ffi.rs` with the settings in `cbindgen.toml`, set `UPDATE_FFI_HEADER` to regenerate it.
+#[test]
+fn test_ffi_header_is_generated() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let config = cbindgen::Config::from_file(format!("{manifest_dir}/cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_crate(manifest_dir)
+        .with_config(config)
+        .generate()
+        .unwrap()
+        .write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    let header_path = format!("{manifest_dir}/include/moss_decoder.h");
+    if std::env::var_os("UPDATE_FFI_HEADER").is_some() {
+        std::fs::write(&header_path, &generated).unwrap();
+    }
+    let committed = std::fs::read_to_string(&header_path).unwrap();
+    assert!(
+        committed == generated,
+        "include/moss_decoder.h is out of date, regenerate it with UPDATE_FFI_HEADER=1\n{generated}"
+    );
+}
+
+#[test]
+fn test_decode_with_framing_config() {
+    pyo3::prepare_freethreaded_python();
+    // Events padded to 4 bytes with zeros and separated by 0xFF filler bytes
+    let event = fake_event_simple();
+    let padding = vec![0x00; (4 - event.len() % 4) % 4];
+    let mut bytes = Vec::new();
+    for _ in 0..3 {
+        bytes.extend(&event);
+        bytes.extend(&padding);
+        bytes.extend([0xFF; 2]);
+    }
+    let last_event_end = bytes.len() - 2 - 1;
+    let framing = FramingConfig::new([0xFF], 4, false).unwrap();
+
+    let (packets, last_trailer_idx) = decode_all_events(&bytes, Some(framing)).unwrap();
+    assert_eq!(packets.len(), 3);
+    assert_eq!(last_trailer_idx, last_event_end);
+    assert!(packets.iter().all(|p| p.hits.len() == 4));
+
+    let (batch, batch_last_trailer_idx) = decode_all_events_batch(&bytes, Some(framing)).unwrap();
+    assert_eq!(batch.to_packets(), packets);
+    assert_eq!(batch_last_trailer_idx, last_event_end);
+
+    let (skipped, skip_last_trailer_idx) =
+        decode_n_events(&bytes, 2, Some(1), None, Some(framing)).unwrap();
+    assert_eq!(skipped.len(), 2);
+    assert_eq!(skip_last_trailer_idx, last_event_end);
+
+    let (debug_packets, _, invalid_words) = debug_decode_all_events(&bytes, Some(framing)).unwrap();
+    assert_eq!(debug_packets.len(), 3);
+    assert!(invalid_words.is_empty(), "{invalid_words:?}");
+
+    // The default framing only allows 0xFA between events
+    assert!(decode_all_events(&bytes, None)
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid delimiter"));
+
+    // A leading trailer is rejected if the framing does not allow it
+    let mut leading_trailer = vec![UNIT_FRAME_TRAILER];
+    leading_trailer.extend(&bytes);
+    assert!(decode_all_events(&leading_trailer, None).is_err());
+    assert!(decode_all_events(&leading_trailer, Some(framing)).is_err());
+    assert!(decode_all_events(
+        &leading_trailer,
+        Some(FramingConfig::new([0xFF], 4, true).unwrap())
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_decode_wrapped_events() {
+    pyo3::prepare_freethreaded_python();
+    let wrapper = ReadoutWrapper::default();
+    let mut event_with_filler = fake_event_simple();
+    event_with_filler.extend([0xFA, 0xFA]);
+    let mut bytes = wrapper.wrap(1, 1000, &fake_event_simple()).unwrap();
+    bytes.extend(wrapper.wrap(2, 2000, &[]).unwrap()); // Empty trigger
+    bytes.extend(wrapper.wrap(3, 3000, &event_with_filler).unwrap());
+
+    let (packets, last_idx) = decode_wrapped_events(&bytes, None, None).unwrap();
+    assert_eq!(last_idx, bytes.len() - 1);
+    assert_eq!(packets.len(), 2);
+    for (packet, (trigger_id, timestamp)) in packets.iter().zip([(1, 1000), (3, 3000)]) {
+        assert_eq!(packet.hits.len(), 4);
+        let metadata = packet.metadata.as_ref().unwrap();
+        assert_eq!(metadata.trigger_id, Some(trigger_id));
+        assert_eq!(metadata.timestamp, Some(timestamp));
+        assert_eq!(bytes[metadata.header_offset] & 0xF, packet.unit_id);
+        assert_eq!(bytes[metadata.trailer_offset], UNIT_FRAME_TRAILER);
+    }
+
+    // A partial record at the end
+    let err = decode_wrapped_events(&bytes[..bytes.len() - 1], None, None).unwrap_err();
+    assert!(err.to_string().contains("end of the record"), "{err}");
+
+    // A corrupted record
+    let mut corrupted = bytes.clone();
+    corrupted[ReadoutWrapper::HEADER_LEN + 3] ^= 0x01;
+    let err = decode_wrapped_events(&corrupted, None, None).unwrap_err();
+    assert!(err.to_string().contains("Checksum mismatch"), "{err}");
+
+    let path = std::env::temp_dir().join(format!(
+        "moss_decoder_test_decode_wrapped_events_{}.raw",
+        std::process::id()
+    ));
+    std::fs::write(&path, &bytes).unwrap();
+    let file_packets = decode_wrapped_from_file(path.clone(), None, None).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(file_packets.len(), packets.len());
+    for (file_packet, packet) in file_packets.iter().zip(packets.iter()) {
+        let file_metadata = file_packet.metadata.as_ref().unwrap();
+        assert_eq!(file_metadata.source_file.as_ref(), Some(&path));
+        assert_eq!(
+            file_metadata.header_offset,
+            packet.metadata.as_ref().unwrap().header_offset
+        );
+        assert_eq!(file_packet.hits, packet.hits);
+    }
+
+    // A partial record at the end of the file is reported
+    std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    let err = decode_wrapped_from_file(path.clone(), None, None).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(err.to_string().contains("end of file"), "{err}");
+
+    // Read errors are raised, reading a directory fails after opening it
+    let err = decode_wrapped_from_file("tests/test-data".into(), None, None).unwrap_err();
+    assert!(err.to_string().contains("directory"), "{err}");
+}
+
 #[test]
 fn test_debug_decode_noise_all_region() {
     pyo3::prepare_freethreaded_python();
@@ -684,7 +1187,7 @@ fn test_debug_decode_noise_all_region() {
 
     let bytes = std::fs::read(std::path::PathBuf::from(FILE_MOSS_NOISE_ALL_REGION)).unwrap();
 
-    let res = moss_decoder::debug_decode_all_events(&bytes);
+    let res = moss_decoder::debug_decode_all_events(&bytes, None);
 
     println!("Decoded in: {t:?}\n", t = time.elapsed());
 
@@ -768,7 +1271,7 @@ fn test_compare_result_4_events_partial_end() {
 
     // Do an initial comparison with the simple naive decoder and the expected values
     let (debug_packets, debug_last_trailer_idx, invalid_words) =
-        moss_decoder::debug_decode_all_events(&bytes).unwrap();
+        moss_decoder::debug_decode_all_events(&bytes, None).unwrap();
     assert_eq!(debug_last_trailer_idx, FOUR_EVENTS_PARTIAL_END_LAST_TRAILER_IDX, "Unexpected last trailer index, got trailer index: {debug_last_trailer_idx}, expected: {FOUR_EVENTS_PARTIAL_END_LAST_TRAILER_IDX}. From trailer index to end of bytes: {remainder:#X?}", remainder = bytes.get(debug_last_trailer_idx..).unwrap());
     assert_eq!(
         debug_packets.len(),
@@ -785,17 +1288,17 @@ fn test_compare_result_4_events_partial_end() {
     // Then use that result to compare with the other decoding methods
 
     // Check moss_decoder::decode_all_events
-    match moss_decoder::decode_all_events(&bytes) {
+    match moss_decoder::decode_all_events(&bytes, None) {
         Ok((decode_all_events_packets, decode_all_events_last_trailer_idx)) => panic!("This should have failed, got {decode_all_events_packets:?} packets, last trailer index: {decode_all_events_last_trailer_idx}"),
         Err(e) => {println!("Got error: {e}"); assert!(e.to_string().contains("Failed decoding packet #5"))},
     }
 
     // Check moss_decoder::decode_from_file
-    let packets = moss_decoder::decode_from_file(FILE_4_EVENTS_PARTIAL_END.into()).unwrap();
+    let packets = moss_decoder::decode_from_file(FILE_4_EVENTS_PARTIAL_END.into(), None).unwrap();
     compare_all_packets(&packets, &debug_packets);
 
     // Check moss_decoder::skip_n_take_all
-    let (packets, remainder) = moss_decoder::skip_n_take_all(&bytes, 0).unwrap();
+    let (packets, remainder) = moss_decoder::skip_n_take_all(&bytes, 0, None).unwrap();
     let packets = packets.unwrap();
     assert!(remainder.is_some());
     assert!(
@@ -805,7 +1308,8 @@ fn test_compare_result_4_events_partial_end() {
 
     // Check moss_decoder::decode_n_events
     let (packets, last_trailer_idx) =
-        moss_decoder::decode_n_events(&bytes, FOUR_EVENTS_PARTIAL_END_PACKETS, None, None).unwrap();
+        moss_decoder::decode_n_events(&bytes, FOUR_EVENTS_PARTIAL_END_PACKETS, None, None, None)
+            .unwrap();
     assert_eq!(last_trailer_idx, debug_last_trailer_idx);
     compare_all_packets(&packets, &debug_packets);
 }
@@ -817,7 +1321,7 @@ fn test_compare_result_3_events_partial_start() {
 
     // Do an initial comparison with the simple naive decoder and the expected values
     let (debug_packets, debug_last_trailer_idx, invalid_words) =
-        moss_decoder::debug_decode_all_events(&bytes).unwrap();
+        moss_decoder::debug_decode_all_events(&bytes, None).unwrap();
     assert_eq!(debug_last_trailer_idx, THREE_EVENTS_PARTIAL_START_LAST_TRAILER_IDX, "Unexpected last trailer index, got trailer index: {debug_last_trailer_idx}, expected: {THREE_EVENTS_PARTIAL_START_LAST_TRAILER_IDX}. From trailer index to end of bytes: {remainder:#X?}", remainder = bytes.get(debug_last_trailer_idx..).unwrap());
     assert_eq!(
         debug_packets.len(),
@@ -834,13 +1338,13 @@ fn test_compare_result_3_events_partial_start() {
     // Then use that result to compare with the other decoding methods
 
     // Check moss_decoder::decode_all_events
-    match moss_decoder::decode_all_events(&bytes) {
+    match moss_decoder::decode_all_events(&bytes, None) {
         Ok((decode_all_events_packets, decode_all_events_last_trailer_idx)) => panic!("This should have failed, got {decode_all_events_packets:?} packets, last trailer index: {decode_all_events_last_trailer_idx}"),
         Err(e) => {println!("Got error: {e}"); assert!(e.to_string().contains("Failed decoding packet #1"))},
     }
 
     // Check moss_decoder::decode_from_file
-    match moss_decoder::decode_from_file(FILE_3_EVENTS_PARTIAL_START.into()) {
+    match moss_decoder::decode_from_file(FILE_3_EVENTS_PARTIAL_START.into(), None) {
         Ok(packets) => panic!("This should have failed, got {packets:?} packets"),
         Err(e) => {
             println!("Got error: {e}");
@@ -849,7 +1353,7 @@ fn test_compare_result_3_events_partial_start() {
     }
 
     // Check moss_decoder::skip_n_take_all
-    match moss_decoder::skip_n_take_all(&bytes, 0) {
+    match moss_decoder::skip_n_take_all(&bytes, 0, None) {
         Ok(packets) => panic!("This should have failed, got {packets:?} packets"),
         Err(e) => {
             println!("Got error: {e}");
@@ -858,7 +1362,13 @@ fn test_compare_result_3_events_partial_start() {
     }
 
     // Check moss_decoder::decode_n_events
-    match moss_decoder::decode_n_events(&bytes, THREE_EVENTS_PARTIAL_START_PACKETS, None, None) {
+    match moss_decoder::decode_n_events(
+        &bytes,
+        THREE_EVENTS_PARTIAL_START_PACKETS,
+        None,
+        None,
+        None,
+    ) {
         Ok(packets) => panic!("This should have failed, got {packets:?} packets"),
         Err(e) => {
             println!("Got error: {e}");
@@ -866,3 +1376,116 @@ fn test_compare_result_3_events_partial_start() {
         }
     }
 }
+
+#[test]
+fn test_diff_files() {
+    let same = moss_decoder::diff_files(
+        "tests/test-data/pattern_all_regions.raw".into(),
+        "tests/test-data/pattern_all_regions.raw".into(),
+        None,
+    )
+    .unwrap();
+    assert!(same.is_identical());
+    assert_eq!(same.identical_events, same.events_a);
+
+    let diff = moss_decoder::diff_files(
+        "tests/test-data/pattern_all_regions.raw".into(),
+        "tests/test-data/noise_all_regions.raw".into(),
+        None,
+    )
+    .unwrap();
+    println!("{diff}");
+    assert!(!diff.is_identical());
+    assert_eq!(
+        diff.identical_events + diff.differences.len() - diff.missing_in_a,
+        diff.events_a
+    );
+}
+
+#[test]
+fn test_filter_raw_file() {
+    let all_packets = decode_from_file(FILE_NOISE_RANDOM_REGION.into(), None).unwrap();
+    let output = std::env::temp_dir().join("moss_decoder_test_filter_raw_file.raw");
+
+    // Split the first 100 events into files of 30 events
+    let stats = filter_raw_file(
+        FILE_NOISE_RANDOM_REGION.into(),
+        output.clone(),
+        Some(raw_filter::EventFilter {
+            event_range: Some(0..100),
+            ..Default::default()
+        }),
+        Some(30),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(stats.events_read, 100);
+    assert_eq!(stats.events_written, 100);
+    assert_eq!(stats.output_files.len(), 4);
+    let mut split_packets = Vec::new();
+    for file in &stats.output_files {
+        split_packets.extend(decode_from_file(file.clone(), None).unwrap());
+        std::fs::remove_file(file).unwrap();
+    }
+    assert!(diff::diff_packets(&all_packets[..100], &split_packets).is_identical());
+
+    // Select the events of one unit with hits
+    let stats = filter_raw_file(
+        FILE_NOISE_RANDOM_REGION.into(),
+        output.clone(),
+        Some(raw_filter::EventFilter {
+            unit_ids: Some(vec![7]),
+            min_hits: Some(1),
+            ..Default::default()
+        }),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let filtered_packets = decode_from_file(output.clone(), None).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    let expected: Vec<MossPacket> = all_packets
+        .into_iter()
+        .filter(|p| p.unit_id == 7 && !p.hits.is_empty())
+        .collect();
+    assert_eq!(stats.events_read, NOISE_RANDOM_REGION_PACKETS);
+    assert_eq!(stats.events_written, expected.len());
+    assert!(diff::diff_packets(&expected, &filtered_packets).is_identical());
+}
+
+#[test]
+fn test_replay_over_tcp() {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let target = replay::ReplayTarget::TcpConnect(listener.local_addr().unwrap().to_string());
+    let replayer = std::thread::spawn(move || {
+        let mut output = target.open().unwrap();
+        replay::replay(
+            FILE_NOISE_RANDOM_REGION,
+            &mut output,
+            &replay::ReplayOptions {
+                rate: replay::ReplayRate::BytesPerSec(1e6),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    });
+
+    let mut received = Vec::new();
+    _ = listener
+        .accept()
+        .unwrap()
+        .0
+        .read_to_end(&mut received)
+        .unwrap();
+    let stats = replayer.join().unwrap();
+
+    assert_eq!(stats.events, NOISE_RANDOM_REGION_PACKETS);
+    assert_eq!(received, std::fs::read(FILE_NOISE_RANDOM_REGION).unwrap());
+    let (packets, last_trailer_idx) = decode_all_events(&received, None).unwrap();
+    assert_eq!(packets.len(), NOISE_RANDOM_REGION_PACKETS);
+    assert_eq!(last_trailer_idx, NOISE_RANDOM_REGION_LAST_TRAILER_IDX);
+}
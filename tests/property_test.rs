@@ -0,0 +1,111 @@
+use moss_decoder::moss_protocol::test_util::encode_event;
+use moss_decoder::*;
+
+use pretty_assertions::assert_eq;
+use proptest::prelude::*;
+
+/// Bytes biased towards MOSS protocol words so the decoders get past the first byte.
+fn moss_byte() -> impl Strategy<Value = u8> {
+    prop_oneof![
+        3 => 0xD1..=0xDAu8,                          // Unit Frame Header
+        3 => Just(0xE0u8),                           // Unit Frame Trailer
+        3 => 0xC0..=0xC3u8,                          // Region Header
+        4 => 0x00..=0x28u8,                          // Data 0
+        4 => 0x40..=0x7Du8,                          // Data 1
+        4 => 0x80..=0xBFu8,                          // Data 2
+        2 => Just(0xFFu8),                           // Idle
+        2 => Just(0xFAu8),                           // Delimiter
+        2 => any::<u8>(),
+    ]
+}
+
+fn moss_bytes() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(moss_byte(), 0..200)
+}
+
+fn framing() -> impl Strategy<Value = FramingConfig> {
+    (1..=8usize, any::<bool>()).prop_map(|(alignment, allow_leading_trailer)| {
+        FramingConfig::new([0xFA], alignment, allow_leading_trailer).unwrap()
+    })
+}
+
+fn moss_hit() -> impl Strategy<Value = MossHit> {
+    (0..4u8, 0..320u16, 0..320u16).prop_map(|(region, row, column)| MossHit {
+        region,
+        row,
+        column,
+    })
+}
+
+fn moss_packet() -> impl Strategy<Value = MossPacket> {
+    (1..=10u8, prop::collection::vec(moss_hit(), 0..20)).prop_map(|(unit_id, mut hits)| {
+        // The decoder returns the hits in the order of the regions
+        hits.sort_by_key(|hit| hit.region);
+        MossPacket {
+            unit_id,
+            hits,
+            metadata: None,
+        }
+    })
+}
+
+proptest! {
+    #[test]
+    fn decode_never_panics(bytes in moss_bytes(), framing in framing()) {
+        _ = decode_event(&bytes, Some(framing));
+        _ = decode_all_events(&bytes, Some(framing));
+        _ = decode_all_events_with_metadata(&bytes, Some(framing));
+        _ = decode_all_events_batch(&bytes, Some(framing));
+        _ = decode_n_events(&bytes, 2, None, None, Some(framing));
+        _ = decode_n_events(&bytes, 1, Some(1), None, Some(framing));
+        _ = skip_n_take_all(&bytes, 1, Some(framing));
+        _ = debug_decode_all_events(&bytes, Some(framing));
+        _ = hexdump(&bytes, None, None, None, None, Some(framing));
+        _ = raw_filter::split_raw_events(&bytes, true, &framing);
+        _ = StreamDemux::new(framing).feed(&bytes);
+    }
+
+    #[test]
+    fn internal_decode_never_panics(bytes in moss_bytes(), split in any::<prop::sample::Index>(), framing in framing()) {
+        let (prepend, rest) = bytes.split_at(split.index(bytes.len() + 1));
+        _ = fuzzing::extract_packet_from_buf(&bytes, None, &framing);
+        _ = fuzzing::extract_packet_from_buf(rest, Some(prepend.to_vec()), &framing);
+        _ = fuzzing::get_all_packets_from_buf(&bytes, &framing);
+        _ = fuzzing::debug_decode_event(&bytes, &framing);
+    }
+
+    #[test]
+    fn encode_decode_round_trip(packet in moss_packet()) {
+        let bytes = encode_event(&packet);
+
+        let (decoded, trailer_idx) = decode_event(&bytes, None).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(trailer_idx, bytes.len() - 1);
+
+        let (decoded, trailer_idx, invalid_words) = fuzzing::debug_decode_event(&bytes, &FramingConfig::default()).unwrap();
+        assert_eq!(decoded, packet);
+        assert_eq!(trailer_idx, bytes.len() - 1);
+        assert!(invalid_words.is_empty());
+    }
+
+    #[test]
+    fn encode_decode_round_trip_multiple_events(
+        packets in prop::collection::vec(moss_packet(), 1..20),
+        filler_cnt in 0..4usize,
+    ) {
+        let bytes: Vec<u8> = packets
+            .iter()
+            .flat_map(|packet| {
+                let mut event = encode_event(packet);
+                event.extend(std::iter::repeat_n(0xFA, filler_cnt));
+                event
+            })
+            .collect();
+
+        let (decoded, _) = fuzzing::get_all_packets_from_buf(&bytes, &FramingConfig::default()).unwrap();
+        assert_eq!(&decoded, &packets);
+
+        let (batch, _) = decode_all_events_batch(&bytes, None).unwrap();
+        assert_eq!(batch.to_packets(), packets);
+    }
+}
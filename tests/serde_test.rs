@@ -0,0 +1,98 @@
+//! Round trips of the types with `Serialize` and `Deserialize` implementations, run with `--features serde`.
+#![cfg(feature = "serde")]
+use moss_decoder::decode_options::ErrorPolicy;
+use moss_decoder::moss_protocol::test_util::fake_event_simple;
+use moss_decoder::*;
+
+use pretty_assertions::assert_eq;
+
+const FILE_PATTERN_ALL_REGIONS: &str = "tests/test-data/pattern_all_regions.raw";
+
+fn round_trip<T>(value: &T) -> T
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let json = serde_json::to_string(value).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+fn pattern_packets() -> Vec<MossPacket> {
+    let bytes = std::fs::read(FILE_PATTERN_ALL_REGIONS).unwrap();
+    let (packets, _) = decode_all_events_with_metadata(&bytes, None).unwrap();
+    packets
+}
+
+#[test]
+fn test_packets_and_events_round_trip() {
+    let packets = pattern_packets();
+    assert!(packets[0].metadata.is_some());
+    let restored = round_trip(&packets);
+    assert_eq!(restored, packets);
+    // Packets compare equal regardless of their metadata
+    assert!(restored
+        .iter()
+        .zip(&packets)
+        .all(|(restored, packet)| restored.metadata == packet.metadata));
+    assert_eq!(round_trip(&packets[0].hits[0]), packets[0].hits[0]);
+
+    let events = build_events(packets.clone(), None);
+    assert_eq!(round_trip(&events), events);
+
+    let batch = MossEventBatch::from(packets.as_slice());
+    assert_eq!(round_trip(&batch), batch);
+}
+
+#[test]
+fn test_inconsistent_batch_is_rejected() {
+    let batch = MossEventBatch::from(pattern_packets().as_slice());
+    let mut json = serde_json::to_value(&batch).unwrap();
+    json["event_offsets"].as_array_mut().unwrap().pop();
+    assert!(serde_json::from_value::<MossEventBatch>(json).is_err());
+}
+
+#[test]
+fn test_reports_and_statistics_round_trip() {
+    let mut bytes = fake_event_simple();
+    bytes.insert(3, 0xD5); // Unexpected header in the first event
+    bytes.extend(fake_event_simple());
+    let options = DecodeOptions {
+        errors: ErrorPolicy::Skip,
+        ..Default::default()
+    };
+    let mut packets = Vec::new();
+    let result = decode_options::decode_bytes_into(
+        &bytes,
+        &options,
+        &FramingConfig::default(),
+        &mut packets,
+    )
+    .unwrap();
+    assert!(!result.errors.is_empty());
+    assert_eq!(round_trip(&result), result);
+
+    let a = pattern_packets();
+    let b = a[1..].to_vec();
+    let diff = diff::diff_packets(&a, &b);
+    assert!(!diff.differences.is_empty());
+    assert_eq!(round_trip(&diff), diff);
+
+    let mut decoder = MossDecoder::default();
+    _ = decoder.feed(&bytes).count();
+    assert_eq!(&round_trip(decoder.stats()), decoder.stats());
+}
+
+#[test]
+fn test_hit_map_round_trip() {
+    let mut hit_map = HitMap::default();
+    pattern_packets()
+        .iter()
+        .for_each(|packet| hit_map.add(packet));
+    let json = serde_json::to_string(&hit_map).unwrap();
+    // Only the pixels with hits are serialized
+    assert!(json.len() < 100_000);
+    assert_eq!(serde_json::from_str::<HitMap>(&json).unwrap(), hit_map);
+
+    let outside_matrix =
+        r#"{"events":1,"hits":1,"pixels":[[1,{"region":4,"row":0,"column":0},1]]}"#;
+    assert!(serde_json::from_str::<HitMap>(outside_matrix).is_err());
+}